@@ -0,0 +1,44 @@
+#ifndef NORD_PROXY_H
+#define NORD_PROXY_H
+
+/* Generated by cbindgen from src/ffi.rs, do not edit. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+// Lists the endpoints of `kind`, `"socks5"` or `"https"`, as a JSON array.
+//
+// # Safety
+//
+// `kind` must be a valid NUL-terminated string.
+char *nord_proxy_list(const char *kind);
+
+// Connects to `hostname:port`, returning `{"latency_ms": n}`, with `null` if it is
+// unreachable within `timeout_ms`.
+//
+// # Safety
+//
+// `hostname` must be a valid NUL-terminated string.
+char *nord_proxy_test(const char *hostname, uint16_t port, uint64_t timeout_ms);
+
+// Exports the endpoints of `kind` with the service credentials `username` and `password` in
+// `format`: `"proxifier"`, `"foxyproxy"` or a template for [`export::render`].
+//
+// # Safety
+//
+// All arguments must be valid NUL-terminated strings.
+char *nord_proxy_export(const char *kind,
+                        const char *format,
+                        const char *username,
+                        const char *password);
+
+// Frees a string returned by the functions above. NULL is ignored.
+//
+// # Safety
+//
+// `s` must come from this library and must not be used afterwards.
+void nord_proxy_free(char *s);
+
+#endif  /* NORD_PROXY_H */
@@ -0,0 +1,117 @@
+//! Subscription status of a NordVPN account, looked up with an access token.
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Deserializer};
+
+use crate::api;
+
+const URL: &str = "https://api.nordvpn.com/v1/users/services";
+
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Service {
+    #[serde(deserialize_with = "datetime")]
+    pub expires_at: SystemTime,
+    #[serde(rename = "service")]
+    pub kind: ServiceKind,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ServiceKind {
+    pub identifier: String,
+    pub name: String,
+}
+
+impl Service {
+    pub fn is_active(&self) -> bool {
+        self.expires_at > SystemTime::now()
+    }
+
+    /// Time until the service expires, `None` if it already has.
+    pub fn expires_in(&self) -> Option<Duration> {
+        self.expires_at.duration_since(SystemTime::now()).ok()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Services(pub Vec<Service>);
+
+impl Services {
+    /// The service with the latest expiry for `identifier`, e.g. `vpn`.
+    pub fn get(&self, identifier: &str) -> Option<&Service> {
+        self.0
+            .iter()
+            .filter(|v| v.kind.identifier == identifier)
+            .max_by_key(|v| v.expires_at)
+    }
+
+    /// The service granting proxy access, which comes with the VPN subscription.
+    pub fn proxy(&self) -> Option<&Service> {
+        self.get("proxy").or_else(|| self.get("vpn"))
+    }
+
+    pub fn proxy_active(&self) -> bool {
+        self.proxy().is_some_and(Service::is_active)
+    }
+}
+
+/// Services of the account owning `token`.
+pub async fn services(token: &str) -> reqwest::Result<Services> {
+    let response = api::get(URL)
+        .basic_auth("token", Some(token))
+        .send()
+        .await?
+        .error_for_status()?;
+    Ok(Services(response.json().await?))
+}
+
+/// Parses `YYYY-MM-DD HH:MM:SS` (UTC) as returned by the API.
+fn datetime<'de, D: Deserializer<'de>>(deserializer: D) -> Result<SystemTime, D::Error> {
+    let s = String::deserialize(deserializer)?;
+    parse_datetime(&s).ok_or_else(|| serde::de::Error::custom(format!("invalid datetime {s:?}")))
+}
+
+fn parse_datetime(s: &str) -> Option<SystemTime> {
+    let (date, time) = s.split_once([' ', 'T'])?;
+    let mut date = date.splitn(3, '-').map(|v| v.parse::<i64>().ok());
+    let (y, m, d) = (date.next()??, date.next()??, date.next()??);
+    let mut time = time
+        .trim_end_matches('Z')
+        .splitn(3, ':')
+        .map(|v| v.parse::<u64>().ok());
+    let (hh, mm, ss) = (time.next()??, time.next()??, time.next()??);
+    // Days since the epoch of a proleptic Gregorian date (Howard Hinnant's days_from_civil).
+    let y = if m <= 2 { y - 1 } else { y };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let doy = (153 * (m + if m > 2 { -3 } else { 9 }) + 2) / 5 + d - 1;
+    let days = era * 146097 + yoe * 365 + yoe / 4 - yoe / 100 + doy - 719468;
+    Some(
+        UNIX_EPOCH
+            + Duration::from_secs(u64::try_from(days).ok()? * 86400 + hh * 3600 + mm * 60 + ss),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use super::{Service, Services};
+
+    #[test]
+    fn parse() {
+        let json = r#"[
+            {"id": 1, "expires_at": "2020-02-29 12:30:00",
+             "service": {"id": 1, "name": "VPN", "identifier": "vpn"}},
+            {"id": 2, "expires_at": "2999-01-01 00:00:00",
+             "service": {"id": 1, "name": "VPN", "identifier": "vpn"}}
+        ]"#;
+        let services = Services(serde_json::from_str::<Vec<Service>>(json).unwrap());
+        assert_eq!(
+            services.0[0].expires_at,
+            UNIX_EPOCH + Duration::from_secs(1_582_979_400)
+        );
+        assert!(services.proxy_active());
+        assert!(services.proxy().unwrap().expires_in().is_some());
+        assert!(services.get("nordpass").is_none());
+    }
+}
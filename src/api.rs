@@ -0,0 +1,62 @@
+//! The HTTP client for requests to the NordVPN API.
+use std::sync::RwLock;
+
+static CLIENT: RwLock<Option<reqwest::Client>> = RwLock::new(None);
+
+const ORIGIN: &str = "https://api.nordvpn.com";
+
+tokio::task_local! {
+    static BASE: String;
+}
+
+/// Sends the requests to the NordVPN API, e.g. for the server lists, through `proxy`, for
+/// networks that only reach the internet through a corporate proxy.
+///
+/// Without one, the `HTTP_PROXY`, `HTTPS_PROXY` and `NO_PROXY` environment variables apply.
+pub fn set_api_proxy(proxy: reqwest::Proxy) -> reqwest::Result<()> {
+    let client = reqwest::Client::builder().proxy(proxy).build()?;
+    *CLIENT.write().unwrap() = Some(client);
+    Ok(())
+}
+
+/// Runs `f` with its requests to the NordVPN API going to `base`, e.g. `http://127.0.0.1:8080`,
+/// instead, for a mirror or a mock of the API.
+///
+/// The base is scoped to the task, including the tasks this crate spawns for `f`; tasks that
+/// `f` spawns itself talk to the real API.
+pub async fn with_api_base<F: Future>(base: impl Into<String>, f: F) -> F::Output {
+    BASE.scope(base.into(), f).await
+}
+
+/// `future` with the [`with_api_base`] base of the current task, to be spawned as a task of
+/// its own.
+pub(crate) fn keep_base<F: Future>(future: F) -> impl Future<Output = F::Output> {
+    let base = BASE.try_with(Clone::clone).ok();
+    async move {
+        match base {
+            Some(base) => BASE.scope(base, future).await,
+            None => future.await,
+        }
+    }
+}
+
+/// A GET request to `url` on the NordVPN API, sent to the [`with_api_base`] base if any.
+pub(crate) fn get(url: &str) -> reqwest::RequestBuilder {
+    let rebased = BASE
+        .try_with(|base| url.strip_prefix(ORIGIN).map(|path| format!("{base}{path}")))
+        .ok()
+        .flatten();
+    client().get(rebased.as_deref().unwrap_or(url))
+}
+
+/// Shared between all API requests, so they reuse connections.
+pub(crate) fn client() -> reqwest::Client {
+    if let Some(v) = &*CLIENT.read().unwrap() {
+        return v.clone();
+    }
+    CLIENT
+        .write()
+        .unwrap()
+        .get_or_insert_with(reqwest::Client::new)
+        .clone()
+}
@@ -0,0 +1,498 @@
+#[cfg(any(feature = "socks5", feature = "https-proxy"))]
+use std::time::Duration;
+use std::{collections::HashMap, io, marker::PhantomData, time::SystemTime};
+
+use serde::Deserialize;
+use tokio_util::{sync::CancellationToken, task::AbortOnDropHandle};
+
+#[cfg(feature = "https-proxy")]
+use crate::Proxy;
+#[cfg(feature = "socks5")]
+use crate::Socks5;
+use crate::{
+    City, Country, DedicatedIp, Obfuscated, ServerGroups, Technology, api,
+    geo::{COUNTRIES_URL, Coordinates},
+    get_info, legacy,
+    structure::Root,
+    view,
+};
+
+/// The fields of [`Root`], so the API leaves out the IPs, specifications, coordinates, ... that
+/// make up most of the payload.
+const FIELDS: &[&str] = &[
+    "hostname",
+    "load",
+    "status",
+    "services.identifier",
+    "groups.identifier",
+    "locations.country.code",
+    "locations.country.city.name",
+    "locations.country.city.hub_score",
+    "technologies.identifier",
+    "technologies.pivot.status",
+    "technologies.metadata",
+];
+
+/// How the builders treat countries, cities and technologies the crate doesn't know.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ParseMode {
+    /// Fails the fetch, e.g. to catch changes of the API early in CI.
+    Strict,
+    /// Keeps them as `Other`, which the filters skip.
+    #[default]
+    Lenient,
+}
+
+/// Options for fetching a server list, created by `Proxy::builder`, `Socks5::builder` and
+/// [`DedicatedIp::builder`].
+pub struct Builder<T> {
+    legacy_fallback: bool,
+    recommended: Option<usize>,
+    coordinates: Option<Coordinates>,
+    detect_coordinates: bool,
+    #[cfg(feature = "https-proxy")]
+    threat_protection: bool,
+    #[cfg(any(feature = "socks5", feature = "https-proxy"))]
+    probe_ports: bool,
+    include_maintenance: bool,
+    countries: Vec<Country>,
+    hostnames: Vec<String>,
+    parse_mode: ParseMode,
+    obfuscated: Obfuscated,
+    shutdown: Option<CancellationToken>,
+    _marker: PhantomData<fn() -> T>,
+}
+
+impl<T> Builder<T> {
+    pub(crate) fn new() -> Self {
+        Builder {
+            legacy_fallback: false,
+            recommended: None,
+            coordinates: None,
+            detect_coordinates: false,
+            #[cfg(feature = "https-proxy")]
+            threat_protection: false,
+            #[cfg(any(feature = "socks5", feature = "https-proxy"))]
+            probe_ports: false,
+            include_maintenance: false,
+            countries: Vec::new(),
+            hostnames: Vec::new(),
+            parse_mode: ParseMode::default(),
+            obfuscated: Obfuscated::default(),
+            shutdown: None,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Falls back to the legacy `https://api.nordvpn.com/server` endpoint when `v1/servers`
+    /// fails or returns no servers.
+    pub fn legacy_fallback(mut self, enabled: bool) -> Self {
+        self.legacy_fallback = enabled;
+        self
+    }
+
+    /// Asks NordVPN for its `limit` recommended servers instead of listing all of them.
+    ///
+    /// Without [`Builder::coordinates`] the recommendations are made for the location of the
+    /// caller's IP address as seen by the API.
+    pub fn recommended(mut self, limit: usize) -> Self {
+        self.recommended = Some(limit);
+        self
+    }
+
+    /// Location the recommendations are optimized for.
+    pub fn coordinates(mut self, coordinates: Coordinates) -> Self {
+        self.coordinates = Some(coordinates);
+        self
+    }
+
+    /// Detects the coordinates with [`Coordinates::detect`] before fetching recommendations.
+    pub fn detect_coordinates(mut self) -> Self {
+        self.detect_coordinates = true;
+        self
+    }
+
+    /// Also lists servers under maintenance, e.g. to monitor the whole fleet or plan capacity.
+    /// They show up in [`ProxyTrait::status_counts`](crate::ProxyTrait::status_counts), but the
+    /// selection methods and [`Pool`](crate::pool::Pool) skip them.
+    pub fn include_maintenance(mut self, enabled: bool) -> Self {
+        self.include_maintenance = enabled;
+        self
+    }
+
+    /// Only lists servers in `countries`, fetching every country's servers concurrently instead
+    /// of downloading the whole list.
+    ///
+    /// With [`Builder::recommended`], `limit` servers are recommended per country.
+    pub fn countries(mut self, countries: impl IntoIterator<Item = Country>) -> Self {
+        self.countries.extend(countries);
+        self
+    }
+
+    /// Only lists servers whose hostname matches one of the glob `patterns`, e.g.
+    /// `us*.nordvpn.com` or `de105?` (matching the part before the first dot).
+    pub fn hostnames<S: Into<String>>(mut self, patterns: impl IntoIterator<Item = S>) -> Self {
+        self.hostnames.extend(patterns.into_iter().map(Into::into));
+        self
+    }
+
+    /// Only lists obfuscated servers, or leaves them out, by their group.
+    pub fn obfuscated(mut self, mode: Obfuscated) -> Self {
+        self.obfuscated = mode;
+        self
+    }
+
+    /// Whether unknown countries, cities or technologies fail the fetch, see [`ParseMode`].
+    ///
+    /// A strict fetch that fails goes on to the fallbacks like any other failed fetch.
+    pub fn parse_mode(mut self, mode: ParseMode) -> Self {
+        self.parse_mode = mode;
+        self
+    }
+
+    /// Stops fetching once `token` is cancelled, failing `try_build` with
+    /// [`io::ErrorKind::Interrupted`].
+    ///
+    /// Dropping the `build` future, e.g. in `tokio::select!`, cancels the fetch as well.
+    pub fn shutdown(mut self, token: CancellationToken) -> Self {
+        self.shutdown = Some(token);
+        self
+    }
+
+    fn url(&self, filter: &str, coordinates: Option<Coordinates>) -> String {
+        let mut url = self.endpoint(filter, coordinates);
+        for v in FIELDS {
+            url += &format!("&fields[servers.{v}]");
+        }
+        url
+    }
+
+    fn endpoint(&self, filter: &str, coordinates: Option<Coordinates>) -> String {
+        match self.recommended {
+            Some(limit) => {
+                let mut url = format!(
+                    "https://api.nordvpn.com/v1/servers/recommendations?{filter}&limit={limit}"
+                );
+                if let Some(v) = coordinates {
+                    url += &format!(
+                        "&coordinates[latitude]={}&coordinates[longitude]={}",
+                        v.latitude, v.longitude
+                    );
+                }
+                url
+            }
+            None => format!("https://api.nordvpn.com/v1/servers?{filter}&limit=0"),
+        }
+    }
+
+    /// The servers and when they were fetched, which is earlier than now for the embedded list.
+    async fn fetch(&self, filter: &str) -> io::Result<(Vec<Root>, SystemTime)> {
+        let (mut servers, fetched_at) = match &self.shutdown {
+            Some(token) => token
+                .run_until_cancelled(self.fetch_servers(filter))
+                .await
+                .unwrap_or_else(|| {
+                    Err(io::Error::new(
+                        io::ErrorKind::Interrupted,
+                        "fetching servers was cancelled",
+                    ))
+                })?,
+            None => self.fetch_servers(filter).await?,
+        };
+        if !self.hostnames.is_empty() {
+            servers.retain(|v| {
+                self.hostnames
+                    .iter()
+                    .any(|p| view::hostname_matches(p, &v.hostname))
+            });
+        }
+        servers.retain(|v| {
+            self.obfuscated
+                .allows(ServerGroups::new(&v.groups).obfuscated)
+        });
+        Ok((servers, fetched_at))
+    }
+
+    async fn fetch_servers(&self, filter: &str) -> io::Result<(Vec<Root>, SystemTime)> {
+        let mut coordinates = self.coordinates;
+        if coordinates.is_none() && self.detect_coordinates && self.recommended.is_some() {
+            coordinates = Coordinates::detect()
+                .await
+                .inspect_err(|v| warn!("detecting coordinates failed: {v}"))
+                .ok();
+        }
+        let mut result = match self.countries.is_empty() {
+            true => get_info(&self.url(filter, coordinates)).await,
+            false => self.fetch_countries(filter, coordinates).await,
+        }
+        .and_then(|v| self.check(v));
+        if self.legacy_fallback && !result.as_ref().is_ok_and(|v| !v.is_empty()) {
+            match &result {
+                Ok(_) => warn!("no servers listed, falling back to the legacy endpoint"),
+                Err(v) => {
+                    warn!("fetching servers failed, falling back to the legacy endpoint: {v}")
+                }
+            }
+            if let Ok(v) = legacy::get_info().await {
+                result = Ok(self.in_countries(v));
+            }
+        }
+        #[cfg(feature = "embedded-fallback")]
+        if let Err(v) = &result {
+            warn!("fetching servers failed, falling back to the embedded list: {v}");
+            if let Ok((servers, fetched_at)) = crate::embedded::servers() {
+                return Ok((self.in_countries(servers), fetched_at));
+            }
+        }
+        Ok((result?, SystemTime::now()))
+    }
+
+    /// Fails on the first unknown country, city or technology in [`ParseMode::Strict`].
+    fn check(&self, servers: Vec<Root>) -> io::Result<Vec<Root>> {
+        if self.parse_mode == ParseMode::Lenient {
+            return Ok(servers);
+        }
+        for v in &servers {
+            let unknown = match v.location() {
+                Some(l) if matches!(l.code, Country::Other(_)) => {
+                    Some(format!("country {:?}", l.code))
+                }
+                Some(l) if matches!(l.city.name, City::Other(_)) => {
+                    Some(format!("city {:?}", l.city.name))
+                }
+                _ => v
+                    .technologies
+                    .iter()
+                    .find(|v| matches!(v.identifier, Technology::Other(_)))
+                    .map(|v| format!("technology {:?}", v.identifier)),
+            };
+            if let Some(unknown) = unknown {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("unknown {unknown} on {}", v.hostname),
+                ));
+            }
+        }
+        Ok(servers)
+    }
+
+    /// Narrows down the legacy and embedded fallbacks, which can't filter by country.
+    fn in_countries(&self, mut servers: Vec<Root>) -> Vec<Root> {
+        if !self.countries.is_empty() {
+            servers.retain(|v| {
+                v.location()
+                    .is_some_and(|v| self.countries.contains(&v.code))
+            });
+        }
+        servers
+    }
+
+    async fn fetch_countries(
+        &self,
+        filter: &str,
+        coordinates: Option<Coordinates>,
+    ) -> io::Result<Vec<Root>> {
+        let ids = country_ids().await?;
+        let mut tasks = Vec::new();
+        for country in &self.countries {
+            let Some(id) = ids.get(country) else {
+                warn!("NordVPN has no servers in {country:?}");
+                continue;
+            };
+            let url = self.url(&format!("{filter}&filters[country_id]={id}"), coordinates);
+            tasks.push(AbortOnDropHandle::new(tokio::spawn(api::keep_base(
+                async move { get_info(&url).await },
+            ))));
+        }
+        // Awaited in order, so the servers keep the order the countries were given in.
+        let mut servers = Vec::new();
+        for task in tasks {
+            servers.extend(task.await.map_err(io::Error::other)??);
+        }
+        Ok(servers)
+    }
+}
+
+#[derive(Deserialize)]
+struct CountryId {
+    id: u32,
+    code: Country,
+}
+
+/// The API filters by its own country ids rather than by code.
+pub(crate) async fn country_ids() -> io::Result<HashMap<Country, u32>> {
+    let countries: Vec<CountryId> = api::get(COUNTRIES_URL)
+        .send()
+        .await
+        .and_then(|v| v.error_for_status())
+        .map_err(io::Error::other)?
+        .json()
+        .await
+        .map_err(io::Error::other)?;
+    Ok(countries.into_iter().map(|v| (v.code, v.id)).collect())
+}
+
+#[cfg(feature = "https-proxy")]
+impl Builder<Proxy> {
+    /// Uses the CyberSec endpoints, which filter malware and ads on the exit.
+    pub fn threat_protection(mut self, enabled: bool) -> Self {
+        self.threat_protection = enabled;
+        self
+    }
+
+    /// Tests which of its advertised ports (89 and 443 unless the API lists others) every
+    /// endpoint accepts connections on from this network, using the first one that does and
+    /// dropping the endpoints where none does, for networks that block port 89.
+    pub fn probe_ports(mut self, enabled: bool) -> Self {
+        self.probe_ports = enabled;
+        self
+    }
+
+    /// Like [`Builder::try_build`], panicking if the list can't be fetched.
+    pub async fn build(self) -> Proxy {
+        self.try_build()
+            .await
+            .expect("fetching the server list failed")
+    }
+
+    /// Fetches the list, failing if neither the API nor an enabled fallback provides one or,
+    /// in [`ParseMode::Strict`], if the API lists something unknown.
+    pub async fn try_build(self) -> io::Result<Proxy> {
+        let technology = match self.threat_protection {
+            true => Technology::ProxySslCybersec,
+            false => Technology::ProxySsl,
+        };
+        let (servers, fetched_at) = self.fetch(Proxy::FILTER).await?;
+        let mut proxy = Proxy::from_roots(servers, &technology, self.include_maintenance);
+        proxy.fetched_at = fetched_at;
+        if self.probe_ports {
+            proxy.probe_ports(Duration::from_secs(3)).await;
+        }
+        Ok(proxy)
+    }
+}
+
+#[cfg(feature = "socks5")]
+impl Builder<Socks5> {
+    /// Tests whether every server accepts connections from this network on its advertised port
+    /// or on 1080, using the first one that does and dropping the servers where neither does.
+    pub fn probe_ports(mut self, enabled: bool) -> Self {
+        self.probe_ports = enabled;
+        self
+    }
+
+    /// Like [`Builder::try_build`], panicking if the list can't be fetched.
+    pub async fn build(self) -> Socks5 {
+        self.try_build()
+            .await
+            .expect("fetching the server list failed")
+    }
+
+    /// Fetches the list, failing if neither the API nor an enabled fallback provides one or,
+    /// in [`ParseMode::Strict`], if the API lists something unknown.
+    pub async fn try_build(self) -> io::Result<Socks5> {
+        let (servers, fetched_at) = self.fetch(Socks5::FILTER).await?;
+        let mut socks = Socks5::from_roots(servers, self.include_maintenance);
+        socks.fetched_at = fetched_at;
+        if self.probe_ports {
+            socks.probe_ports(Duration::from_secs(3)).await;
+        }
+        Ok(socks)
+    }
+}
+
+impl Builder<DedicatedIp> {
+    /// Like [`Builder::try_build`], panicking if the list can't be fetched.
+    pub async fn build(self) -> DedicatedIp {
+        self.try_build()
+            .await
+            .expect("fetching the server list failed")
+    }
+
+    /// Fetches the list, failing if neither the API nor an enabled fallback provides one or,
+    /// in [`ParseMode::Strict`], if the API lists something unknown.
+    pub async fn try_build(self) -> io::Result<DedicatedIp> {
+        let (servers, fetched_at) = self.fetch(DedicatedIp::FILTER).await?;
+        let mut dedicated = DedicatedIp::from_roots(servers, self.include_maintenance);
+        dedicated.fetched_at = fetched_at;
+        Ok(dedicated)
+    }
+}
+
+#[cfg(all(test, feature = "socks5"))]
+mod tests {
+    use tokio_util::sync::CancellationToken;
+
+    use super::ParseMode;
+    use crate::{Country, Socks5, Technology, geo::Coordinates, structure::Root};
+
+    #[test]
+    fn url() {
+        let builder = Socks5::builder();
+        assert_eq!(
+            builder.endpoint(Socks5::FILTER, None),
+            "https://api.nordvpn.com/v1/servers?filters[servers_technologies][identifier]=socks&limit=0"
+        );
+        assert!(
+            builder
+                .url(Socks5::FILTER, None)
+                .ends_with("&limit=0&fields[servers.hostname]&fields[servers.load]&fields[servers.status]&fields[servers.services.identifier]&fields[servers.groups.identifier]&fields[servers.locations.country.code]&fields[servers.locations.country.city.name]&fields[servers.locations.country.city.hub_score]&fields[servers.technologies.identifier]&fields[servers.technologies.pivot.status]&fields[servers.technologies.metadata]")
+        );
+        let builder = builder.recommended(5);
+        assert_eq!(
+            builder.endpoint(Socks5::FILTER, Some(Coordinates::new(52.5, 13.25))),
+            "https://api.nordvpn.com/v1/servers/recommendations?filters[servers_technologies][identifier]=socks&limit=5&coordinates[latitude]=52.5&coordinates[longitude]=13.25"
+        );
+    }
+
+    #[test]
+    fn countries() {
+        let roots = serde_json::from_str(include_str!("../fixtures/servers.json")).unwrap();
+        let servers = Socks5::builder()
+            .countries([Country::NL, Country::JP])
+            .in_countries(roots);
+        let hostnames = servers
+            .iter()
+            .map(|v| v.hostname.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            hostnames,
+            [
+                "nl912.nordvpn.com",
+                "nl980.nordvpn.com",
+                "jp612.nordvpn.com"
+            ]
+        );
+        let ids: Vec<super::CountryId> =
+            serde_json::from_str(r#"[{"id": 81, "name": "Germany", "code": "DE", "cities": []}]"#)
+                .unwrap();
+        assert_eq!((ids[0].id, &ids[0].code), (81, &Country::DE));
+    }
+
+    #[test]
+    fn parse_mode() {
+        let mut roots: Vec<Root> =
+            serde_json::from_str(include_str!("../fixtures/servers.json")).unwrap();
+        let strict = Socks5::builder().parse_mode(ParseMode::Strict);
+        assert!(strict.check(roots.clone()).is_ok());
+        roots[1].technologies[0].identifier = Technology::Other("quantum_udp".to_string());
+        let error = strict.check(roots.clone()).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "unknown technology Other(\"quantum_udp\") on nl912.nordvpn.com"
+        );
+        assert_eq!(Socks5::builder().check(roots).unwrap().len(), 5);
+    }
+
+    #[tokio::test]
+    async fn shutdown() {
+        let token = CancellationToken::new();
+        token.cancel();
+        let error = Socks5::builder()
+            .shutdown(token)
+            .try_build()
+            .await
+            .unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::Interrupted);
+    }
+}
@@ -0,0 +1,197 @@
+use std::{
+    env, fmt, fs, io,
+    path::Path,
+    sync::{Arc, RwLock},
+    time::{Duration, SystemTime},
+};
+
+use serde::Deserialize;
+
+use crate::api;
+
+const URL: &str = "https://api.nordvpn.com/v1/users/services/credentials";
+
+/// NordVPN service credentials, which differ from the account login.
+///
+/// `Debug` never prints the password.
+#[derive(Clone, PartialEq, Eq, Hash, Deserialize)]
+pub struct Credentials {
+    username: String,
+    password: String,
+}
+
+impl Credentials {
+    pub fn new(username: impl Into<String>, password: impl Into<String>) -> Self {
+        Credentials {
+            username: username.into(),
+            password: password.into(),
+        }
+    }
+
+    /// Looks up the service credentials of the account owning the access `token`.
+    pub async fn from_token(token: &str) -> reqwest::Result<Self> {
+        api::get(URL)
+            .basic_auth("token", Some(token))
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await
+    }
+
+    /// Reads an OpenVPN `auth-user-pass` file: the username on the first line, the password on
+    /// the second.
+    pub fn from_file(path: impl AsRef<Path>) -> io::Result<Self> {
+        let content = fs::read_to_string(path)?;
+        let mut lines = content.lines().map(str::trim);
+        match (lines.next(), lines.next()) {
+            (Some(username), Some(password)) if !username.is_empty() && !password.is_empty() => {
+                Ok(Self::new(username, password))
+            }
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "expected a username and a password line",
+            )),
+        }
+    }
+
+    /// Reads the `NORDVPN_USERNAME` and `NORDVPN_PASSWORD` environment variables.
+    pub fn from_env() -> io::Result<Self> {
+        let var = |name| {
+            env::var(name)
+                .map_err(|_| io::Error::new(io::ErrorKind::NotFound, format!("{name} is not set")))
+        };
+        Ok(Self::new(
+            var("NORDVPN_USERNAME")?,
+            var("NORDVPN_PASSWORD")?,
+        ))
+    }
+
+    pub fn username(&self) -> &str {
+        &self.username
+    }
+
+    pub fn password(&self) -> &str {
+        &self.password
+    }
+}
+
+impl fmt::Debug for Credentials {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Credentials")
+            .field("username", &self.username)
+            .field("password", &"<redacted>")
+            .finish()
+    }
+}
+
+/// Username and password as the arguments of tokio-socks' `connect_with_password`.
+#[derive(Clone, Copy)]
+pub struct SocksAuth<'a> {
+    pub username: &'a str,
+    pub password: &'a str,
+}
+
+impl<'a> From<&'a Credentials> for SocksAuth<'a> {
+    fn from(v: &'a Credentials) -> Self {
+        SocksAuth {
+            username: &v.username,
+            password: &v.password,
+        }
+    }
+}
+
+/// Credentials that can be replaced at runtime, e.g. after rotating them in the NordVPN
+/// dashboard, while long-lived components keep a handle to them.
+#[derive(Clone)]
+pub struct SharedCredentials {
+    inner: Arc<RwLock<Credentials>>,
+}
+
+impl SharedCredentials {
+    pub fn new(credentials: Credentials) -> Self {
+        SharedCredentials {
+            inner: Arc::new(RwLock::new(credentials)),
+        }
+    }
+
+    pub fn get(&self) -> Credentials {
+        self.inner.read().unwrap().clone()
+    }
+
+    pub fn set(&self, credentials: Credentials) {
+        *self.inner.write().unwrap() = credentials;
+    }
+
+    /// Reloads the credentials from the `auth-user-pass` file at `path` whenever its
+    /// modification time changes, checking every `interval`. An unreadable file keeps the
+    /// current credentials. Never returns.
+    pub async fn watch(&self, path: impl AsRef<Path>, interval: Duration) {
+        let path = path.as_ref();
+        let mut modified = None::<SystemTime>;
+        let mut interval = tokio::time::interval(interval);
+        loop {
+            interval.tick().await;
+            let Ok(current) = fs::metadata(path).and_then(|v| v.modified()) else {
+                continue;
+            };
+            if modified == Some(current) {
+                continue;
+            }
+            if let Ok(v) = Credentials::from_file(path) {
+                info!("reloaded service credentials from {}", path.display());
+                self.set(v);
+                modified = Some(current);
+            }
+        }
+    }
+}
+
+impl From<Credentials> for SharedCredentials {
+    fn from(credentials: Credentials) -> Self {
+        Self::new(credentials)
+    }
+}
+
+impl fmt::Debug for SharedCredentials {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("SharedCredentials")
+            .field(&*self.inner.read().unwrap())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, time::Duration};
+
+    use super::{Credentials, SharedCredentials};
+
+    #[test]
+    fn redacted() {
+        let debug = format!("{:?}", Credentials::new("user", "hunter2"));
+        assert!(debug.contains("user") && !debug.contains("hunter2"));
+    }
+
+    #[tokio::test]
+    async fn watch() {
+        let path = std::env::temp_dir().join(format!("nord-proxy-auth-{}", std::process::id()));
+        fs::write(&path, "user\nfirst\n").unwrap();
+        let shared = SharedCredentials::new(Credentials::new("", ""));
+        let task = tokio::spawn({
+            let (shared, path) = (shared.clone(), path.clone());
+            async move { shared.watch(path, Duration::from_millis(10)).await }
+        });
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(shared.get(), Credentials::new("user", "first"));
+
+        fs::write(&path, "user\nsecond\n").unwrap();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(shared.get().password(), "second");
+
+        fs::write(&path, "user\n").unwrap();
+        assert!(Credentials::from_file(&path).is_err());
+        task.abort();
+        fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,89 @@
+//! Global switch for reproducible selection, e.g. in CI or research runs.
+use std::{
+    cell::Cell,
+    cmp::Ordering,
+    sync::atomic::{AtomicBool, Ordering as AtomicOrdering},
+};
+
+use rand::{SeedableRng, rngs::StdRng};
+
+use crate::ProxyInfo;
+
+static DETERMINISTIC: AtomicBool = AtomicBool::new(false);
+
+thread_local! {
+    static SCOPED: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Makes selection reproducible: random sampling uses a fixed seed and ties in load are broken
+/// by hostname instead of the order the API listed the servers in.
+///
+/// Given the same server list, every run on every machine then hands out the same sequence.
+pub fn set_deterministic(enabled: bool) {
+    DETERMINISTIC.store(enabled, AtomicOrdering::Relaxed);
+}
+
+/// Runs `f` with selection reproducible on the current thread only, as with
+/// [`set_deterministic`], leaving other threads alone.
+pub fn with_deterministic<T>(f: impl FnOnce() -> T) -> T {
+    struct Restore(bool);
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            SCOPED.set(self.0);
+        }
+    }
+    let _restore = Restore(SCOPED.replace(true));
+    f()
+}
+
+pub fn is_deterministic() -> bool {
+    SCOPED.get() || DETERMINISTIC.load(AtomicOrdering::Relaxed)
+}
+
+pub(crate) fn rng() -> StdRng {
+    match is_deterministic() {
+        true => StdRng::seed_from_u64(0),
+        false => StdRng::from_rng(&mut rand::rng()),
+    }
+}
+
+/// Least loaded first; equally loaded endpoints keep their order unless deterministic.
+pub(crate) fn by_load(a: &ProxyInfo, b: &ProxyInfo) -> Ordering {
+    let order = a.load.cmp(&b.load);
+    match is_deterministic() {
+        true => order.then_with(|| a.hostname.cmp(&b.hostname)),
+        false => order,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{is_deterministic, with_deterministic};
+    use crate::{Country, Credentials, ProxyInfo, ProxyTrait, pool::tests::proxy};
+
+    struct Fixed;
+
+    impl ProxyTrait for Fixed {
+        fn proxies_with(&self, _: &Credentials) -> Vec<ProxyInfo> {
+            (0..20)
+                .rev()
+                .map(|v| proxy(&format!("de{v}.nordvpn.com"), Country::DE, 10))
+                .collect()
+        }
+    }
+
+    #[test]
+    fn reproducible() {
+        let credentials = Credentials::new("user", "pass");
+        let hostnames = |v: Vec<ProxyInfo>| v.into_iter().map(|v| v.hostname).collect::<Vec<_>>();
+        with_deterministic(|| {
+            let first = hostnames(Fixed.sample(5, &credentials));
+            assert_eq!(first, hostnames(Fixed.sample(5, &credentials)));
+            assert_eq!(
+                Fixed.best(&credentials).unwrap().hostname,
+                "de0.nordvpn.com"
+            );
+        });
+        assert!(!is_deterministic());
+    }
+}
@@ -0,0 +1,27 @@
+//! Diagnostics through `tracing` or `log`, whichever feature is enabled; `tracing` wins when
+//! both are. Without either the messages are compiled out.
+
+macro_rules! emit {
+    ($level:ident, $($arg:tt)+) => {{
+        #[cfg(feature = "tracing")]
+        ::tracing::$level!($($arg)+);
+        #[cfg(all(feature = "log", not(feature = "tracing")))]
+        ::log::$level!($($arg)+);
+        #[cfg(not(any(feature = "tracing", feature = "log")))]
+        {
+            let _ = format_args!($($arg)+);
+        }
+    }};
+}
+
+macro_rules! debug {
+    ($($arg:tt)+) => { emit!(debug, $($arg)+) };
+}
+
+macro_rules! info {
+    ($($arg:tt)+) => { emit!(info, $($arg)+) };
+}
+
+macro_rules! warn {
+    ($($arg:tt)+) => { emit!(warn, $($arg)+) };
+}
@@ -0,0 +1,172 @@
+//! Resolving endpoint hostnames and connecting to them.
+use std::{
+    io,
+    net::{IpAddr, SocketAddr},
+    pin::Pin,
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+
+use tokio::{
+    net::{TcpStream, lookup_host},
+    task::JoinSet,
+};
+
+/// Resolver for endpoint hostnames, installed with [`set_resolver`].
+pub trait Resolve: Send + Sync {
+    fn resolve<'a>(
+        &'a self,
+        host: &'a str,
+        port: u16,
+    ) -> Pin<Box<dyn Future<Output = io::Result<Vec<SocketAddr>>> + Send + 'a>>;
+}
+
+#[cfg(feature = "hickory")]
+impl Resolve for hickory_resolver::TokioResolver {
+    fn resolve<'a>(
+        &'a self,
+        host: &'a str,
+        port: u16,
+    ) -> Pin<Box<dyn Future<Output = io::Result<Vec<SocketAddr>>> + Send + 'a>> {
+        Box::pin(async move {
+            let ips = self.lookup_ip(host).await.map_err(io::Error::other)?;
+            Ok(ips.iter().map(|v| SocketAddr::new(v, port)).collect())
+        })
+    }
+}
+
+static RESOLVER: RwLock<Option<Arc<dyn Resolve>>> = RwLock::new(None);
+
+/// Resolves endpoint hostnames with `resolver` instead of the system resolver, in health
+/// checks, [`ProxyInfo::resolve`](crate::ProxyInfo::resolve), raw streams and the gateway.
+///
+/// reqwest clients resolve on their own; give them a resolver with
+/// `ClientBuilder::dns_resolver`.
+pub fn set_resolver(resolver: impl Resolve + 'static) {
+    *RESOLVER.write().unwrap() = Some(Arc::new(resolver));
+}
+
+/// Goes back to the system resolver after [`set_resolver`].
+pub fn reset_resolver() {
+    *RESOLVER.write().unwrap() = None;
+}
+
+pub(crate) async fn resolve(host: &str, port: u16) -> io::Result<Vec<SocketAddr>> {
+    if let Ok(v) = host.parse::<IpAddr>() {
+        return Ok(vec![SocketAddr::new(v, port)]);
+    }
+    let resolver = RESOLVER.read().unwrap().clone();
+    match resolver {
+        Some(v) => v.resolve(host, port).await,
+        None => Ok(lookup_host((host, port)).await?.collect()),
+    }
+}
+
+/// How long an attempt gets before the next address is tried in parallel (RFC 8305).
+const ATTEMPT_DELAY: Duration = Duration::from_millis(250);
+
+/// Connects to `host:port`, racing its addresses Happy Eyeballs style so that a dead address
+/// only costs [`ATTEMPT_DELAY`] instead of a full connect timeout.
+pub(crate) async fn connect(host: &str, port: u16) -> io::Result<TcpStream> {
+    let mut addresses = interleave(resolve(host, port).await?).into_iter();
+    let mut attempts = JoinSet::new();
+    let mut error = None;
+    loop {
+        let more = match addresses.next() {
+            Some(v) => {
+                attempts.spawn(TcpStream::connect(v));
+                true
+            }
+            None => false,
+        };
+        if attempts.is_empty() {
+            return Err(error.unwrap_or_else(|| {
+                io::Error::new(io::ErrorKind::NotFound, format!("{host} has no addresses"))
+            }));
+        }
+        tokio::select! {
+            Some(result) = attempts.join_next() => match result.map_err(io::Error::other)? {
+                Ok(v) => return Ok(v),
+                Err(v) => error = Some(v),
+            },
+            _ = tokio::time::sleep(ATTEMPT_DELAY), if more => {}
+        }
+    }
+}
+
+/// Alternates between IPv6 and IPv4, starting with the family of the first address.
+fn interleave(addresses: Vec<SocketAddr>) -> Vec<SocketAddr> {
+    let Some(first) = addresses.first() else {
+        return addresses;
+    };
+    let (mut preferred, mut other): (Vec<_>, Vec<_>) = addresses
+        .iter()
+        .partition(|v| v.is_ipv6() == first.is_ipv6());
+    let mut interleaved = Vec::with_capacity(addresses.len());
+    preferred.reverse();
+    other.reverse();
+    while let Some(v) = preferred.pop() {
+        interleaved.push(v);
+        interleaved.extend(other.pop());
+    }
+    interleaved.extend(other.into_iter().rev());
+    interleaved
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{io, net::SocketAddr, pin::Pin};
+
+    use tokio::net::TcpListener;
+
+    use super::{Resolve, connect, interleave, reset_resolver, set_resolver};
+
+    #[test]
+    fn order() {
+        let addresses = ["[::1]:1", "[::2]:1", "[::3]:1", "1.0.0.1:1"]
+            .map(|v| v.parse::<SocketAddr>().unwrap())
+            .to_vec();
+        let ordered = interleave(addresses.clone());
+        assert_eq!(
+            ordered,
+            [addresses[0], addresses[3], addresses[1], addresses[2]]
+        );
+    }
+
+    #[tokio::test]
+    async fn fallback() {
+        // `localhost` usually resolves to `::1` as well, where nothing listens.
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let stream = connect("localhost", port).await.unwrap();
+        assert_eq!(stream.peer_addr().unwrap().port(), port);
+    }
+
+    struct Fixed;
+
+    impl Resolve for Fixed {
+        fn resolve<'a>(
+            &'a self,
+            host: &'a str,
+            port: u16,
+        ) -> Pin<Box<dyn Future<Output = io::Result<Vec<SocketAddr>>> + Send + 'a>> {
+            Box::pin(async move {
+                match host {
+                    "endpoint.test" => Ok(vec![SocketAddr::from(([127, 0, 0, 1], port))]),
+                    _ => Ok(tokio::net::lookup_host((host, port)).await?.collect()),
+                }
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn resolver() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        set_resolver(Fixed);
+        let connected = connect("endpoint.test", port).await;
+        reset_resolver();
+        assert!(connected.is_ok());
+        assert!(connect("endpoint.test", port).await.is_err());
+    }
+}
@@ -0,0 +1,43 @@
+//! The server list bundled with the `embedded-fallback` feature, which the builders fall back to
+//! when the API can't be reached, so tools keep working with a slightly stale list.
+//!
+//! `data/servers.json.deflate` is the raw-deflated JSON `{"fetched_at": <unix seconds>,
+//! "servers": [..]}`, with `servers` as returned by `v1/servers?limit=0` with the fields the
+//! builders ask for. It is refreshed before releases.
+use std::{
+    io,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use serde::Deserialize;
+
+use crate::structure::Root;
+
+static SERVERS: &[u8] = include_bytes!("../data/servers.json.deflate");
+
+#[derive(Deserialize)]
+struct Embedded {
+    fetched_at: u64,
+    servers: Vec<Root>,
+}
+
+/// The bundled servers and when they were fetched.
+pub(crate) fn servers() -> io::Result<(Vec<Root>, SystemTime)> {
+    let json = miniz_oxide::inflate::decompress_to_vec(SERVERS)
+        .map_err(|v| io::Error::new(io::ErrorKind::InvalidData, v.to_string()))?;
+    let embedded: Embedded = serde_json::from_slice(&json)?;
+    Ok((
+        embedded.servers,
+        UNIX_EPOCH + Duration::from_secs(embedded.fetched_at),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn servers() {
+        let (servers, fetched_at) = super::servers().unwrap();
+        assert!(!servers.is_empty());
+        assert!(fetched_at.elapsed().is_ok());
+    }
+}
@@ -0,0 +1,182 @@
+//! C ABI for embedding the proxy discovery in non-Rust applications, declared in
+//! `include/nord_proxy.h`. Build the shared library with
+//! `cargo rustc --lib --release --features ffi --crate-type cdylib`.
+//!
+//! Every function returns a string the caller owns and frees with [`nord_proxy_free`], or NULL
+//! on invalid arguments or if the server list can't be fetched.
+use std::{
+    ffi::{CStr, CString, c_char},
+    time::Duration,
+};
+
+use crate::{Credentials, Proxy, ProxyInfo, ProxyTrait, Socks5, export, monitor, snapshot};
+
+/// Lists the endpoints of `kind`, `"socks5"` or `"https"`, as a JSON array.
+///
+/// # Safety
+///
+/// `kind` must be a valid NUL-terminated string.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn nord_proxy_list(kind: *const c_char) -> *mut c_char {
+    let Some(kind) = (unsafe { string(kind) }) else {
+        return std::ptr::null_mut();
+    };
+    let Some(proxies) = block_on(fetch(kind, &Credentials::new("", ""))) else {
+        return std::ptr::null_mut();
+    };
+    let entries = snapshot::Snapshot::new(&proxies).entries;
+    into_raw(serde_json::to_string(&entries).expect("entries serialize to JSON"))
+}
+
+/// Connects to `hostname:port`, returning `{"latency_ms": n}`, with `null` if it is
+/// unreachable within `timeout_ms`.
+///
+/// # Safety
+///
+/// `hostname` must be a valid NUL-terminated string.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn nord_proxy_test(
+    hostname: *const c_char,
+    port: u16,
+    timeout_ms: u64,
+) -> *mut c_char {
+    let Some(hostname) = (unsafe { string(hostname) }) else {
+        return std::ptr::null_mut();
+    };
+    let latency = block_on(monitor::probe(
+        hostname,
+        port,
+        Duration::from_millis(timeout_ms),
+    ));
+    let latency = latency.map(|v| v.as_millis() as u64);
+    into_raw(serde_json::json!({ "latency_ms": latency }).to_string())
+}
+
+/// Exports the endpoints of `kind` with the service credentials `username` and `password` in
+/// `format`: `"proxifier"`, `"foxyproxy"` or a template for [`export::render`].
+///
+/// # Safety
+///
+/// All arguments must be valid NUL-terminated strings.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn nord_proxy_export(
+    kind: *const c_char,
+    format: *const c_char,
+    username: *const c_char,
+    password: *const c_char,
+) -> *mut c_char {
+    let args = unsafe {
+        (
+            string(kind),
+            string(format),
+            string(username),
+            string(password),
+        )
+    };
+    let (Some(kind), Some(format), Some(username), Some(password)) = args else {
+        return std::ptr::null_mut();
+    };
+    let credentials = Credentials::new(username, password);
+    let Some(proxies) = block_on(fetch(kind, &credentials)) else {
+        return std::ptr::null_mut();
+    };
+    into_raw(match format {
+        "proxifier" => export::proxifier(&proxies, &credentials, &[]),
+        "foxyproxy" => export::foxyproxy(&proxies, &credentials, &[]),
+        template => export::render(template, &proxies, &credentials),
+    })
+}
+
+/// Frees a string returned by the functions above. NULL is ignored.
+///
+/// # Safety
+///
+/// `s` must come from this library and must not be used afterwards.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn nord_proxy_free(s: *mut c_char) {
+    if !s.is_null() {
+        drop(unsafe { CString::from_raw(s) });
+    }
+}
+
+/// `None` for unknown kinds and failed fetches, which must not panic across the C boundary.
+async fn fetch(kind: &str, credentials: &Credentials) -> Option<Vec<ProxyInfo>> {
+    match kind {
+        "socks5" => Some(
+            Socks5::builder()
+                .try_build()
+                .await
+                .ok()?
+                .proxies_with(credentials),
+        ),
+        "https" => Some(
+            Proxy::builder()
+                .try_build()
+                .await
+                .ok()?
+                .proxies_with(credentials),
+        ),
+        _ => None,
+    }
+}
+
+unsafe fn string<'a>(s: *const c_char) -> Option<&'a str> {
+    if s.is_null() {
+        return None;
+    }
+    unsafe { CStr::from_ptr(s) }.to_str().ok()
+}
+
+fn into_raw(s: String) -> *mut c_char {
+    // Exports never contain NUL, but a template could.
+    CString::new(s).map_or(std::ptr::null_mut(), CString::into_raw)
+}
+
+/// Runs `future` to completion on a runtime of its own, as C callers have none.
+fn block_on<F: Future>(future: F) -> F::Output {
+    tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .expect("building a current-thread runtime")
+        .block_on(future)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::{CStr, CString};
+
+    use super::{nord_proxy_free, nord_proxy_list, nord_proxy_test};
+
+    #[test]
+    fn strings() {
+        let kind = CString::new("wireguard").unwrap();
+        assert!(unsafe { nord_proxy_list(kind.as_ptr()) }.is_null());
+        assert!(unsafe { nord_proxy_list(std::ptr::null()) }.is_null());
+
+        let hostname = CString::new("127.0.0.1").unwrap();
+        let result = unsafe { nord_proxy_test(hostname.as_ptr(), 1, 100) };
+        let json = unsafe { CStr::from_ptr(result) }
+            .to_str()
+            .unwrap()
+            .to_string();
+        unsafe { nord_proxy_free(result) };
+        assert_eq!(json, r#"{"latency_ms":null}"#);
+    }
+
+    #[cfg(not(feature = "embedded-fallback"))]
+    #[tokio::test]
+    async fn unreachable() {
+        use super::fetch;
+        use crate::{
+            Credentials,
+            mock::{MockApi, Response},
+        };
+
+        let mock = MockApi::new().route("/v1/servers", Response::Status(503));
+        mock.run(async {
+            assert!(fetch("socks5", &Credentials::new("", "")).await.is_none());
+            assert!(fetch("https", &Credentials::new("", "")).await.is_none());
+        })
+        .await;
+    }
+}
@@ -0,0 +1,120 @@
+//! Fetching everything a service needs at startup at once.
+use std::{collections::HashMap, io, sync::Arc};
+
+use tokio::task::JoinSet;
+
+#[cfg(feature = "https-proxy")]
+use crate::Proxy;
+#[cfg(feature = "socks5")]
+use crate::Socks5;
+use crate::{
+    Country, ProxyInfo, ProxyTrait, api, builder,
+    monitor::{Monitor, Store},
+};
+
+/// The proxy lists and the ids NordVPN's API filters countries by.
+#[derive(Debug, Clone)]
+pub struct Fleet {
+    #[cfg(feature = "socks5")]
+    pub socks5: Socks5,
+    #[cfg(feature = "https-proxy")]
+    pub https: Proxy,
+    pub country_ids: HashMap<Country, u32>,
+}
+
+enum Part {
+    #[cfg(feature = "socks5")]
+    Socks5(io::Result<Socks5>),
+    #[cfg(feature = "https-proxy")]
+    Https(io::Result<Proxy>),
+    CountryIds(io::Result<HashMap<Country, u32>>),
+    Probed,
+}
+
+impl Fleet {
+    /// Fetches the lists and the country ids concurrently, cutting the cold start to the
+    /// slowest of the requests; fails with the first of them that fails.
+    pub async fn fetch() -> io::Result<Self> {
+        Self::bootstrap::<crate::monitor::MemoryStore>(None).await
+    }
+
+    /// Like [`Fleet::fetch`], also probing the endpoints of each list with `monitor` as soon as
+    /// it is in, while the others are still being fetched.
+    pub async fn fetch_probed<S: Store + 'static>(monitor: Arc<Monitor<S>>) -> io::Result<Self> {
+        Self::bootstrap(Some(monitor)).await
+    }
+
+    async fn bootstrap<S: Store + 'static>(monitor: Option<Arc<Monitor<S>>>) -> io::Result<Self> {
+        let mut set = JoinSet::new();
+        #[cfg(feature = "socks5")]
+        set.spawn(api::keep_base(async {
+            Part::Socks5(Socks5::builder().try_build().await)
+        }));
+        #[cfg(feature = "https-proxy")]
+        set.spawn(api::keep_base(async {
+            Part::Https(Proxy::builder().try_build().await)
+        }));
+        set.spawn(api::keep_base(async {
+            Part::CountryIds(builder::country_ids().await)
+        }));
+
+        #[cfg(feature = "socks5")]
+        let mut socks5 = None;
+        #[cfg(feature = "https-proxy")]
+        let mut https = None;
+        let mut country_ids = None;
+        // Probing only needs the addresses, not working credentials.
+        let probe = |set: &mut JoinSet<Part>, proxies: Vec<ProxyInfo>| {
+            if let Some(monitor) = monitor.clone() {
+                set.spawn(async move {
+                    monitor.probe(&proxies).await;
+                    Part::Probed
+                });
+            }
+        };
+        while let Some(part) = set.join_next().await {
+            match part.map_err(io::Error::other)? {
+                #[cfg(feature = "socks5")]
+                Part::Socks5(v) => {
+                    let v = v?;
+                    probe(&mut set, v.proxies("", ""));
+                    socks5 = Some(v);
+                }
+                #[cfg(feature = "https-proxy")]
+                Part::Https(v) => {
+                    let v = v?;
+                    probe(&mut set, v.proxies("", ""));
+                    https = Some(v);
+                }
+                Part::CountryIds(v) => country_ids = Some(v?),
+                Part::Probed => {}
+            }
+        }
+        Ok(Fleet {
+            #[cfg(feature = "socks5")]
+            socks5: socks5.expect("the SOCKS5 list was fetched"),
+            #[cfg(feature = "https-proxy")]
+            https: https.expect("the HTTPS list was fetched"),
+            country_ids: country_ids.expect("the country ids were fetched"),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(not(feature = "embedded-fallback"))]
+    #[tokio::test]
+    async fn unreachable() {
+        use super::Fleet;
+        use crate::mock::{MockApi, Response};
+
+        let mock = MockApi::new()
+            .route("/v1/servers", Response::Status(503))
+            .route(
+                "/v1/servers/countries",
+                Response::Body(r#"[{"id": 81, "code": "DE"}]"#.to_string()),
+            );
+        let error = mock.run(Fleet::fetch()).await.unwrap_err();
+        assert!(!error.to_string().contains("panicked"), "{error}");
+    }
+}
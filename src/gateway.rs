@@ -0,0 +1,1117 @@
+//! Local proxy that forwards HTTP and SOCKS5 clients through the endpoints of a [`Pool`].
+//!
+//! One listener speaks both protocols: connections starting with the SOCKS5 version byte are
+//! handled as SOCKS5, everything else as an HTTP proxy (`CONNECT` tunnels and plain `http://`
+//! requests). Listeners opened with [`Gateway::serve_country`] only exit through endpoints of
+//! their country, so clients pick the exit by port. Requests for a local path are answered by
+//! the gateway itself:
+//!
+//! | Route                     | Response                                            |
+//! |---------------------------|-----------------------------------------------------|
+//! | `GET /proxy.pac`          | proxy auto-config pointing clients at the gateway   |
+//! | `GET /healthz`            | number of usable endpoints and age of the pool      |
+//! | `GET /readyz`             | `/healthz`, but `503` when unusable or outdated     |
+//! | `GET /admin/proxies`      | endpoints of the pool with ban state and use count  |
+//! | `GET /admin/audit`        | sticky sessions moved to other endpoints, and why   |
+//! | `GET /admin/usage`        | requests, failures, bytes and last use per endpoint |
+//! | `GET /admin/metrics`      | `/admin/usage` in the Prometheus text format        |
+//! | `POST /admin/rotate`      | skips the endpoint that would be handed out next    |
+//! | `POST /admin/ban/{host}`  | bans an endpoint, for `?seconds=` (default 3600)    |
+//! | `POST /admin/refresh`     | replaces the pool using [`Gateway::refresh`]        |
+//! | `POST /admin/credentials` | `{"username": .., "password": ..}` for new tunnels  |
+//!
+//! The admin routes are only served after [`Gateway::admin`] and with [`Gateway::auth`]
+//! credentials configured, which they take as `Authorization: Basic`.
+use std::{
+    io,
+    net::{IpAddr, SocketAddr},
+    pin::Pin,
+    sync::{Arc, RwLock},
+    time::{Duration, Instant},
+};
+
+use base64::{Engine, prelude::BASE64_STANDARD};
+use serde::Serialize;
+use tokio::{
+    io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader, copy_bidirectional},
+    net::{TcpListener, TcpStream, ToSocketAddrs},
+    sync::Mutex,
+    task::JoinSet,
+};
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    Country, Credentials, SharedCredentials,
+    pool::{InFlight, MemoryState, Pool, State},
+    routing,
+    snapshot::Entry,
+    stream::{self, CertificatePins, ProxyStream, SocketOptions},
+};
+
+pub struct Gateway<S = MemoryState> {
+    pool: RwLock<Arc<Pool<S>>>,
+    credentials: SharedCredentials,
+    bypass: Vec<String>,
+    auth: Option<Credentials>,
+    access_log: Option<AccessLog>,
+    admin: bool,
+    refresh: Option<Refresh<S>>,
+    refreshing: Mutex<()>,
+    shutdown: CancellationToken,
+    drain_timeout: Duration,
+    socket: SocketOptions,
+    pins: CertificatePins,
+}
+
+type Refresh<S> = Box<dyn Fn() -> Pin<Box<dyn Future<Output = Pool<S>> + Send>> + Send + Sync>;
+
+type AccessLog = Box<dyn Fn(&Access) + Send + Sync>;
+
+/// One client connection, passed to the [`Gateway::access_log`] callback once it ends.
+#[derive(Debug, Clone, Serialize)]
+pub struct Access {
+    pub client: SocketAddr,
+    /// `host:port` the client asked for, or the path of a local request.
+    pub target: String,
+    /// Endpoint the connection went through, `None` if it was bypassed or never connected.
+    pub upstream: Option<String>,
+    /// Bytes from the client to the target.
+    pub sent: u64,
+    /// Bytes from the target to the client.
+    pub received: u64,
+    pub duration: Duration,
+    pub outcome: Outcome,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Outcome {
+    /// The tunnel was relayed until one side closed it.
+    Completed,
+    /// Answered by the gateway itself, e.g. `/proxy.pac`.
+    Local,
+    Unauthorized,
+    BadRequest,
+    /// No endpoint was available or the target couldn't be reached through it.
+    ConnectFailed,
+    /// The connection broke off with an I/O error.
+    Aborted,
+}
+
+/// Request line and headers of an HTTP request.
+struct Head {
+    method: String,
+    target: String,
+    version: String,
+    headers: Vec<(String, String)>,
+}
+
+impl Head {
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+}
+
+impl<S: State + 'static> Gateway<S> {
+    pub fn new(pool: Arc<Pool<S>>, credentials: impl Into<SharedCredentials>) -> Self {
+        Gateway {
+            pool: RwLock::new(pool),
+            credentials: credentials.into(),
+            bypass: Vec::new(),
+            auth: None,
+            access_log: None,
+            admin: false,
+            refresh: None,
+            refreshing: Mutex::new(()),
+            shutdown: CancellationToken::new(),
+            drain_timeout: Duration::from_secs(30),
+            socket: SocketOptions::default(),
+            pins: CertificatePins::new(),
+        }
+    }
+
+    /// Hosts connected to directly instead of through an endpoint; `*.example.com` matches
+    /// every subdomain.
+    pub fn bypass(mut self, hosts: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.bypass.extend(hosts.into_iter().map(Into::into));
+        self
+    }
+
+    /// Requires clients to log in with `credentials`, via SOCKS5 username/password
+    /// authentication or `Proxy-Authorization: Basic`. `/proxy.pac` stays public.
+    pub fn auth(mut self, credentials: Credentials) -> Self {
+        self.auth = Some(credentials);
+        self
+    }
+
+    /// Calls `log` with an [`Access`] record for every client connection.
+    pub fn access_log(mut self, log: impl Fn(&Access) + Send + Sync + 'static) -> Self {
+        self.access_log = Some(Box::new(log));
+        self
+    }
+
+    /// Serves the `/admin` routes, as long as [`Gateway::auth`] credentials are configured.
+    pub fn admin(mut self, enabled: bool) -> Self {
+        self.admin = enabled;
+        self
+    }
+
+    /// Builds the replacement pool for `POST /admin/refresh` and for pools past their
+    /// [`Pool::max_age`]. Pools sharing an `Arc`ed [`State`] keep their bans and usage across
+    /// refreshes.
+    pub fn refresh<F: Future<Output = Pool<S>> + Send + 'static>(
+        mut self,
+        refresh: impl Fn() -> F + Send + Sync + 'static,
+    ) -> Self {
+        self.refresh = Some(Box::new(move || Box::pin(refresh())));
+        self
+    }
+
+    /// Makes the listeners stop accepting once `token` is cancelled and return after the open
+    /// tunnels closed, or the [`Gateway::drain_timeout`] elapsed.
+    pub fn shutdown(mut self, token: CancellationToken) -> Self {
+        self.shutdown = token;
+        self
+    }
+
+    /// How long a shutdown waits for open tunnels, 30 seconds by default.
+    pub fn drain_timeout(mut self, timeout: Duration) -> Self {
+        self.drain_timeout = timeout;
+        self
+    }
+
+    /// TCP options for the connections to endpoints and bypassed hosts.
+    pub fn socket_options(mut self, options: SocketOptions) -> Self {
+        self.socket = options;
+        self
+    }
+
+    /// Refuses HTTPS endpoints whose certificate doesn't match `pins`. Keep a clone of `pins`
+    /// to refresh them while the gateway runs.
+    pub fn certificate_pins(mut self, pins: CertificatePins) -> Self {
+        self.pins = pins;
+        self
+    }
+
+    /// Service credentials for the endpoints whose pool has no [`Pool::accounts`]; replacing them
+    /// affects new tunnels only.
+    pub fn credentials(&self) -> &SharedCredentials {
+        &self.credentials
+    }
+
+    pub fn pool(&self) -> Arc<Pool<S>> {
+        self.pool.read().unwrap().clone()
+    }
+
+    /// Swaps in `pool` for new connections; open tunnels keep their endpoint.
+    pub fn replace_pool(&self, pool: Arc<Pool<S>>) {
+        *self.pool.write().unwrap() = pool;
+    }
+
+    pub async fn serve(self: Arc<Self>, addr: impl ToSocketAddrs) -> io::Result<()> {
+        self.listen(addr, None).await
+    }
+
+    /// Serves a listener whose connections only go through endpoints in `country`.
+    pub async fn serve_country(
+        self: Arc<Self>,
+        addr: impl ToSocketAddrs,
+        country: Country,
+    ) -> io::Result<()> {
+        self.listen(addr, Some(country)).await
+    }
+
+    /// Serves one listener per `(address, country)` pair, returning when any of them fails or
+    /// all of them shut down.
+    pub async fn serve_countries(
+        self: Arc<Self>,
+        listeners: impl IntoIterator<Item = (SocketAddr, Country)>,
+    ) -> io::Result<()> {
+        let mut set = JoinSet::new();
+        for (addr, country) in listeners {
+            set.spawn(self.clone().serve_country(addr, country));
+        }
+        while let Some(result) = set.join_next().await {
+            result.map_err(io::Error::other)??;
+        }
+        Ok(())
+    }
+
+    async fn listen(
+        self: Arc<Self>,
+        addr: impl ToSocketAddrs,
+        country: Option<Country>,
+    ) -> io::Result<()> {
+        let listener = TcpListener::bind(addr).await?;
+        let country = Arc::new(country);
+        let mut connections = JoinSet::new();
+        loop {
+            let stream = tokio::select! {
+                v = listener.accept() => v?.0,
+                Some(_) = connections.join_next() => continue,
+                _ = self.shutdown.cancelled() => break,
+            };
+            let (gateway, country) = (self.clone(), country.clone());
+            connections.spawn(async move {
+                if let Err(v) = gateway.handle(stream, country.as_ref().as_ref()).await {
+                    debug!("gateway connection failed: {v}");
+                }
+            });
+        }
+        drop(listener);
+        // Tunnels still open after the drain timeout are cut when the set is dropped.
+        let drain = async { while connections.join_next().await.is_some() {} };
+        let _ = tokio::time::timeout(self.drain_timeout, drain).await;
+        Ok(())
+    }
+
+    async fn handle(&self, stream: TcpStream, country: Option<&Country>) -> io::Result<()> {
+        let started = Instant::now();
+        let local = stream.local_addr()?;
+        let mut access = Access {
+            client: stream.peer_addr()?,
+            target: String::new(),
+            upstream: None,
+            sent: 0,
+            received: 0,
+            duration: Duration::ZERO,
+            outcome: Outcome::Aborted,
+        };
+        let mut stream = BufReader::new(stream);
+        let result = match stream.fill_buf().await?.first() {
+            None => return Ok(()),
+            Some(5) => self.socks(stream, country, &mut access).await,
+            Some(_) => self.http(stream, local, country, &mut access).await,
+        };
+        access.duration = started.elapsed();
+        debug!(
+            "{} -> {} via {}: {:?}, {} bytes sent, {} received in {:?}",
+            access.client,
+            access.target,
+            access.upstream.as_deref().unwrap_or("direct"),
+            access.outcome,
+            access.sent,
+            access.received,
+            access.duration
+        );
+        if let Some(log) = &self.access_log {
+            log(&access);
+        }
+        result
+    }
+
+    async fn socks(
+        &self,
+        mut client: BufReader<TcpStream>,
+        country: Option<&Country>,
+        access: &mut Access,
+    ) -> io::Result<()> {
+        let mut greeting = [0; 2];
+        client.read_exact(&mut greeting).await?;
+        let mut methods = vec![0; greeting[1] as usize];
+        client.read_exact(&mut methods).await?;
+        match &self.auth {
+            None if methods.contains(&0) => client.write_all(&[5, 0]).await?,
+            Some(_) if methods.contains(&2) => {
+                client.write_all(&[5, 2]).await?;
+                let _version = client.read_u8().await?;
+                let mut username = vec![0; client.read_u8().await? as usize];
+                client.read_exact(&mut username).await?;
+                let mut password = vec![0; client.read_u8().await? as usize];
+                client.read_exact(&mut password).await?;
+                let ok = self.auth.as_ref().is_some_and(|v| {
+                    v.username().as_bytes() == username && v.password().as_bytes() == password
+                });
+                client.write_all(&[1, !ok as u8]).await?;
+                if !ok {
+                    access.outcome = Outcome::Unauthorized;
+                    return Ok(());
+                }
+            }
+            _ => {
+                access.outcome = Outcome::Unauthorized;
+                return client.write_all(&[5, 0xff]).await;
+            }
+        }
+
+        let mut request = [0; 4];
+        client.read_exact(&mut request).await?;
+        let host = match request[3] {
+            1 => {
+                let mut ip = [0; 4];
+                client.read_exact(&mut ip).await?;
+                IpAddr::from(ip).to_string()
+            }
+            4 => {
+                let mut ip = [0; 16];
+                client.read_exact(&mut ip).await?;
+                IpAddr::from(ip).to_string()
+            }
+            _ => {
+                let mut name = vec![0; client.read_u8().await? as usize];
+                client.read_exact(&mut name).await?;
+                String::from_utf8_lossy(&name).into_owned()
+            }
+        };
+        let port = client.read_u16().await?;
+        access.target = stream::authority(&host, port);
+        if request[1] != 1 {
+            // Command not supported.
+            access.outcome = Outcome::BadRequest;
+            return client.write_all(&[5, 7, 0, 1, 0, 0, 0, 0, 0, 0]).await;
+        }
+        match self.connect(&host, port, country, access).await {
+            Ok((upstream, _slot)) => {
+                client.write_all(&[5, 0, 0, 1, 0, 0, 0, 0, 0, 0]).await?;
+                self.relay(client, upstream, access).await
+            }
+            Err(_) => client.write_all(&[5, 1, 0, 1, 0, 0, 0, 0, 0, 0]).await,
+        }
+    }
+
+    async fn http(
+        &self,
+        mut client: BufReader<TcpStream>,
+        local: SocketAddr,
+        country: Option<&Country>,
+        access: &mut Access,
+    ) -> io::Result<()> {
+        let head = read_head(&mut client).await?;
+        access.target = head.target.clone();
+        if head.target.starts_with('/') {
+            access.outcome = Outcome::Local;
+            let length = head
+                .header("content-length")
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(0);
+            let mut body = vec![0; usize::min(length, 65536)];
+            client.read_exact(&mut body).await?;
+            let (status, content_type, body) = self.local(&head, &body, local).await;
+            return respond(&mut client, status, content_type, &body).await;
+        }
+
+        if !self.authorized(head.header("proxy-authorization")) {
+            access.outcome = Outcome::Unauthorized;
+            return client
+                .write_all(
+                    b"HTTP/1.1 407 Proxy Authentication Required\r\nProxy-Authenticate: Basic realm=\"nord-proxy\"\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                )
+                .await;
+        }
+        if head.method == "CONNECT" {
+            let Some((host, port)) = split_authority(&head.target, 443) else {
+                access.outcome = Outcome::BadRequest;
+                return respond(&mut client, "400 Bad Request", "text/plain", "").await;
+            };
+            return match self.connect(&host, port, country, access).await {
+                Ok((upstream, _slot)) => {
+                    client
+                        .write_all(b"HTTP/1.1 200 Connection established\r\n\r\n")
+                        .await?;
+                    self.relay(client, upstream, access).await
+                }
+                Err(_) => respond(&mut client, "502 Bad Gateway", "text/plain", "").await,
+            };
+        }
+        let Some(rest) = head.target.strip_prefix("http://") else {
+            access.outcome = Outcome::BadRequest;
+            return respond(&mut client, "400 Bad Request", "text/plain", "").await;
+        };
+        let (authority, path) = rest.split_at(rest.find('/').unwrap_or(rest.len()));
+        let Some((host, port)) = split_authority(authority, 80) else {
+            access.outcome = Outcome::BadRequest;
+            return respond(&mut client, "400 Bad Request", "text/plain", "").await;
+        };
+        access.target = stream::authority(&host, port);
+        let Ok((mut upstream, _slot)) = self.connect(&host, port, country, access).await else {
+            return respond(&mut client, "502 Bad Gateway", "text/plain", "").await;
+        };
+        let path = if path.is_empty() { "/" } else { path };
+        let mut forwarded = format!("{} {path} {}\r\n", head.method, head.version);
+        for (k, v) in &head.headers {
+            if !k.to_ascii_lowercase().starts_with("proxy-") {
+                forwarded += &format!("{k}: {v}\r\n");
+            }
+        }
+        forwarded += "\r\n";
+        upstream.write_all(forwarded.as_bytes()).await?;
+        self.relay(client, upstream, access).await
+    }
+
+    /// Opens a connection to the target, through the next endpoint of the pool (in `country` if
+    /// given) unless the host is bypassed, after the endpoint's [`Pool::rate_limit`]. The endpoint
+    /// used is noted in `access`, its [`Pool::max_in_flight`] slot has to be held until the
+    /// connection is closed.
+    async fn connect(
+        &self,
+        host: &str,
+        port: u16,
+        country: Option<&Country>,
+        access: &mut Access,
+    ) -> io::Result<(ProxyStream, Option<InFlight>)> {
+        access.outcome = Outcome::ConnectFailed;
+        if self.bypassed(host) {
+            let stream = self.socket.connect(host, port).await?;
+            return Ok((ProxyStream::Tcp(stream), None));
+        }
+        // Connections arriving meanwhile go through the outdated pool.
+        if let Some(refresh) = &self.refresh
+            && self.pool().is_outdated()
+            && let Ok(_guard) = self.refreshing.try_lock()
+        {
+            debug!("the pool is outdated, refreshing it");
+            self.replace_pool(Arc::new(refresh().await));
+        }
+        let pool = self.pool();
+        let acquired = match country {
+            Some(v) => pool.acquire_in(v).await,
+            None => pool.acquire().await,
+        };
+        let (proxy, slot) = acquired.ok_or_else(|| io::Error::other("no endpoint available"))?;
+        access.upstream = Some(proxy.hostname.clone());
+        let credentials = match pool.account_for(proxy) {
+            Some(v) => v.get(),
+            None => self.credentials.get(),
+        };
+        let stream =
+            stream::connect_pinned(proxy, &credentials, host, port, &self.socket, &self.pins)
+                .await
+                .inspect_err(|_| pool.record_failure(&proxy.hostname))?;
+        Ok((stream, Some(slot)))
+    }
+
+    async fn relay(
+        &self,
+        mut client: BufReader<TcpStream>,
+        mut upstream: ProxyStream,
+        access: &mut Access,
+    ) -> io::Result<()> {
+        access.outcome = Outcome::Aborted;
+        let (sent, received) = copy_bidirectional(&mut client, &mut upstream).await?;
+        (access.sent, access.received) = (sent, received);
+        access.outcome = Outcome::Completed;
+        if let Some(hostname) = &access.upstream {
+            self.pool().record_bytes(hostname, sent + received);
+        }
+        Ok(())
+    }
+
+    /// Status, content type and body for a request to a path of the gateway itself.
+    async fn local(
+        &self,
+        head: &Head,
+        body: &[u8],
+        local: SocketAddr,
+    ) -> (&'static str, &'static str, String) {
+        let (path, query) = head.target.split_once('?').unwrap_or((&head.target, ""));
+        if path == "/proxy.pac" {
+            let host = head
+                .header("host")
+                .map(str::to_string)
+                .unwrap_or_else(|| local.to_string());
+            return (
+                "200 OK",
+                "application/x-ns-proxy-autoconfig",
+                self.pac(&host),
+            );
+        }
+        if let "/healthz" | "/readyz" = path {
+            let pool = self.pool();
+            let health = Health {
+                proxies: pool.proxies().len(),
+                healthy: pool.available_count().await,
+                age: pool.age().as_secs(),
+                outdated: pool.is_outdated(),
+            };
+            let ready = health.healthy > 0 && !health.outdated;
+            return match path == "/healthz" || ready {
+                true => json(&health),
+                false => (
+                    "503 Service Unavailable",
+                    "application/json",
+                    serde_json::to_string(&health).unwrap(),
+                ),
+            };
+        }
+        // Without credentials anyone reaching the listener could take over the pool.
+        let admin = self.admin && self.auth.is_some();
+        let Some(route) = path.strip_prefix("/admin/").filter(|_| admin) else {
+            return ("404 Not Found", "text/plain", String::new());
+        };
+        if !self.authorized(head.header("authorization")) {
+            return ("401 Unauthorized", "text/plain", String::new());
+        }
+        let pool = self.pool();
+        match (head.method.as_str(), route) {
+            ("GET", "proxies") => {
+                let banned = pool.state().banned().await;
+                let usage = pool.state().usage().await;
+                json(
+                    &pool
+                        .proxies()
+                        .iter()
+                        .map(|v| AdminEntry {
+                            banned: banned.contains(&v.hostname),
+                            requests: usage.get(&v.hostname).copied().unwrap_or_default(),
+                            entry: Entry::from(v),
+                        })
+                        .collect::<Vec<_>>(),
+                )
+            }
+            ("GET", "audit") => json(&pool.audit_log()),
+            ("GET", "usage") if query.split('&').any(|v| v == "format=csv") => {
+                ("200 OK", "text/csv", pool.usage_report().to_csv())
+            }
+            ("GET", "usage") => json(&pool.usage_report()),
+            ("GET", "metrics") => (
+                "200 OK",
+                "text/plain; version=0.0.4",
+                pool.usage_report().to_prometheus(),
+            ),
+            ("POST", "rotate") => match pool.next().await {
+                Some(v) => json(&Entry::from(v)),
+                None => ("503 Service Unavailable", "text/plain", String::new()),
+            },
+            ("POST", "refresh") => match &self.refresh {
+                Some(refresh) => {
+                    let pool = refresh().await;
+                    let proxies = pool.proxies().len();
+                    self.replace_pool(Arc::new(pool));
+                    json(&Refreshed { proxies })
+                }
+                None => ("501 Not Implemented", "text/plain", String::new()),
+            },
+            ("POST", "credentials") => match serde_json::from_slice(body) {
+                Ok(v) => {
+                    self.credentials.set(v);
+                    ("204 No Content", "text/plain", String::new())
+                }
+                Err(_) => ("400 Bad Request", "text/plain", String::new()),
+            },
+            ("POST", v) if v.starts_with("ban/") => {
+                let seconds = query
+                    .split('&')
+                    .find_map(|v| v.strip_prefix("seconds="))
+                    .and_then(|v| v.parse().ok())
+                    .unwrap_or(3600);
+                pool.ban(&v[4..], Duration::from_secs(seconds)).await;
+                ("204 No Content", "text/plain", String::new())
+            }
+            (
+                _,
+                "proxies" | "audit" | "usage" | "metrics" | "rotate" | "refresh" | "credentials",
+            ) => ("405 Method Not Allowed", "text/plain", String::new()),
+            (_, v) if v.starts_with("ban/") => {
+                ("405 Method Not Allowed", "text/plain", String::new())
+            }
+            _ => ("404 Not Found", "text/plain", String::new()),
+        }
+    }
+
+    fn authorized(&self, header: Option<&str>) -> bool {
+        let Some(credentials) = &self.auth else {
+            return true;
+        };
+        header
+            .and_then(|v| v.strip_prefix("Basic "))
+            .and_then(|v| BASE64_STANDARD.decode(v.trim()).ok())
+            .is_some_and(|v| {
+                v == format!("{}:{}", credentials.username(), credentials.password()).as_bytes()
+            })
+    }
+
+    fn bypassed(&self, host: &str) -> bool {
+        self.bypass.iter().any(|v| routing::matches(v, host))
+    }
+
+    /// Proxy auto-config sending everything except the bypassed hosts through the gateway at
+    /// `gateway` (`host:port`).
+    pub(crate) fn pac(&self, gateway: &str) -> String {
+        let mut pac = String::from("function FindProxyForURL(url, host) {\n");
+        pac += "  if (isPlainHostName(host)) return \"DIRECT\";\n";
+        for v in &self.bypass {
+            pac += &format!("  if (shExpMatch(host, {v:?})) return \"DIRECT\";\n");
+        }
+        pac += &format!("  return \"PROXY {gateway}; SOCKS5 {gateway}\";\n}}\n");
+        pac
+    }
+}
+
+#[derive(Serialize)]
+struct AdminEntry {
+    #[serde(flatten)]
+    entry: Entry,
+    banned: bool,
+    requests: u64,
+}
+
+#[derive(Serialize)]
+struct Health {
+    proxies: usize,
+    /// Endpoints that are neither banned nor reported unhealthy.
+    healthy: usize,
+    /// Seconds since the pool's list was fetched.
+    age: u64,
+    outdated: bool,
+}
+
+#[derive(Serialize)]
+struct Refreshed {
+    proxies: usize,
+}
+
+fn json(value: &impl Serialize) -> (&'static str, &'static str, String) {
+    (
+        "200 OK",
+        "application/json",
+        serde_json::to_string(value).unwrap(),
+    )
+}
+
+async fn read_head(client: &mut BufReader<TcpStream>) -> io::Result<Head> {
+    let invalid = || io::Error::new(io::ErrorKind::InvalidData, "malformed request head");
+    let mut line = String::new();
+    client.read_line(&mut line).await?;
+    let mut parts = line.split_whitespace();
+    let (Some(method), Some(target), Some(version)) = (parts.next(), parts.next(), parts.next())
+    else {
+        return Err(invalid());
+    };
+    let mut head = Head {
+        method: method.to_string(),
+        target: target.to_string(),
+        version: version.to_string(),
+        headers: Vec::new(),
+    };
+    loop {
+        line.clear();
+        if client.read_line(&mut line).await? == 0 || head.headers.len() > 100 {
+            return Err(invalid());
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            return Ok(head);
+        }
+        let (k, v) = line.split_once(':').ok_or_else(invalid)?;
+        head.headers.push((k.to_string(), v.trim().to_string()));
+    }
+}
+
+/// Splits `host:port`, accepting bracketed IPv6 literals.
+fn split_authority(authority: &str, default_port: u16) -> Option<(String, u16)> {
+    let (host, port) = match authority.strip_prefix('[') {
+        Some(rest) => {
+            let (host, rest) = rest.split_once(']')?;
+            (host, rest.strip_prefix(':'))
+        }
+        None => match authority.rsplit_once(':') {
+            Some((host, port)) => (host, Some(port)),
+            None => (authority, None),
+        },
+    };
+    let port = match port {
+        Some(v) => v.parse().ok()?,
+        None => default_port,
+    };
+    (!host.is_empty()).then(|| (host.to_string(), port))
+}
+
+async fn respond(
+    client: &mut BufReader<TcpStream>,
+    status: &str,
+    content_type: &str,
+    body: &str,
+) -> io::Result<()> {
+    client
+        .write_all(
+            format!(
+                "HTTP/1.1 {status}\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                body.len()
+            )
+            .as_bytes(),
+        )
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        net::SocketAddr,
+        sync::{Arc, Mutex},
+        time::Duration,
+    };
+
+    use base64::{Engine, prelude::BASE64_STANDARD};
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::{TcpListener, TcpStream},
+    };
+
+    use tokio_util::sync::CancellationToken;
+
+    use super::{Gateway, Outcome};
+    use crate::{
+        Country, Credentials, Protocol,
+        pool::{Pool, Rotation, tests::proxy},
+        stream,
+    };
+
+    async fn echo() -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                tokio::spawn(async move {
+                    let (mut r, mut w) = stream.split();
+                    let _ = tokio::io::copy(&mut r, &mut w).await;
+                });
+            }
+        });
+        port
+    }
+
+    fn free_port() -> u16 {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        listener.local_addr().unwrap().port()
+    }
+
+    async fn spawn(gateway: Gateway) -> u16 {
+        let port = free_port();
+        tokio::spawn(Arc::new(gateway).serve(("127.0.0.1", port)));
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        port
+    }
+
+    async fn gateway() -> u16 {
+        spawn(
+            Gateway::new(Arc::new(Pool::new(Vec::new())), Credentials::new("u", "p"))
+                .bypass(["127.0.0.1", "*.lan"]),
+        )
+        .await
+    }
+
+    async fn tunnel(gateway: u16, target: &str) -> (TcpStream, String) {
+        let mut client = TcpStream::connect(("127.0.0.1", gateway)).await.unwrap();
+        client
+            .write_all(format!("CONNECT {target} HTTP/1.1\r\n\r\n").as_bytes())
+            .await
+            .unwrap();
+        let mut head = Vec::new();
+        while !head.ends_with(b"\r\n\r\n") {
+            head.push(client.read_u8().await.unwrap());
+        }
+        let status = String::from_utf8_lossy(&head[..12]).into_owned();
+        (client, status)
+    }
+
+    #[tokio::test]
+    async fn tunnels() {
+        let (echo, gateway) = (echo().await, gateway().await);
+
+        let (mut client, status) = tunnel(gateway, &format!("127.0.0.1:{echo}")).await;
+        assert_eq!(status, "HTTP/1.1 200");
+        client.write_all(b"ping").await.unwrap();
+        let mut pong = [0; 4];
+        client.read_exact(&mut pong).await.unwrap();
+        assert_eq!(&pong, b"ping");
+
+        let mut via = proxy("127.0.0.1", Country::DE, 0);
+        (via.port, via.protocol) = (gateway, Protocol::Socks5);
+        let mut client = stream::connect(&via, &Credentials::new("u", "p"), "127.0.0.1", echo)
+            .await
+            .unwrap();
+        client.write_all(b"ping").await.unwrap();
+        client.read_exact(&mut pong).await.unwrap();
+        assert_eq!(&pong, b"ping");
+
+        assert_eq!(tunnel(gateway, "example.com:443").await.1, "HTTP/1.1 502");
+    }
+
+    #[tokio::test]
+    async fn pac() {
+        let gateway = gateway().await;
+        let mut client = TcpStream::connect(("127.0.0.1", gateway)).await.unwrap();
+        client
+            .write_all(b"GET /proxy.pac HTTP/1.1\r\nHost: 10.0.0.2:8080\r\n\r\n")
+            .await
+            .unwrap();
+        let mut response = String::new();
+        client.read_to_string(&mut response).await.unwrap();
+        assert!(response.contains("application/x-ns-proxy-autoconfig"));
+        assert!(response.contains(r#"shExpMatch(host, "*.lan")"#));
+        assert!(response.contains(r#"return "PROXY 10.0.0.2:8080; SOCKS5 10.0.0.2:8080";"#));
+    }
+
+    #[tokio::test]
+    async fn countries() {
+        let (echo, upstream) = (echo().await, gateway().await);
+        let mut via = proxy("127.0.0.1", Country::DE, 0);
+        (via.port, via.protocol) = (upstream, Protocol::Socks5);
+        let gateway = Gateway::new(Arc::new(Pool::new(vec![via])), Credentials::new("u", "p"));
+        let (de, jp) = (free_port(), free_port());
+        tokio::spawn(Arc::new(gateway).serve_countries([
+            (SocketAddr::from(([127, 0, 0, 1], de)), Country::DE),
+            (SocketAddr::from(([127, 0, 0, 1], jp)), Country::JP),
+        ]));
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        let target = format!("127.0.0.1:{echo}");
+        assert_eq!(tunnel(de, &target).await.1, "HTTP/1.1 200");
+        assert_eq!(tunnel(jp, &target).await.1, "HTTP/1.1 502");
+    }
+
+    #[tokio::test]
+    async fn in_flight() {
+        let (echo, upstream) = (echo().await, gateway().await);
+        let mut via = proxy("127.0.0.1", Country::DE, 0);
+        (via.port, via.protocol) = (upstream, Protocol::Socks5);
+        let pool = Pool::new(vec![via]).max_in_flight(1);
+        let gateway = spawn(Gateway::new(Arc::new(pool), Credentials::new("u", "p"))).await;
+
+        let target = format!("127.0.0.1:{echo}");
+        let (client, status) = tunnel(gateway, &target).await;
+        assert_eq!(status, "HTTP/1.1 200");
+        assert_eq!(tunnel(gateway, &target).await.1, "HTTP/1.1 502");
+        drop(client);
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(tunnel(gateway, &target).await.1, "HTTP/1.1 200");
+    }
+
+    #[tokio::test]
+    async fn accounts() {
+        let echo = echo().await;
+        let upstream = spawn(
+            Gateway::new(Arc::new(Pool::new(Vec::new())), Credentials::new("u", "p"))
+                .bypass(["127.0.0.1"])
+                .auth(Credentials::new("user", "secret")),
+        )
+        .await;
+        let mut via = proxy("127.0.0.1", Country::DE, 0);
+        (via.port, via.protocol) = (upstream, Protocol::Socks5);
+        let target = format!("127.0.0.1:{echo}");
+
+        let pool = Pool::new(vec![via.clone()]);
+        let gateway = spawn(Gateway::new(Arc::new(pool), Credentials::new("u", "p"))).await;
+        assert_eq!(tunnel(gateway, &target).await.1, "HTTP/1.1 502");
+
+        let pool =
+            Pool::new(vec![via]).accounts([Credentials::new("user", "secret")], Rotation::PerProxy);
+        let gateway = spawn(Gateway::new(Arc::new(pool), Credentials::new("u", "p"))).await;
+        assert_eq!(tunnel(gateway, &target).await.1, "HTTP/1.1 200");
+    }
+
+    #[tokio::test]
+    async fn auth() {
+        let echo = echo().await;
+        let gateway = spawn(
+            Gateway::new(Arc::new(Pool::new(Vec::new())), Credentials::new("u", "p"))
+                .bypass(["127.0.0.1"])
+                .auth(Credentials::new("user", "secret")),
+        )
+        .await;
+        let target = format!("127.0.0.1:{echo}");
+        assert_eq!(tunnel(gateway, &target).await.1, "HTTP/1.1 407");
+
+        let mut client = TcpStream::connect(("127.0.0.1", gateway)).await.unwrap();
+        client
+            .write_all(
+                format!(
+                    "CONNECT {target} HTTP/1.1\r\nProxy-Authorization: Basic dXNlcjpzZWNyZXQ=\r\n\r\n"
+                )
+                .as_bytes(),
+            )
+            .await
+            .unwrap();
+        let mut status = [0; 12];
+        client.read_exact(&mut status).await.unwrap();
+        assert_eq!(&status, b"HTTP/1.1 200");
+
+        let mut via = proxy("127.0.0.1", Country::DE, 0);
+        (via.port, via.protocol) = (gateway, Protocol::Socks5);
+        assert!(
+            stream::connect(&via, &Credentials::new("user", "secret"), "127.0.0.1", echo)
+                .await
+                .is_ok()
+        );
+        assert!(
+            stream::connect(&via, &Credentials::new("user", "wrong"), "127.0.0.1", echo)
+                .await
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn access_log() {
+        let echo = echo().await;
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let records = log.clone();
+        let gateway = spawn(
+            Gateway::new(Arc::new(Pool::new(Vec::new())), Credentials::new("u", "p"))
+                .bypass(["127.0.0.1"])
+                .access_log(move |v| records.lock().unwrap().push(v.clone())),
+        )
+        .await;
+
+        let (mut client, _) = tunnel(gateway, &format!("127.0.0.1:{echo}")).await;
+        client.write_all(b"ping").await.unwrap();
+        let mut pong = [0; 4];
+        client.read_exact(&mut pong).await.unwrap();
+        drop(client);
+        tunnel(gateway, "example.com:443").await;
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        let log = log.lock().unwrap();
+        let completed = log
+            .iter()
+            .find(|v| v.upstream.is_none() && v.sent > 0)
+            .unwrap();
+        assert_eq!(completed.target, format!("127.0.0.1:{echo}"));
+        assert_eq!((completed.sent, completed.received), (4, 4));
+        assert_eq!(completed.outcome, Outcome::Completed);
+        assert!(
+            log.iter()
+                .any(|v| v.target == "example.com:443" && v.outcome == Outcome::ConnectFailed)
+        );
+    }
+
+    async fn request(gateway: u16, request: &str) -> String {
+        let mut client = TcpStream::connect(("127.0.0.1", gateway)).await.unwrap();
+        client.write_all(request.as_bytes()).await.unwrap();
+        let mut response = String::new();
+        client.read_to_string(&mut response).await.unwrap();
+        response
+    }
+
+    /// `request` with the credentials of the admin tests.
+    async fn admin_request(gateway: u16, request: &str) -> String {
+        let authorization = format!(
+            "\r\nAuthorization: Basic {}",
+            BASE64_STANDARD.encode("admin:pw")
+        );
+        self::request(
+            gateway,
+            &request.replacen("\r\n", &format!("{authorization}\r\n"), 1),
+        )
+        .await
+    }
+
+    #[tokio::test]
+    async fn admin() {
+        let pool = Pool::new(vec![
+            proxy("de1.nordvpn.com", Country::DE, 10),
+            proxy("de2.nordvpn.com", Country::DE, 20),
+        ]);
+        let gateway = spawn(
+            Gateway::new(Arc::new(pool), Credentials::new("u", "p"))
+                .admin(true)
+                .auth(Credentials::new("admin", "pw"))
+                .refresh(move || async move {
+                    Pool::new(vec![proxy("nl1.nordvpn.com", Country::NL, 10)])
+                }),
+        )
+        .await;
+
+        let response = admin_request(
+            gateway,
+            "POST /admin/ban/de1.nordvpn.com?seconds=60 HTTP/1.1\r\n\r\n",
+        )
+        .await;
+        assert!(response.starts_with("HTTP/1.1 204"));
+        let response = admin_request(gateway, "POST /admin/rotate HTTP/1.1\r\n\r\n").await;
+        assert!(response.contains(r#""hostname":"de2.nordvpn.com""#));
+        let response = admin_request(gateway, "GET /admin/proxies HTTP/1.1\r\n\r\n").await;
+        let body = response.split("\r\n\r\n").nth(1).unwrap();
+        let entries: Vec<serde_json::Value> = serde_json::from_str(body).unwrap();
+        assert_eq!(entries[0]["banned"], true);
+        assert_eq!(entries[1]["requests"], 1);
+        let response = admin_request(gateway, "GET /admin/audit HTTP/1.1\r\n\r\n").await;
+        assert!(response.ends_with("[]"));
+        let response = admin_request(gateway, "GET /admin/usage?format=csv HTTP/1.1\r\n\r\n").await;
+        assert!(response.contains("\r\n\r\nhostname,requests,failures,bytes,last_used\n"));
+        let response = admin_request(gateway, "GET /admin/metrics HTTP/1.1\r\n\r\n").await;
+        assert!(response.contains("nord_proxy_requests_total{hostname=\"de2.nordvpn.com\"} 1\n"));
+
+        let response = admin_request(gateway, "POST /admin/refresh HTTP/1.1\r\n\r\n").await;
+        assert!(response.ends_with(r#"{"proxies":1}"#));
+        let body = r#"{"username": "new", "password": "secret"}"#;
+        let response = admin_request(
+            gateway,
+            &format!(
+                "POST /admin/credentials HTTP/1.1\r\nContent-Length: {}\r\n\r\n{body}",
+                body.len()
+            ),
+        )
+        .await;
+        assert!(response.starts_with("HTTP/1.1 204"));
+        let response = admin_request(gateway, "GET /admin/rotate HTTP/1.1\r\n\r\n").await;
+        assert!(response.starts_with("HTTP/1.1 405"));
+    }
+
+    #[tokio::test]
+    async fn admin_without_auth() {
+        let pool = Arc::new(Pool::new(vec![proxy("de1.nordvpn.com", Country::DE, 10)]));
+        let open = spawn(Gateway::new(pool.clone(), Credentials::new("u", "p")).admin(true)).await;
+        let response = request(open, "POST /admin/rotate HTTP/1.1\r\n\r\n").await;
+        assert!(response.starts_with("HTTP/1.1 404"));
+        let body = r#"{"username": "new", "password": "secret"}"#;
+        let response = request(
+            open,
+            &format!(
+                "POST /admin/credentials HTTP/1.1\r\nContent-Length: {}\r\n\r\n{body}",
+                body.len()
+            ),
+        )
+        .await;
+        assert!(response.starts_with("HTTP/1.1 404"));
+
+        let gateway = Gateway::new(pool.clone(), Credentials::new("u", "p"))
+            .admin(true)
+            .auth(Credentials::new("admin", "pw"));
+        let response = request(spawn(gateway).await, "POST /admin/rotate HTTP/1.1\r\n\r\n").await;
+        assert!(response.starts_with("HTTP/1.1 401"));
+        assert_eq!(pool.usage_report().0[0].requests, 0);
+    }
+
+    #[tokio::test]
+    async fn health() {
+        let pool = Arc::new(Pool::new(vec![proxy("de1.nordvpn.com", Country::DE, 10)]));
+        let port = spawn(Gateway::new(pool.clone(), Credentials::new("u", "p"))).await;
+        let response = request(port, "GET /readyz HTTP/1.1\r\n\r\n").await;
+        assert!(response.starts_with("HTTP/1.1 200"));
+        assert!(response.contains(r#""healthy":1"#));
+
+        pool.ban("de1.nordvpn.com", Duration::from_secs(60)).await;
+        let response = request(port, "GET /readyz HTTP/1.1\r\n\r\n").await;
+        assert!(response.starts_with("HTTP/1.1 503"));
+        let response = request(port, "GET /healthz HTTP/1.1\r\n\r\n").await;
+        assert!(response.starts_with("HTTP/1.1 200") && response.contains(r#""healthy":0"#));
+    }
+
+    #[tokio::test]
+    async fn shutdown() {
+        let (echo, token) = (echo().await, CancellationToken::new());
+        let gateway = Gateway::new(Arc::new(Pool::new(Vec::new())), Credentials::new("u", "p"))
+            .bypass(["127.0.0.1"])
+            .shutdown(token.clone());
+        let port = free_port();
+        let serve = tokio::spawn(Arc::new(gateway).serve(("127.0.0.1", port)));
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        let (client, status) = tunnel(port, &format!("127.0.0.1:{echo}")).await;
+        assert_eq!(status, "HTTP/1.1 200");
+        token.cancel();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(!serve.is_finished());
+        assert!(TcpStream::connect(("127.0.0.1", port)).await.is_err());
+
+        drop(client);
+        tokio::time::timeout(Duration::from_secs(1), serve)
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+    }
+}
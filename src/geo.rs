@@ -0,0 +1,151 @@
+//! Location and public IP address of the caller as seen by NordVPN.
+use std::net::IpAddr;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{Country, Credentials, ProxyInfo, api};
+
+const INSIGHTS_URL: &str = "https://api.nordvpn.com/v1/helpers/ips/insights";
+pub(crate) const COUNTRIES_URL: &str = "https://api.nordvpn.com/v1/servers/countries";
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Coordinates {
+    pub latitude: f64,
+    pub longitude: f64,
+}
+
+impl Coordinates {
+    pub fn new(latitude: f64, longitude: f64) -> Self {
+        Coordinates {
+            latitude,
+            longitude,
+        }
+    }
+
+    /// Geolocates the public IP address of this machine.
+    pub async fn detect() -> reqwest::Result<Self> {
+        api::get(INSIGHTS_URL)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await
+    }
+
+    /// Great-circle distance in kilometers.
+    pub fn distance_km(self, other: Coordinates) -> f64 {
+        let (a, b) = (self.latitude.to_radians(), other.latitude.to_radians());
+        let dlat = b - a;
+        let dlon = (other.longitude - self.longitude).to_radians();
+        let h = (dlat / 2.0).sin().powi(2) + a.cos() * b.cos() * (dlon / 2.0).sin().powi(2);
+        2.0 * 6371.0 * h.sqrt().asin()
+    }
+}
+
+#[derive(Deserialize)]
+struct CountryCities {
+    code: Country,
+    cities: Vec<Coordinates>,
+}
+
+/// The `n` countries with NordVPN servers closest to `origin`, nearest first, measured to the
+/// closest city of each country that has servers.
+///
+/// Pass [`Coordinates::detect`] for the countries nearest to this machine, or coordinates from
+/// any other geo lookup.
+pub async fn nearest_countries(origin: Coordinates, n: usize) -> reqwest::Result<Vec<Country>> {
+    let countries: Vec<CountryCities> = api::get(COUNTRIES_URL)
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    Ok(rank(origin, countries, n))
+}
+
+fn rank(origin: Coordinates, countries: Vec<CountryCities>, n: usize) -> Vec<Country> {
+    let mut distances = countries
+        .into_iter()
+        .filter_map(|v| {
+            let distance = v
+                .cities
+                .iter()
+                .map(|vv| origin.distance_km(*vv))
+                .min_by(f64::total_cmp)?;
+            Some((distance, v.code))
+        })
+        .collect::<Vec<_>>();
+    distances.sort_by(|a, b| a.0.total_cmp(&b.0));
+    distances.into_iter().take(n).map(|v| v.1).collect()
+}
+
+#[derive(Deserialize)]
+struct Insights {
+    ip: IpAddr,
+}
+
+/// Public IP address of this machine.
+pub async fn my_ip() -> reqwest::Result<IpAddr> {
+    ip(api::client()).await
+}
+
+/// Public IP address traffic through `proxy` leaves from, e.g. to check that it differs from
+/// [`my_ip`].
+pub async fn my_ip_via(proxy: &ProxyInfo, credentials: &Credentials) -> reqwest::Result<IpAddr> {
+    ip(reqwest::Client::builder()
+        .proxy(proxy.proxy_with(credentials))
+        .build()?)
+    .await
+}
+
+async fn ip(client: reqwest::Client) -> reqwest::Result<IpAddr> {
+    let insights: Insights = client
+        .get(INSIGHTS_URL)
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    Ok(insights.ip)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Coordinates, Insights, rank};
+    use crate::Country;
+
+    #[test]
+    fn insights() {
+        let json = r#"{"ip": "185.130.184.51", "country": "Germany", "country_code": "DE",
+            "city": "Frankfurt", "longitude": 8.6833, "latitude": 50.1167,
+            "isp": "Packethub S.A.", "protected": true}"#;
+        let insights: Insights = serde_json::from_str(json).unwrap();
+        assert_eq!(
+            insights.ip,
+            "185.130.184.51".parse::<std::net::IpAddr>().unwrap()
+        );
+        let coordinates: Coordinates = serde_json::from_str(json).unwrap();
+        assert_eq!(coordinates, Coordinates::new(50.1167, 8.6833));
+    }
+
+    #[test]
+    fn nearest() {
+        let json = r#"[
+            {"id": 81, "name": "Germany", "code": "DE", "cities": [
+                {"id": 2215709, "name": "Frankfurt", "latitude": 50.116667, "longitude": 8.683333},
+                {"id": 2181458, "name": "Berlin", "latitude": 52.516667, "longitude": 13.4}
+            ]},
+            {"id": 153, "name": "Netherlands", "code": "NL", "cities": [
+                {"id": 6076868, "name": "Amsterdam", "latitude": 52.35, "longitude": 4.916667}
+            ]},
+            {"id": 108, "name": "Japan", "code": "JP", "cities": [
+                {"id": 8287, "name": "Tokyo", "latitude": 35.685, "longitude": 139.7513889}
+            ]}
+        ]"#;
+        let potsdam = Coordinates::new(52.4, 13.06);
+        let countries = rank(potsdam, serde_json::from_str(json).unwrap(), 2);
+        assert_eq!(countries, [Country::DE, Country::NL]);
+        let km = potsdam.distance_km(Coordinates::new(52.516667, 13.4));
+        assert!((20.0..30.0).contains(&km));
+    }
+}
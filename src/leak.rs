@@ -0,0 +1,133 @@
+//! DNS leak test: which resolvers look up hostnames requested through an endpoint.
+//!
+//! Uses the canary domains of bash.ws, which records the resolvers asking for them.
+use std::{net::IpAddr, time::Duration};
+
+use serde::Deserialize;
+
+use crate::{Credentials, ProxyInfo, api};
+
+const ID_URL: &str = "https://bash.ws/id";
+/// Canary lookups per path, so that every resolver of a pool has a chance to show up.
+const LOOKUPS: usize = 5;
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct Resolver {
+    pub ip: IpAddr,
+    #[serde(default, rename = "country_name")]
+    pub country: String,
+    #[serde(default)]
+    pub asn: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Leak {
+    /// Resolvers that looked up the hostnames requested through the endpoint.
+    pub tunnel: Vec<Resolver>,
+    /// Resolvers of this machine.
+    pub local: Vec<Resolver>,
+}
+
+impl Leak {
+    /// Whether a resolver of this machine saw a hostname requested through the endpoint, as
+    /// with `socks5://` instead of `socks5h://`.
+    pub fn leaking(&self) -> bool {
+        self.tunnel
+            .iter()
+            .any(|v| self.local.iter().any(|vv| vv.ip == v.ip))
+    }
+}
+
+#[derive(Deserialize)]
+struct Observation {
+    #[serde(rename = "type")]
+    kind: String,
+    #[serde(flatten)]
+    resolver: Option<Resolver>,
+}
+
+/// Requests canary hostnames through `proxy` and looks up others locally, comparing the
+/// resolvers that saw them.
+pub async fn leak_test(proxy: &ProxyInfo, credentials: &Credentials) -> reqwest::Result<Leak> {
+    leak_test_client(
+        &reqwest::Client::builder()
+            .proxy(proxy.proxy_with(credentials))
+            .timeout(Duration::from_secs(10))
+            .build()?,
+    )
+    .await
+}
+
+/// Like [`leak_test`] for a client configured elsewhere, e.g. with a `socks5://` proxy.
+pub async fn leak_test_client(client: &reqwest::Client) -> reqwest::Result<Leak> {
+    let direct = api::client();
+    let id = canary(&direct).await?;
+    for i in 0..LOOKUPS {
+        // The canaries don't serve anything, only the lookup matters.
+        let _ = client.get(format!("https://{i}.{id}.bash.ws")).send().await;
+    }
+    let tunnel = resolvers(&direct, &id).await?;
+
+    let id = canary(&direct).await?;
+    for i in 0..LOOKUPS {
+        let _ = tokio::net::lookup_host((format!("{i}.{id}.bash.ws"), 443)).await;
+    }
+    let local = resolvers(&direct, &id).await?;
+    Ok(Leak { tunnel, local })
+}
+
+async fn canary(client: &reqwest::Client) -> reqwest::Result<String> {
+    let id = client.get(ID_URL).send().await?.error_for_status()?;
+    Ok(id.text().await?.trim().to_string())
+}
+
+async fn resolvers(client: &reqwest::Client, id: &str) -> reqwest::Result<Vec<Resolver>> {
+    let observations: Vec<Observation> = client
+        .get(format!("https://bash.ws/dnsleak/test/{id}?json"))
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    Ok(dns(observations))
+}
+
+fn dns(observations: Vec<Observation>) -> Vec<Resolver> {
+    let mut resolvers = Vec::<Resolver>::new();
+    for v in observations {
+        if v.kind == "dns"
+            && let Some(v) = v.resolver
+            && !resolvers.contains(&v)
+        {
+            resolvers.push(v);
+        }
+    }
+    resolvers
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Leak, Observation, dns};
+
+    #[test]
+    fn observations() {
+        let json = r#"[
+            {"ip": "185.187.168.10", "country": "DE", "country_name": "Germany", "asn": "AS9009", "type": "ip"},
+            {"ip": "103.86.96.100", "country": "NL", "country_name": "Netherlands", "asn": "AS207137", "type": "dns"},
+            {"ip": "103.86.96.100", "country": "NL", "country_name": "Netherlands", "asn": "AS207137", "type": "dns"},
+            {"ip": "84.116.46.21", "country": "AT", "country_name": "Austria", "asn": "AS6830", "type": "dns"},
+            {"ip": "DNS may be leaking.", "country": "", "asn": "", "type": "conclusion"}
+        ]"#;
+        let resolvers = dns(serde_json::from_str::<Vec<Observation>>(json).unwrap());
+        assert_eq!(resolvers.len(), 2);
+        assert_eq!(resolvers[0].country, "Netherlands");
+
+        let mut leak = Leak {
+            tunnel: resolvers.clone(),
+            local: resolvers[1..].to_vec(),
+        };
+        assert!(leak.leaking());
+        leak.tunnel.truncate(1);
+        assert!(!leak.leaking());
+    }
+}
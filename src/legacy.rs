@@ -0,0 +1,132 @@
+//! The legacy `https://api.nordvpn.com/server` endpoint, mapped onto the `v1/servers` schema.
+use std::collections::BTreeMap;
+
+use serde::Deserialize;
+
+use crate::{
+    City, Country, Status, Technology, api,
+    structure::{self, Groups, Locations, Metadata, Pivot, Root, Services, Technologies},
+};
+
+const URL: &str = "https://api.nordvpn.com/server";
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct Server {
+    pub domain: String,
+    pub flag: Country,
+    pub load: u32,
+    #[serde(default)]
+    pub categories: Vec<Category>,
+    #[serde(default)]
+    pub features: BTreeMap<String, bool>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct Category {
+    pub name: String,
+}
+
+pub async fn get_info() -> reqwest::Result<Vec<Root>> {
+    let response = api::get(URL).send().await?;
+    let json: Vec<Server> = response.json().await?;
+    Ok(json.into_iter().map(Root::from).collect())
+}
+
+fn group(category: &str) -> String {
+    match category {
+        "Standard VPN servers" => "legacy_standard".to_string(),
+        "Obfuscated Servers" => "legacy_obfuscated_servers".to_string(),
+        v => format!("legacy_{}", v.to_lowercase().replace(' ', "_")),
+    }
+}
+
+impl From<Server> for Root {
+    fn from(v: Server) -> Self {
+        let technologies = v
+            .features
+            .iter()
+            .filter(|(_, enabled)| **enabled)
+            .map(|(identifier, _)| {
+                let identifier = Technology::from(identifier.clone());
+                Technologies {
+                    metadata: match identifier {
+                        Technology::ProxySsl | Technology::ProxySslCybersec => vec![Metadata {
+                            name: "proxy_hostname".to_string(),
+                            value: v.domain.clone(),
+                        }],
+                        _ => Vec::new(),
+                    },
+                    identifier,
+                    pivot: Pivot {
+                        status: Status::Online,
+                    },
+                }
+            })
+            .collect::<Vec<_>>();
+        let mut services = vec![Services {
+            identifier: "vpn".to_string(),
+        }];
+        if technologies.iter().any(|v| v.identifier.is_proxy()) {
+            services.push(Services {
+                identifier: "proxy".to_string(),
+            });
+        }
+        Root {
+            status: Status::Online,
+            services,
+            groups: v
+                .categories
+                .iter()
+                .map(|v| Groups {
+                    identifier: group(&v.name),
+                })
+                .collect(),
+            hostname: v.domain,
+            load: v.load,
+            locations: vec![Locations {
+                country: structure::Country {
+                    code: v.flag,
+                    city: structure::City {
+                        name: City::Other(String::new()),
+                        hub_score: 0,
+                    },
+                },
+            }],
+            technologies,
+        }
+    }
+}
+
+#[cfg(all(test, feature = "socks5", feature = "https-proxy"))]
+mod tests {
+    use super::Server;
+    use crate::{Country, Proxy, ProxyTrait, Socks5, Technology, structure::Root};
+
+    #[test]
+    fn mapping() {
+        let json = r#"[{
+            "id": 1, "name": "Germany #1057", "domain": "de1057.nordvpn.com", "flag": "DE",
+            "country": "Germany", "load": 17,
+            "categories": [{"name": "Standard VPN servers"}, {"name": "P2P"}],
+            "features": {"socks": true, "proxy": false, "proxy_ssl": true, "openvpn_udp": true}
+        }]"#;
+        let roots = serde_json::from_str::<Vec<Server>>(json)
+            .unwrap()
+            .into_iter()
+            .map(Root::from)
+            .collect::<Vec<_>>();
+        assert_eq!(roots[0].groups[1].identifier, "legacy_p2p");
+
+        let https =
+            Proxy::from_roots(roots.clone(), &Technology::ProxySsl, false).proxies("user", "pass");
+        assert_eq!(https.len(), 1);
+        assert_eq!(https[0].hostname, "de1057.nordvpn.com");
+        assert_eq!(https[0].country, Country::DE);
+        assert_eq!(
+            Socks5::from_roots(roots, false)
+                .proxies("user", "pass")
+                .len(),
+            1
+        );
+    }
+}
@@ -6,164 +6,1789 @@
 //! ## Usage
 //!
 //! ```rust
-//! use nord_proxy::{Proxy, Socks5, ProxyTrait};
+//! use nord_proxy::{Credentials, Proxy, Socks5, ProxyTrait};
+//!
+//! # async fn run() {
+//! // NordVPN service credentials (not the account login)
+//! let credentials = Credentials::new("username", "password");
 //!
 //! // SOCKS5 proxies
 //! let socks5 = Socks5::new().await;
-//! let socks5_proxies = socks5.proxies("username", "password");
+//! let socks5_proxies = socks5.proxies_with(&credentials);
 //!
 //! // HTTP / HTTPS proxies
 //! let proxy = Proxy::new().await;
-//! let http_proxies = proxy.proxies("username", "password");
+//! let http_proxies = proxy.proxies_with(&credentials);
 //!
 //! // Example: use with reqwest
 //! let proxy_info = &http_proxies[0];
 //! let client = reqwest::Client::builder()
-//!     .proxy(reqwest::Proxy::all(proxy_info.proxy.clone()).unwrap())
+//!     .proxy(proxy_info.proxy.clone())
 //!     .build().unwrap();
+//! # }
 //! ```
-use serde::{Deserialize, Serialize};
+use std::{
+    borrow::Borrow,
+    collections::{HashMap, VecDeque},
+    convert::Infallible,
+    fmt, io,
+    net::{SocketAddr, ToSocketAddrs},
+    str::FromStr,
+    time::{Duration, SystemTime},
+};
+
+use rand::seq::SliceRandom;
+use serde::{Deserialize, Serialize, de::value::StrDeserializer};
+
+use crate::structure::Root;
+#[cfg(feature = "https-proxy")]
+use crate::structure::Technologies;
+pub use crate::{
+    api::{set_api_proxy, with_api_base},
+    builder::{Builder, ParseMode},
+    credentials::{Credentials, SharedCredentials, SocksAuth},
+    determinism::{is_deterministic, set_deterministic, with_deterministic},
+    dial::{Resolve, reset_resolver, set_resolver},
+    fleet::Fleet,
+    shared::SharedProxies,
+    view::FilteredProxies,
+};
 
-use crate::structure::{Root, Technologies};
+#[cfg(not(any(feature = "socks5", feature = "https-proxy")))]
+compile_error!("enable at least one of the `socks5` and `https-proxy` features");
 
+#[macro_use]
+mod diag;
+
+pub mod account;
+mod api;
+mod builder;
+mod credentials;
+mod determinism;
+mod dial;
+#[cfg(feature = "embedded-fallback")]
+mod embedded;
+pub mod export;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+mod fleet;
+#[cfg(feature = "gateway")]
+pub mod gateway;
+pub mod geo;
+pub mod leak;
+mod legacy;
+#[cfg(feature = "middleware")]
+pub mod middleware;
+#[cfg(test)]
+mod mock;
+pub mod monitor;
+pub mod pool;
+#[cfg(feature = "python")]
+mod python;
+pub mod routing;
+#[cfg(feature = "server")]
+pub mod server;
+mod shared;
+pub mod snapshot;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+#[cfg(feature = "stream")]
+pub mod stream;
 mod structure;
+mod view;
+pub mod webhook;
+
+async fn get_info(s: &str) -> io::Result<Vec<Root>> {
+    let response = api::get(s).send().await.map_err(io::Error::other)?;
+    parse(response.bytes().await.map_err(io::Error::other)?.to_vec())
+}
+
+/// The payload runs into megabytes with `limit=0`, where simd-json parses it several times
+/// faster; it works in place, hence the owned buffer.
+#[cfg(feature = "simd-json")]
+fn parse(mut body: Vec<u8>) -> io::Result<Vec<Root>> {
+    simd_json::serde::from_slice(&mut body)
+        .map_err(|v| io::Error::new(io::ErrorKind::InvalidData, v))
+}
 
-async fn get_info(s: &str) -> Vec<Root> {
-    let client = reqwest::Client::new();
-    let response = client.get(s).send().await.unwrap();
-    let json: Vec<Root> = response.json().await.unwrap();
-    json
+#[cfg(not(feature = "simd-json"))]
+fn parse(body: Vec<u8>) -> io::Result<Vec<Root>> {
+    Ok(serde_json::from_slice(&body)?)
 }
 
+#[cfg(feature = "socks5")]
+#[derive(Debug, Clone)]
 pub struct Socks5 {
-    data: Vec<Root>,
+    /// Hostnames of all servers back to back, indexed by [`Server::hostname`].
+    hostnames: String,
+    data: Vec<Server>,
+    warnings: Vec<Warning>,
+    fetched_at: SystemTime,
+    /// Port overrides by country, see [`Socks5::with_country_port`].
+    ports: HashMap<Country, u16>,
+}
+
+/// A SOCKS5 server, reduced to what [`ProxyInfo`] needs.
+///
+/// Services keep thousands of these around, so the hostname is a range into
+/// [`Socks5::hostnames`] instead of an allocation of its own.
+#[cfg(feature = "socks5")]
+#[derive(Debug, Clone)]
+struct Server {
+    hostname: (u32, u8),
+    load: Load,
+    country: Country,
+    city: City,
+    status: Status,
+    groups: ServerGroups,
+    /// From the technology's metadata, [`SOCKS_PORT`] if it lists none.
+    port: u16,
 }
 
+/// The port the SOCKS5 servers listen on unless their metadata says otherwise.
+#[cfg(feature = "socks5")]
+const SOCKS_PORT: u16 = 1080;
+
+/// The ports the HTTPS proxies listen on unless their metadata says otherwise, preferred first.
+#[cfg(feature = "https-proxy")]
+const HTTPS_PORTS: [u16; 2] = [89, 443];
+
+/// Endpoints probed at the same time by `probe_ports`.
+#[cfg(any(feature = "socks5", feature = "https-proxy"))]
+const PROBE_CONCURRENCY: usize = 64;
+
+#[cfg(feature = "https-proxy")]
+#[derive(Debug, Clone)]
 pub struct Proxy {
-    data: Vec<(u32, Country, City, Technologies)>,
+    data: Vec<Endpoint>,
+    /// Overrides the ports of all endpoints, see [`Proxy::with_port`].
+    port: Option<u16>,
+    warnings: Vec<Warning>,
+    fetched_at: SystemTime,
+}
+
+/// An HTTPS endpoint of a server, reduced to what [`ProxyInfo`] needs.
+#[cfg(feature = "https-proxy")]
+#[derive(Debug, Clone)]
+struct Endpoint {
+    load: Load,
+    country: Country,
+    city: City,
+    hostname: String,
+    inferred: bool,
+    status: Status,
+    groups: ServerGroups,
+    /// From the technology's metadata, [`HTTPS_PORTS`] if it lists none; the first one is used.
+    ports: Vec<u16>,
+}
+
+#[cfg(feature = "https-proxy")]
+impl Endpoint {
+    fn new(server: &Root, technology: &Technologies) -> Option<Self> {
+        let c = server.location()?;
+        let (hostname, inferred) = proxy_hostname(server, technology);
+        Some(Endpoint {
+            load: Load::new(server.load),
+            country: c.code.clone(),
+            city: c.city.name.clone(),
+            hostname: hostname.to_string(),
+            inferred,
+            status: server.status.clone(),
+            groups: ServerGroups::new(&server.groups),
+            ports: https_ports(technology),
+        })
+    }
+
+    /// Like [`Endpoint::new`], moving the fields out of `server` instead of cloning them.
+    fn from_root(server: Root, technology: usize) -> Option<Self> {
+        let Root {
+            status,
+            groups,
+            hostname,
+            load,
+            locations,
+            mut technologies,
+            ..
+        } = server;
+        let location = locations.into_iter().next()?.country;
+        let technology = technologies.swap_remove(technology);
+        let ports = https_ports(&technology);
+        let (hostname, inferred) = match technology
+            .metadata
+            .into_iter()
+            .find(|v| v.name == "proxy_hostname")
+        {
+            Some(v) => (v.value, false),
+            None => (hostname, true),
+        };
+        Some(Endpoint {
+            load: Load::new(load),
+            country: location.code,
+            city: location.city.name,
+            hostname,
+            inferred,
+            status,
+            groups: ServerGroups::new(&groups),
+            ports,
+        })
+    }
+
+    fn port(&self) -> u16 {
+        self.ports.first().copied().unwrap_or(HTTPS_PORTS[0])
+    }
 }
 
+#[cfg(feature = "https-proxy")]
 impl Proxy {
+    pub(crate) const FILTER: &str = "filters[servers_services][identifier]=proxy";
+
     pub async fn new() -> Self {
-        let url = "https://api.nordvpn.com/v1/servers?filters[servers_services][identifier]=proxy&limit=0";
-        Proxy {
-            data: get_info(url)
-                .await
-                .into_iter()
-                .filter(|v| {
-                    v.status.to_lowercase() == "online"
-                        && v.services.iter().any(|v| v.identifier == "proxy")
-                })
-                .flat_map(|v| {
-                    v.technologies
-                        .into_iter()
-                        .filter(|v| v.identifier == "proxy_ssl")
-                        .map(|vv| {
-                            (
-                                v.load,
-                                v.locations.first().unwrap().country.code.clone(),
-                                v.locations.first().unwrap().country.city.name.clone(),
-                                vv,
-                            )
-                        })
-                        .collect::<Vec<_>>()
-                })
-                .collect(),
+        Self::builder().build().await
+    }
+
+    pub fn builder() -> Builder<Self> {
+        Builder::new()
+    }
+
+    /// Fetches the endpoint of the server `name`, e.g. `us9591` as referenced by support, for
+    /// when it isn't in a list at hand; see [`ProxyTrait::get`].
+    pub async fn server(name: &str, credentials: &Credentials) -> Option<ProxyInfo> {
+        let list = Self::builder().hostnames([server_name(name)]).build().await;
+        list.proxies_with(credentials).into_iter().next()
+    }
+
+    /// Parses a `v1/servers` response, e.g. a recorded one, the way [`Proxy::new`] does.
+    pub fn from_json_str(json: &str) -> serde_json::Result<Self> {
+        Ok(Self::from_roots(
+            serde_json::from_str(json)?,
+            &Technology::ProxySsl,
+            false,
+        ))
+    }
+
+    /// Keeps the entries of `technology`, i.e. [`Technology::ProxySsl`] or
+    /// [`Technology::ProxySslCybersec`].
+    pub(crate) fn from_roots(
+        data: Vec<Root>,
+        technology: &Technology,
+        include_maintenance: bool,
+    ) -> Self {
+        let mut proxy = Proxy {
+            data: Vec::with_capacity(data.len()),
+            port: None,
+            warnings: Vec::new(),
+            fetched_at: SystemTime::now(),
+        };
+        for v in data {
+            if !v.status.usable(include_maintenance)
+                || !v.services.iter().any(|v| v.identifier == "proxy")
+            {
+                continue;
+            }
+            if let Some(problem) = problem(&v) {
+                proxy.warnings.push(Warning::new(v.hostname, problem));
+                continue;
+            }
+            if let Some(i) = v
+                .technologies
+                .iter()
+                .position(|vv| &vv.identifier == technology)
+            {
+                proxy.data.extend(Endpoint::from_root(v, i));
+            }
         }
+        proxy
+    }
+
+    /// Hostnames of the servers in [`Proxy::warnings`].
+    pub fn invalid(&self) -> Vec<&str> {
+        invalid(&self.warnings)
+    }
+
+    /// Servers of the response left out for failing validation, and why.
+    pub fn warnings(&self) -> &[Warning] {
+        &self.warnings
+    }
+
+    pub fn fetched_at(&self) -> SystemTime {
+        self.fetched_at
+    }
+
+    /// Time since the list was fetched.
+    pub fn age(&self) -> Duration {
+        self.fetched_at.elapsed().unwrap_or_default()
+    }
+
+    /// Port the HTTPS proxies are reached on.
+    /// The port set with [`Proxy::with_port`], which the endpoints are reached on instead of
+    /// their own.
+    pub fn port(&self) -> Option<u16> {
+        self.port
+    }
+
+    /// Reaches the HTTPS proxies on `port` instead of their own, e.g. 443 where 89 is blocked;
+    /// see [`Builder::probe_ports`] to detect it per endpoint.
+    pub fn with_port(mut self, port: u16) -> Self {
+        self.port = Some(port);
+        self
+    }
+
+    /// Keeps the ports of every endpoint that accept connections, dropping the endpoints
+    /// without any; leaves the list as it is if no endpoint is reachable at all.
+    pub(crate) async fn probe_ports(&mut self, timeout: Duration) {
+        let endpoints = self
+            .data
+            .iter()
+            .map(|v| (v.hostname.clone(), v.ports.clone()))
+            .collect();
+        let reachable = monitor::reachable_ports_of(endpoints, timeout, PROBE_CONCURRENCY).await;
+        if reachable.iter().all(Vec::is_empty) {
+            debug!("no HTTPS proxy is reachable, keeping their advertised ports");
+            return;
+        }
+        for (v, ports) in self.data.iter_mut().zip(reachable) {
+            v.ports = ports;
+        }
+        self.data.retain(|v| {
+            if v.ports.is_empty() {
+                debug!("dropping {}, none of its ports is reachable", v.hostname);
+            }
+            !v.ports.is_empty()
+        });
     }
 }
 
+#[cfg(feature = "https-proxy")]
 impl ProxyTrait for Proxy {
-    fn proxies(&self, username: &str, password: &str) -> Vec<ProxyInfo> {
+    fn proxies_with(&self, credentials: &Credentials) -> Vec<ProxyInfo> {
         self.data
             .iter()
-            .map(|v| ProxyInfo {
-                load: v.0,
-                country: v.1.clone(),
-                city: v.2.clone(),
-                proxy: reqwest::Proxy::https(format!(
-                    "https://{}:89",
-                    v.3.metadata
-                        .iter()
-                        .find(|v| v.name == "proxy_hostname")
-                        .unwrap()
-                        .value
-                ))
-                .unwrap()
-                .basic_auth(username, password),
-            })
+            .filter_map(|v| https_info(v, self.port.unwrap_or(v.port()), credentials))
             .collect()
     }
 }
+
+#[cfg(feature = "socks5")]
 impl Socks5 {
+    pub(crate) const FILTER: &str = "filters[servers_technologies][identifier]=socks";
+
     pub async fn new() -> Self {
-        let url = "https://api.nordvpn.com/v1/servers?filters[servers_technologies][identifier]=socks&limit=0";
-        Socks5 {
-            data: get_info(url)
-                .await
-                .into_iter()
-                .filter(|v| {
-                    v.status == "online"
-                        && v.technologies
-                            .iter()
-                            .any(|v| v.pivot.status == "online" && v.identifier == "socks")
+        Self::builder().build().await
+    }
+
+    pub fn builder() -> Builder<Self> {
+        Builder::new()
+    }
+
+    /// Fetches the endpoint of the server `name`, e.g. `us9591` as referenced by support, for
+    /// when it isn't in a list at hand; see [`ProxyTrait::get`].
+    pub async fn server(name: &str, credentials: &Credentials) -> Option<ProxyInfo> {
+        let list = Self::builder().hostnames([server_name(name)]).build().await;
+        list.proxies_with(credentials).into_iter().next()
+    }
+
+    /// Parses a `v1/servers` response, e.g. a recorded one, the way [`Socks5::new`] does.
+    pub fn from_json_str(json: &str) -> serde_json::Result<Self> {
+        Ok(Self::from_roots(serde_json::from_str(json)?, false))
+    }
+
+    pub(crate) fn from_roots(data: Vec<Root>, include_maintenance: bool) -> Self {
+        let (data, warnings) = valid(data.into_iter().filter(|v| {
+            v.status.usable(include_maintenance)
+                && v.technologies.iter().any(|v| {
+                    v.pivot.status.usable(include_maintenance) && v.identifier == Technology::Socks
                 })
-                .collect(),
+        }));
+        let mut socks = Socks5 {
+            hostnames: String::new(),
+            data: Vec::with_capacity(data.len()),
+            warnings,
+            fetched_at: SystemTime::now(),
+            ports: HashMap::new(),
+        };
+        for v in data {
+            // Hostnames are DNS names, so they usually fit in the 255 bytes a `u8` length allows.
+            let Ok(len) = u8::try_from(v.hostname.len()) else {
+                socks
+                    .warnings
+                    .push(Warning::new(v.hostname, Problem::LongHostname));
+                continue;
+            };
+            let port = v
+                .technologies
+                .iter()
+                .find(|v| v.identifier == Technology::Socks)
+                .map_or(SOCKS_PORT, socks_port);
+            let Some(location) = v.locations.into_iter().next() else {
+                continue;
+            };
+            socks.data.push(Server {
+                hostname: (socks.hostnames.len() as u32, len),
+                load: Load::new(v.load),
+                country: location.country.code,
+                city: location.country.city.name,
+                status: v.status,
+                groups: ServerGroups::new(&v.groups),
+                port,
+            });
+            socks.hostnames += &v.hostname;
+        }
+        socks.hostnames.shrink_to_fit();
+        socks
+    }
+
+    fn hostname(&self, server: &Server) -> &str {
+        let (start, len) = server.hostname;
+        &self.hostnames[start as usize..start as usize + len as usize]
+    }
+
+    /// Keeps the first of its advertised port and [`SOCKS_PORT`] that every server accepts
+    /// connections on, dropping the servers without any; leaves the list as it is if no server
+    /// is reachable at all.
+    pub(crate) async fn probe_ports(&mut self, timeout: Duration) {
+        let servers = self
+            .data
+            .iter()
+            .map(|v| {
+                let mut ports = vec![v.port, SOCKS_PORT];
+                ports.dedup();
+                (self.hostname(v).to_string(), ports)
+            })
+            .collect();
+        let reachable = monitor::reachable_ports_of(servers, timeout, PROBE_CONCURRENCY).await;
+        if reachable.iter().all(Vec::is_empty) {
+            debug!("no SOCKS5 server is reachable, keeping their advertised ports");
+            return;
+        }
+        let mut reachable = reachable.into_iter();
+        let mut dropped = Vec::new();
+        self.data
+            .retain_mut(|v| match reachable.next().unwrap_or_default().first() {
+                Some(port) => {
+                    v.port = *port;
+                    true
+                }
+                None => {
+                    dropped.push(v.hostname);
+                    false
+                }
+            });
+        for (start, len) in dropped {
+            debug!(
+                "dropping {}, none of its ports is reachable",
+                &self.hostnames[start as usize..start as usize + len as usize]
+            );
         }
     }
+
+    /// Uses `port` for the servers in `country`, for networks that rewrite the port the servers
+    /// listen on.
+    pub fn with_country_port(mut self, country: Country, port: u16) -> Self {
+        self.ports.insert(country, port);
+        self
+    }
+
+    /// Hostnames of the servers in [`Socks5::warnings`].
+    pub fn invalid(&self) -> Vec<&str> {
+        invalid(&self.warnings)
+    }
+
+    /// Servers of the response left out for failing validation, and why.
+    pub fn warnings(&self) -> &[Warning] {
+        &self.warnings
+    }
+
+    pub fn fetched_at(&self) -> SystemTime {
+        self.fetched_at
+    }
+
+    /// Time since the list was fetched.
+    pub fn age(&self) -> Duration {
+        self.fetched_at.elapsed().unwrap_or_default()
+    }
 }
 
+#[cfg(feature = "socks5")]
 impl ProxyTrait for Socks5 {
-    fn proxies(&self, username: &str, password: &str) -> Vec<ProxyInfo> {
+    fn proxies_with(&self, credentials: &Credentials) -> Vec<ProxyInfo> {
         self.data
             .iter()
-            .map(|v| {
-                let c = v.locations.first().unwrap();
-                ProxyInfo {
-                    load: v.load,
-                    city: c.country.city.name.clone(),
-                    country: c.country.code.clone(),
-                    proxy: reqwest::Proxy::all(format!(
-                        "socks5h://{username}:{password}@{}:1080",
-                        v.hostname
-                    ))
-                    .unwrap(),
-                }
+            .filter_map(|v| {
+                let info = socks5_info(
+                    self.hostname(v),
+                    v.load,
+                    &v.country,
+                    &v.city,
+                    &v.status,
+                    v.groups,
+                    credentials,
+                )?;
+                Some(
+                    match self.ports.get(&v.country).copied().unwrap_or(v.port) {
+                        SOCKS_PORT => info,
+                        port => info.with_port(port),
+                    },
+                )
             })
             .collect()
     }
 }
 
+/// Servers from NordVPN's dedicated IP group, optionally narrowed down to the one assigned to
+/// a subscription.
+#[derive(Debug, Clone)]
+pub struct DedicatedIp {
+    data: Vec<Root>,
+    warnings: Vec<Warning>,
+    include_maintenance: bool,
+    fetched_at: SystemTime,
+}
+
+impl DedicatedIp {
+    pub(crate) const FILTER: &str = "filters[servers_groups][identifier]=legacy_dedicated_ip";
+
+    pub async fn new() -> Self {
+        Self::builder().build().await
+    }
+
+    pub fn builder() -> Builder<Self> {
+        Builder::new()
+    }
+
+    /// Parses a `v1/servers` response, e.g. a recorded one, the way [`DedicatedIp::new`] does.
+    pub fn from_json_str(json: &str) -> serde_json::Result<Self> {
+        Ok(Self::from_roots(serde_json::from_str(json)?, false))
+    }
+
+    pub(crate) fn from_roots(data: Vec<Root>, include_maintenance: bool) -> Self {
+        let (data, warnings) = valid(data.into_iter().filter(|v| {
+            v.status.usable(include_maintenance)
+                && v.groups
+                    .iter()
+                    .any(|v| v.identifier == "legacy_dedicated_ip")
+        }));
+        DedicatedIp {
+            data,
+            warnings,
+            include_maintenance,
+            fetched_at: SystemTime::now(),
+        }
+    }
+
+    /// Hostnames of the servers in [`DedicatedIp::warnings`].
+    pub fn invalid(&self) -> Vec<&str> {
+        invalid(&self.warnings)
+    }
+
+    /// Servers of the response left out for failing validation, and why.
+    pub fn warnings(&self) -> &[Warning] {
+        &self.warnings
+    }
+
+    pub fn fetched_at(&self) -> SystemTime {
+        self.fetched_at
+    }
+
+    /// Time since the list was fetched.
+    pub fn age(&self) -> Duration {
+        self.fetched_at.elapsed().unwrap_or_default()
+    }
+
+    /// Keeps only the server with the given hostname, e.g. `us1234.nordvpn.com` or `us1234`.
+    pub fn assigned(mut self, hostname: &str) -> Self {
+        self.data
+            .retain(|v| v.hostname == hostname || v.hostname.split('.').next() == Some(hostname));
+        self
+    }
+}
+
+impl ProxyTrait for DedicatedIp {
+    fn proxies_with(&self, credentials: &Credentials) -> Vec<ProxyInfo> {
+        self.data
+            .iter()
+            .flat_map(|v| {
+                v.technologies
+                    .iter()
+                    .filter(|vv| vv.pivot.status.usable(self.include_maintenance))
+                    .filter_map(move |vv| match vv.identifier {
+                        #[cfg(feature = "socks5")]
+                        Technology::Socks => {
+                            let c = v.location()?;
+                            let info = socks5_info(
+                                &v.hostname,
+                                Load::new(v.load),
+                                &c.code,
+                                &c.city.name,
+                                &v.status,
+                                ServerGroups::new(&v.groups),
+                                credentials,
+                            )?;
+                            Some(match socks_port(vv) {
+                                SOCKS_PORT => info,
+                                port => info.with_port(port),
+                            })
+                        }
+                        #[cfg(feature = "https-proxy")]
+                        Technology::ProxySsl => {
+                            let endpoint = Endpoint::new(v, vv)?;
+                            https_info(&endpoint, endpoint.port(), credentials)
+                        }
+                        _ => None,
+                    })
+            })
+            .collect()
+    }
+}
+
+/// A server of an API response that failed validation and was left out.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Warning {
+    pub hostname: String,
+    pub problem: Problem,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Problem {
+    EmptyHostname,
+    /// Not usable as the host of a proxy URL, e.g. with spaces in it.
+    InvalidHostname,
+    /// Longer than the 255 bytes a [`Socks5`] list keeps per hostname.
+    LongHostname,
+    /// A load outside of 0–100.
+    Load(u32),
+    NoLocation,
+}
+
+impl Warning {
+    fn new(hostname: String, problem: Problem) -> Self {
+        Warning { hostname, problem }
+    }
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.problem {
+            Problem::EmptyHostname => write!(f, "server without a hostname"),
+            Problem::InvalidHostname => write!(f, "{}: invalid hostname", self.hostname),
+            Problem::LongHostname => write!(f, "{}: hostname too long", self.hostname),
+            Problem::Load(v) => write!(f, "{}: implausible load {v}", self.hostname),
+            Problem::NoLocation => write!(f, "{}: no location", self.hostname),
+        }
+    }
+}
+
+/// What makes `server` unusable, if anything.
+fn problem(server: &Root) -> Option<Problem> {
+    if server.hostname.is_empty() {
+        Some(Problem::EmptyHostname)
+    } else if !valid_host(&server.hostname) {
+        Some(Problem::InvalidHostname)
+    } else if server.load > 100 {
+        Some(Problem::Load(server.load))
+    } else if server.location().is_none() {
+        Some(Problem::NoLocation)
+    } else {
+        None
+    }
+}
+
+/// Whether `hostname` makes up the host of a URL as is.
+fn valid_host(hostname: &str) -> bool {
+    reqwest::Url::parse(&format!("https://{hostname}")).is_ok_and(|v| {
+        v.host_str()
+            .is_some_and(|v| v.eq_ignore_ascii_case(hostname))
+    })
+}
+
+/// Splits off the servers failing validation.
+fn valid(data: impl Iterator<Item = Root>) -> (Vec<Root>, Vec<Warning>) {
+    let mut warnings = Vec::new();
+    let data = data
+        .filter_map(|v| match problem(&v) {
+            Some(problem) => {
+                warnings.push(Warning::new(v.hostname, problem));
+                None
+            }
+            None => Some(v),
+        })
+        .collect();
+    (data, warnings)
+}
+
+fn invalid(warnings: &[Warning]) -> Vec<&str> {
+    warnings.iter().map(|v| v.hostname.as_str()).collect()
+}
+
+/// Hostname of `technology`, falling back to the server's own one; `true` if it fell back.
+#[cfg(feature = "https-proxy")]
+fn proxy_hostname<'a>(server: &'a Root, technology: &'a Technologies) -> (&'a str, bool) {
+    match technology
+        .metadata
+        .iter()
+        .find(|v| v.name == "proxy_hostname")
+    {
+        Some(v) => (&v.value, false),
+        None => (&server.hostname, true),
+    }
+}
+
+/// The ports listed in the metadata of an HTTPS proxy technology, [`HTTPS_PORTS`] if none is.
+#[cfg(feature = "https-proxy")]
+fn https_ports(technology: &Technologies) -> Vec<u16> {
+    let ports = technology
+        .metadata
+        .iter()
+        .filter(|v| v.name == "port")
+        .flat_map(|v| v.value.split(','))
+        .filter_map(|v| v.trim().parse().ok())
+        .collect::<Vec<_>>();
+    match ports.is_empty() {
+        true => HTTPS_PORTS.to_vec(),
+        false => ports,
+    }
+}
+
+/// The port listed in the metadata of a SOCKS5 technology, [`SOCKS_PORT`] if none is.
+#[cfg(feature = "socks5")]
+fn socks_port(technology: &structure::Technologies) -> u16 {
+    technology
+        .metadata
+        .iter()
+        .find(|v| v.name == "port")
+        .and_then(|v| v.value.parse().ok())
+        .unwrap_or(SOCKS_PORT)
+}
+
+#[cfg(feature = "https-proxy")]
+fn https_info(v: &Endpoint, port: u16, credentials: &Credentials) -> Option<ProxyInfo> {
+    Some(ProxyInfo {
+        load: v.load,
+        country: v.country.clone(),
+        city: v.city.clone(),
+        hostname: v.hostname.clone(),
+        port,
+        protocol: Protocol::Https,
+        status: v.status.clone(),
+        inferred: v.inferred,
+        groups: v.groups,
+        proxy: reqwest_proxy(Protocol::Https, &v.hostname, port, credentials).ok()?,
+        credentials: credentials.clone(),
+    })
+}
+
+#[cfg(feature = "socks5")]
+fn socks5_info(
+    hostname: &str,
+    load: Load,
+    country: &Country,
+    city: &City,
+    status: &Status,
+    groups: ServerGroups,
+    credentials: &Credentials,
+) -> Option<ProxyInfo> {
+    Some(ProxyInfo {
+        load,
+        city: city.clone(),
+        country: country.clone(),
+        hostname: hostname.to_string(),
+        port: SOCKS_PORT,
+        protocol: Protocol::Socks5,
+        status: status.clone(),
+        inferred: false,
+        groups,
+        proxy: reqwest_proxy(Protocol::Socks5, hostname, SOCKS_PORT, credentials).ok()?,
+        credentials: credentials.clone(),
+    })
+}
+
+/// Fails for hostnames that don't make up a URL, whose endpoints are skipped.
+fn reqwest_proxy(
+    protocol: Protocol,
+    hostname: &str,
+    port: u16,
+    credentials: &Credentials,
+) -> reqwest::Result<reqwest::Proxy> {
+    let address = format!("{}://{hostname}:{port}", protocol.scheme());
+    let proxy = match protocol {
+        Protocol::Https => reqwest::Proxy::https(address),
+        Protocol::Socks5 => reqwest::Proxy::all(address),
+    }?;
+    Ok(proxy.basic_auth(credentials.username(), credentials.password()))
+}
+
 pub trait ProxyTrait {
-    fn proxies(&self, username: &str, password: &str) -> Vec<ProxyInfo>;
+    fn proxies_with(&self, credentials: &Credentials) -> Vec<ProxyInfo>;
+
+    fn proxies(&self, username: &str, password: &str) -> Vec<ProxyInfo> {
+        self.proxies_with(&Credentials::new(username, password))
+    }
+
+    /// The endpoint of the server `name`, e.g. `us9591` or `us9591.nordvpn.com`.
+    fn get(&self, name: &str, credentials: &Credentials) -> Option<ProxyInfo> {
+        let name = server_name(name);
+        self.proxies_with(credentials)
+            .into_iter()
+            .find(|v| server_name(&v.hostname).eq_ignore_ascii_case(name))
+    }
+
+    /// Number of endpoints per status, including the ones under maintenance that the selection
+    /// methods below skip.
+    fn status_counts(&self) -> HashMap<Status, usize> {
+        let mut counts = HashMap::new();
+        for v in self.proxies_with(&Credentials::new("", "")) {
+            *counts.entry(v.status).or_default() += 1;
+        }
+        counts
+    }
+
+    /// The endpoint with the lowest load.
+    fn best(&self, credentials: &Credentials) -> Option<ProxyInfo> {
+        selectable(self.proxies_with(credentials)).min_by(determinism::by_load)
+    }
+
+    /// Up to `n` endpoints spread across countries: the least loaded endpoint of every country
+    /// first, then the second least loaded, and so on.
+    fn pick(&self, n: usize, credentials: &Credentials) -> Vec<ProxyInfo> {
+        let mut proxies = selectable(self.proxies_with(credentials)).collect::<Vec<_>>();
+        proxies.sort_by(determinism::by_load);
+        round_robin(by_country(proxies), n)
+    }
+
+    /// Up to `n` distinct endpoints chosen uniformly at random.
+    fn sample(&self, n: usize, credentials: &Credentials) -> Vec<ProxyInfo> {
+        let mut proxies = selectable(self.proxies_with(credentials)).collect::<Vec<_>>();
+        proxies.shuffle(&mut determinism::rng());
+        proxies.truncate(n);
+        proxies
+    }
+
+    /// Like [`ProxyTrait::sample`], but stratified by country so that every country is
+    /// represented before any country gets a second endpoint.
+    fn sample_by_country(&self, n: usize, credentials: &Credentials) -> Vec<ProxyInfo> {
+        let mut rng = determinism::rng();
+        let mut proxies = selectable(self.proxies_with(credentials)).collect::<Vec<_>>();
+        proxies.shuffle(&mut rng);
+        let mut countries = by_country(proxies);
+        countries.shuffle(&mut rng);
+        round_robin(countries, n)
+    }
+}
+
+/// `us9591` of `us9591.nordvpn.com`.
+fn server_name(hostname: &str) -> &str {
+    hostname.split('.').next().unwrap_or_default()
 }
 
+/// Leaves out the endpoints listed with [`Builder::include_maintenance`].
+fn selectable(proxies: Vec<ProxyInfo>) -> impl Iterator<Item = ProxyInfo> {
+    proxies.into_iter().filter(|v| v.status.usable(false))
+}
+
+/// Groups `proxies` by country, keeping their order within and between the groups.
+fn by_country<T: Borrow<ProxyInfo>>(proxies: Vec<T>) -> Vec<VecDeque<T>> {
+    let mut index = HashMap::new();
+    let mut countries = Vec::<VecDeque<T>>::new();
+    for v in proxies {
+        let i = *index.entry(v.borrow().country.clone()).or_insert_with(|| {
+            countries.push(VecDeque::new());
+            countries.len() - 1
+        });
+        countries[i].push_back(v);
+    }
+    countries
+}
+
+/// Takes the first element of every group, then the second, ... until `n` are taken.
+fn round_robin(mut groups: Vec<VecDeque<ProxyInfo>>, n: usize) -> Vec<ProxyInfo> {
+    let mut picked = Vec::with_capacity(n);
+    while picked.len() < n && groups.iter().any(|v| !v.is_empty()) {
+        for v in &mut groups {
+            if picked.len() < n
+                && let Some(v) = v.pop_front()
+            {
+                picked.push(v);
+            }
+        }
+    }
+    picked
+}
+
+/// Operational status of a server or one of its technologies, as reported by the API.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
+#[serde(from = "String", into = "String")]
+pub enum Status {
+    Online,
+    Maintenance,
+    Offline,
+    Other(String),
+}
+
+impl Status {
+    /// Whether endpoints in this status are handed out.
+    pub(crate) fn usable(&self, include_maintenance: bool) -> bool {
+        match self {
+            Status::Online => true,
+            Status::Maintenance => include_maintenance,
+            _ => false,
+        }
+    }
+}
+
+/// Case-insensitive, since the API isn't consistent about it.
+impl From<String> for Status {
+    fn from(v: String) -> Self {
+        match v.to_lowercase().as_str() {
+            "online" => Status::Online,
+            "maintenance" => Status::Maintenance,
+            "offline" => Status::Offline,
+            _ => Status::Other(v),
+        }
+    }
+}
+
+impl From<Status> for String {
+    fn from(v: Status) -> Self {
+        match v {
+            Status::Online => "online".to_string(),
+            Status::Maintenance => "maintenance".to_string(),
+            Status::Offline => "offline".to_string(),
+            Status::Other(v) => v,
+        }
+    }
+}
+
+/// Identifier of a technology a server supports, e.g. `socks` or `proxy_ssl`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
+#[serde(from = "String", into = "String")]
+pub enum Technology {
+    Ikev2,
+    OpenvpnUdp,
+    OpenvpnTcp,
+    OpenvpnXorUdp,
+    OpenvpnXorTcp,
+    WireguardUdp,
+    NordWhisper,
+    Socks,
+    Proxy,
+    ProxyCybersec,
+    ProxySsl,
+    ProxySslCybersec,
+    Other(String),
+}
+
+impl Technology {
+    pub fn as_str(&self) -> &str {
+        match self {
+            Technology::Ikev2 => "ikev2",
+            Technology::OpenvpnUdp => "openvpn_udp",
+            Technology::OpenvpnTcp => "openvpn_tcp",
+            Technology::OpenvpnXorUdp => "openvpn_xor_udp",
+            Technology::OpenvpnXorTcp => "openvpn_xor_tcp",
+            Technology::WireguardUdp => "wireguard_udp",
+            Technology::NordWhisper => "nordwhisper",
+            Technology::Socks => "socks",
+            Technology::Proxy => "proxy",
+            Technology::ProxyCybersec => "proxy_cybersec",
+            Technology::ProxySsl => "proxy_ssl",
+            Technology::ProxySslCybersec => "proxy_ssl_cybersec",
+            Technology::Other(v) => v,
+        }
+    }
+
+    /// The technologies that make a server usable as a proxy rather than only as a VPN.
+    pub fn is_proxy(&self) -> bool {
+        matches!(
+            self,
+            Technology::Socks
+                | Technology::Proxy
+                | Technology::ProxyCybersec
+                | Technology::ProxySsl
+                | Technology::ProxySslCybersec
+        )
+    }
+}
+
+impl From<String> for Technology {
+    fn from(v: String) -> Self {
+        match v.as_str() {
+            "ikev2" => Technology::Ikev2,
+            "openvpn_udp" => Technology::OpenvpnUdp,
+            "openvpn_tcp" => Technology::OpenvpnTcp,
+            "openvpn_xor_udp" => Technology::OpenvpnXorUdp,
+            "openvpn_xor_tcp" => Technology::OpenvpnXorTcp,
+            "wireguard_udp" => Technology::WireguardUdp,
+            "nordwhisper" => Technology::NordWhisper,
+            "socks" => Technology::Socks,
+            "proxy" => Technology::Proxy,
+            "proxy_cybersec" => Technology::ProxyCybersec,
+            "proxy_ssl" => Technology::ProxySsl,
+            "proxy_ssl_cybersec" => Technology::ProxySslCybersec,
+            _ => Technology::Other(v),
+        }
+    }
+}
+
+impl From<Technology> for String {
+    fn from(v: Technology) -> Self {
+        match v {
+            Technology::Other(v) => v,
+            v => v.as_str().to_string(),
+        }
+    }
+}
+
+impl fmt::Display for Technology {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// How a client talks to the endpoint.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum Protocol {
+    /// HTTP proxy behind TLS.
+    Https,
+    Socks5,
+}
+
+impl Protocol {
+    /// URL scheme of the endpoint; `socks5h` so that target hostnames are resolved remotely.
+    pub fn scheme(self) -> &'static str {
+        match self {
+            Protocol::Https => "https",
+            Protocol::Socks5 => "socks5h",
+        }
+    }
+}
+
+/// Build endpoints outside the NordVPN lists with [`ProxyInfo::new`], as more fields may be
+/// added.
+#[derive(Clone)]
+#[non_exhaustive]
 pub struct ProxyInfo {
-    pub load: u32,
+    pub load: Load,
     pub country: Country,
     pub city: City,
+    pub hostname: String,
+    pub port: u16,
+    pub protocol: Protocol,
+    pub status: Status,
+    /// The API listed no dedicated hostname for the endpoint, so the server's one is used.
+    pub inferred: bool,
+    pub groups: ServerGroups,
     pub proxy: reqwest::Proxy,
+    /// Kept to rebuild `proxy` when the endpoint changes.
+    credentials: Credentials,
+}
+
+/// A server's load in percent, clamped to 0–100.
+///
+/// (De)serializes as the plain number, clamping out of range values.
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize,
+)]
+#[serde(from = "u32", into = "u32")]
+pub struct Load(u8);
+
+/// Coarse ranges of [`Load`], see [`Load::bucket`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum LoadBucket {
+    /// Below 30 percent.
+    Light,
+    /// 30 to 69 percent.
+    Moderate,
+    /// 70 percent and up.
+    Heavy,
+}
+
+impl Load {
+    /// `percent` clamped to 100.
+    pub const fn new(percent: u32) -> Self {
+        Load(if percent > 100 { 100 } else { percent as u8 })
+    }
+
+    pub const fn get(self) -> u8 {
+        self.0
+    }
+
+    pub const fn is_light(self) -> bool {
+        matches!(self.bucket(), LoadBucket::Light)
+    }
+
+    pub const fn bucket(self) -> LoadBucket {
+        match self.0 {
+            0..30 => LoadBucket::Light,
+            30..70 => LoadBucket::Moderate,
+            _ => LoadBucket::Heavy,
+        }
+    }
+}
+
+impl From<u32> for Load {
+    fn from(value: u32) -> Self {
+        Load::new(value)
+    }
+}
+
+impl From<Load> for u32 {
+    fn from(value: Load) -> Self {
+        value.0.into()
+    }
+}
+
+impl fmt::Display for Load {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+/// Groups of the server an endpoint is on that restrict what it may be used for.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ServerGroups {
+    /// Allows peer-to-peer traffic, e.g. BitTorrent, which the other servers disconnect.
+    pub p2p: bool,
+    /// Disguises VPN traffic for networks that block it, at the cost of some overhead.
+    pub obfuscated: bool,
+}
+
+/// Whether to use obfuscated servers, see [`ServerGroups::obfuscated`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Obfuscated {
+    /// Only obfuscated servers, for restrictive networks.
+    Only,
+    /// No obfuscated servers, avoiding their overhead.
+    Exclude,
+    #[default]
+    Any,
+}
+
+impl Obfuscated {
+    pub(crate) fn allows(self, obfuscated: bool) -> bool {
+        match self {
+            Obfuscated::Only => obfuscated,
+            Obfuscated::Exclude => !obfuscated,
+            Obfuscated::Any => true,
+        }
+    }
+}
+
+impl ServerGroups {
+    pub(crate) fn new(groups: &[structure::Groups]) -> Self {
+        let has = |identifier: &str| groups.iter().any(|v| v.identifier == identifier);
+        ServerGroups {
+            p2p: has("legacy_p2p"),
+            obfuscated: has("legacy_obfuscated_servers"),
+        }
+    }
+}
+
+impl ProxyInfo {
+    /// An online endpoint with an unknown location and a load of 0, e.g. a self-hosted relay.
+    ///
+    /// Panics if `hostname` can't be the host of a URL.
+    pub fn new(
+        hostname: impl Into<String>,
+        port: u16,
+        protocol: Protocol,
+        credentials: &Credentials,
+    ) -> Self {
+        let hostname = hostname.into();
+        ProxyInfo {
+            load: Load::default(),
+            country: Country::Other(String::new()),
+            city: City::Other(String::new()),
+            proxy: reqwest_proxy(protocol, &hostname, port, credentials)
+                .expect("invalid endpoint hostname"),
+            hostname,
+            port,
+            protocol,
+            status: Status::Online,
+            inferred: false,
+            groups: ServerGroups::default(),
+            credentials: credentials.clone(),
+        }
+    }
+
+    /// Moves the endpoint to `port`, e.g. 443 on networks that block the HTTPS proxies' usual
+    /// port, or a NAT-translated one.
+    pub fn with_port(mut self, port: u16) -> Self {
+        self.port = port;
+        self.proxy = reqwest_proxy(self.protocol, &self.hostname, port, &self.credentials)
+            .expect("endpoint hostnames are valid");
+        self
+    }
+
+    /// Clamps `load` to 0–100, see [`Load::new`].
+    pub fn with_load(mut self, load: u32) -> Self {
+        self.load = Load::new(load);
+        self
+    }
+
+    pub fn with_location(mut self, country: Country, city: City) -> Self {
+        self.country = country;
+        self.city = city;
+        self
+    }
+
+    pub fn with_status(mut self, status: Status) -> Self {
+        self.status = status;
+        self
+    }
+
+    pub fn load(&self) -> Load {
+        self.load
+    }
+
+    pub fn country(&self) -> &Country {
+        &self.country
+    }
+
+    pub fn city(&self) -> &City {
+        &self.city
+    }
+
+    pub fn hostname(&self) -> &str {
+        &self.hostname
+    }
+
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    pub fn protocol(&self) -> Protocol {
+        self.protocol
+    }
+
+    pub fn status(&self) -> &Status {
+        &self.status
+    }
+
+    pub fn inferred(&self) -> bool {
+        self.inferred
+    }
+
+    pub fn proxy(&self) -> &reqwest::Proxy {
+        &self.proxy
+    }
+
+    /// The reqwest proxy for this endpoint with other `credentials`, e.g. after rotating them.
+    pub fn proxy_with(&self, credentials: &Credentials) -> reqwest::Proxy {
+        reqwest_proxy(self.protocol, &self.hostname, self.port, credentials)
+            .expect("endpoint hostnames are valid")
+    }
+
+    /// `scheme://hostname:port`, without credentials.
+    pub fn address(&self) -> String {
+        format!(
+            "{}://{}:{}",
+            self.protocol.scheme(),
+            self.hostname,
+            self.port
+        )
+    }
+
+    /// The `(hostname, port)` proxy address and the credentials of a SOCKS5 endpoint, for
+    /// tunneling by hand, e.g. with
+    /// `Socks5Stream::connect_with_password(addr, target, auth.username, auth.password)`.
+    /// `None` for HTTPS endpoints.
+    pub fn socks_addr<'a>(
+        &'a self,
+        credentials: &'a Credentials,
+    ) -> Option<((&'a str, u16), SocksAuth<'a>)> {
+        (self.protocol == Protocol::Socks5)
+            .then(|| ((self.hostname.as_str(), self.port), credentials.into()))
+    }
+
+    /// Resolves the hostname of the endpoint, with the [`set_resolver`] resolver if there is one.
+    pub async fn resolve(&self) -> io::Result<Vec<SocketAddr>> {
+        dial::resolve(&self.hostname, self.port).await
+    }
+
+    /// [`ProxyInfo::address`] as a [`url::Url`]; set the credentials with `set_username` and
+    /// `set_password` where needed.
+    #[cfg(feature = "url")]
+    pub fn to_url(&self) -> url::Url {
+        url::Url::parse(&self.address()).expect("endpoint addresses are valid URLs")
+    }
+
+    /// [`ProxyInfo::address`] as an [`http::Uri`].
+    #[cfg(feature = "http")]
+    pub fn to_uri(&self) -> http::Uri {
+        self.address()
+            .parse()
+            .expect("endpoint addresses are valid URIs")
+    }
+}
+
+/// The endpoint without credentials, to be added with [`reqwest::Proxy::basic_auth`].
+impl TryFrom<&ProxyInfo> for reqwest::Proxy {
+    type Error = reqwest::Error;
+
+    fn try_from(v: &ProxyInfo) -> reqwest::Result<Self> {
+        match v.protocol {
+            Protocol::Https => reqwest::Proxy::https(v.address()),
+            Protocol::Socks5 => reqwest::Proxy::all(v.address()),
+        }
+    }
+}
+
+/// Routes a [`reqwest::ClientBuilder`] through an endpoint in one chained call.
+pub trait NordProxyExt {
+    fn nord_proxy(self, info: &ProxyInfo, credentials: &Credentials) -> Self;
+}
+
+impl NordProxyExt for reqwest::ClientBuilder {
+    fn nord_proxy(self, info: &ProxyInfo, credentials: &Credentials) -> Self {
+        self.proxy(info.proxy_with(credentials))
+    }
+}
+
+/// First address of the endpoint, resolved with the blocking system resolver; see
+/// [`ProxyInfo::resolve`] for async code.
+impl TryFrom<&ProxyInfo> for SocketAddr {
+    type Error = io::Error;
+
+    fn try_from(v: &ProxyInfo) -> io::Result<Self> {
+        (v.hostname.as_str(), v.port)
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "hostname has no address"))
+    }
+}
+
+/// Leaves out `proxy`, which carries the credentials.
+impl fmt::Debug for ProxyInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ProxyInfo")
+            .field("load", &self.load)
+            .field("country", &self.country)
+            .field("city", &self.city)
+            .field("hostname", &self.hostname)
+            .field("port", &self.port)
+            .field("protocol", &self.protocol)
+            .field("status", &self.status)
+            .field("inferred", &self.inferred)
+            .field("groups", &self.groups)
+            .finish_non_exhaustive()
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::{Proxy, ProxyTrait, Socks5};
+    #[cfg(feature = "https-proxy")]
+    use crate::Proxy;
+    #[cfg(feature = "socks5")]
+    use crate::Socks5;
+    use crate::{DedicatedIp, Problem, ProxyTrait, Technology};
 
+    #[cfg(feature = "https-proxy")]
     #[tokio::test]
     async fn proxy() {
         let proxy = Proxy::new().await.proxies("user", "pass");
-        assert!(proxy.len() > 0)
+        assert!(!proxy.is_empty())
     }
 
+    #[cfg(feature = "socks5")]
     #[tokio::test]
     async fn socks() {
         let proxy = Socks5::new().await.proxies("user", "pass");
-        assert!(proxy.len() > 0)
+        assert!(!proxy.is_empty())
+    }
+
+    #[test]
+    fn selectors() {
+        use crate::{Country, Credentials, ProxyInfo, pool::tests::proxy};
+
+        struct Fixed;
+        impl ProxyTrait for Fixed {
+            fn proxies_with(&self, _: &Credentials) -> Vec<ProxyInfo> {
+                vec![
+                    proxy("de1.nordvpn.com", Country::DE, 5),
+                    proxy("de2.nordvpn.com", Country::DE, 10),
+                    proxy("nl1.nordvpn.com", Country::NL, 40),
+                    proxy("jp1.nordvpn.com", Country::JP, 20),
+                ]
+            }
+        }
+        let credentials = Credentials::new("user", "pass");
+        assert_eq!(
+            Fixed.best(&credentials).unwrap().hostname,
+            "de1.nordvpn.com"
+        );
+        let picked = Fixed
+            .pick(3, &credentials)
+            .into_iter()
+            .map(|v| v.hostname)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            picked,
+            ["de1.nordvpn.com", "jp1.nordvpn.com", "nl1.nordvpn.com"]
+        );
+        assert_eq!(Fixed.pick(10, &credentials).len(), 4);
+
+        let sampled = Fixed.sample(2, &credentials);
+        assert_eq!(sampled.len(), 2);
+        assert_ne!(sampled[0].hostname, sampled[1].hostname);
+        let countries = Fixed
+            .sample_by_country(3, &credentials)
+            .into_iter()
+            .map(|v| v.country)
+            .collect::<std::collections::HashSet<_>>();
+        assert_eq!(countries.len(), 3);
+    }
+
+    #[test]
+    fn load() {
+        use crate::{Load, LoadBucket};
+
+        assert_eq!(Load::new(250).get(), 100);
+        assert!(Load::new(29).is_light());
+        assert_eq!(Load::new(30).bucket(), LoadBucket::Moderate);
+        assert_eq!(Load::new(70).bucket(), LoadBucket::Heavy);
+        assert_eq!(serde_json::from_str::<Load>("150").unwrap(), Load::new(100));
+    }
+
+    #[test]
+    fn validation() {
+        let json = r#"[{
+            "status": "online", "hostname": "", "load": 10,
+            "services": [], "groups": [{"identifier": "legacy_dedicated_ip"}],
+            "locations": [{"country": {"code": "DE", "city": {"name": "Berlin", "hub_score": 0}}}],
+            "technologies": []
+        }, {
+            "status": "online", "hostname": "de3.nordvpn.com", "load": 250,
+            "services": [], "groups": [{"identifier": "legacy_dedicated_ip"}],
+            "locations": [{"country": {"code": "DE", "city": {"name": "Berlin", "hub_score": 0}}}],
+            "technologies": []
+        }, {
+            "status": "online", "hostname": "de 4.nordvpn.com", "load": 10,
+            "services": [], "groups": [{"identifier": "legacy_dedicated_ip"}],
+            "locations": [{"country": {"code": "DE", "city": {"name": "Berlin", "hub_score": 0}}}],
+            "technologies": []
+        }, {
+            "status": "online", "hostname": "nowhere1.nordvpn.com", "load": 10,
+            "services": [], "groups": [{"identifier": "legacy_dedicated_ip"}],
+            "locations": [],
+            "technologies": []
+        }]"#;
+        let dedicated = DedicatedIp::from_json_str(json).unwrap();
+        let problems = dedicated
+            .warnings()
+            .iter()
+            .map(|v| v.problem)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            problems,
+            [
+                Problem::EmptyHostname,
+                Problem::Load(250),
+                Problem::InvalidHostname,
+                Problem::NoLocation
+            ]
+        );
+        assert_eq!(
+            dedicated.warnings()[1].to_string(),
+            "de3.nordvpn.com: implausible load 250"
+        );
+    }
+
+    #[cfg(feature = "socks5")]
+    #[test]
+    fn discovered_ports() {
+        let json = r#"[
+            {"status": "online", "hostname": "de1.nordvpn.com", "load": 20, "services": [],
+             "locations": [{"country": {"code": "DE", "city": {"name": "Berlin", "hub_score": 0}}}],
+             "technologies": [{"identifier": "socks", "pivot": {"status": "online"},
+                               "metadata": [{"name": "port", "value": "1085"}]}]},
+            {"status": "online", "hostname": "nl1.nordvpn.com", "load": 20, "services": [],
+             "locations": [{"country": {"code": "NL", "city": {"name": "Amsterdam", "hub_score": 0}}}],
+             "technologies": [{"identifier": "socks", "pivot": {"status": "online"}, "metadata": []}]}
+        ]"#;
+        let socks = Socks5::from_json_str(json).unwrap();
+        let ports = |socks: &Socks5| {
+            socks
+                .proxies("user", "pass")
+                .iter()
+                .map(|v| v.port)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(ports(&socks), [1085, 1080]);
+        let socks = socks.with_country_port(crate::Country::NL, 443);
+        assert_eq!(ports(&socks), [1085, 443]);
+    }
+
+    #[cfg(feature = "https-proxy")]
+    #[test]
+    fn threat_protection() {
+        let json = r#"[{
+            "status": "online", "hostname": "de1057.nordvpn.com", "load": 20,
+            "services": [{"identifier": "proxy"}],
+            "locations": [{"country": {"code": "DE", "city": {"name": "Berlin", "hub_score": 0}}}],
+            "technologies": [
+                {"identifier": "proxy_ssl", "pivot": {"status": "online"},
+                 "metadata": [{"name": "proxy_hostname", "value": "de1057.nordvpn.com"}]},
+                {"identifier": "proxy_ssl_cybersec", "pivot": {"status": "online"},
+                 "metadata": [{"name": "proxy_hostname", "value": "de1057-cs.nordvpn.com"}]}
+            ]
+        }]"#;
+        let roots: Vec<crate::structure::Root> = serde_json::from_str(json).unwrap();
+        let plain = Proxy::from_roots(roots.clone(), &Technology::ProxySsl, false)
+            .proxies("user", "hunter2");
+        assert_eq!(plain[0].hostname, "de1057.nordvpn.com");
+        assert!(!format!("{:?}", plain[0].clone()).contains("hunter2"));
+        assert_eq!(plain[0].address(), "https://de1057.nordvpn.com:89");
+        let moved = Proxy::from_roots(roots.clone(), &Technology::ProxySsl, false).with_port(443);
+        assert_eq!(moved.proxies("user", "pass")[0].port, 443);
+        #[cfg(feature = "url")]
+        assert_eq!(plain[0].to_url().port(), Some(89));
+        #[cfg(feature = "http")]
+        assert_eq!(plain[0].to_uri().host(), Some("de1057.nordvpn.com"));
+        let filtered =
+            Proxy::from_roots(roots, &Technology::ProxySslCybersec, false).proxies("user", "pass");
+        assert_eq!(filtered.len(), 1);
+        assert_eq!(filtered[0].hostname, "de1057-cs.nordvpn.com");
+    }
+
+    #[cfg(all(feature = "socks5", feature = "https-proxy"))]
+    #[tokio::test]
+    async fn probe_ports() {
+        use std::time::Duration;
+
+        let listener = tokio::net::TcpListener::bind("0.0.0.0:0").await.unwrap();
+        let unused = tokio::net::TcpListener::bind("0.0.0.0:0").await.unwrap();
+        let (open, closed) = (
+            listener.local_addr().unwrap().port(),
+            unused.local_addr().unwrap().port(),
+        );
+        drop(unused);
+        let server = |hostname: &str, https: String, socks: u16| {
+            format!(
+                r#"{{
+                    "status": "online", "hostname": "{hostname}", "load": 20,
+                    "services": [{{"identifier": "proxy"}}],
+                    "locations": [{{"country": {{"code": "DE", "city": {{"name": "Berlin", "hub_score": 0}}}}}}],
+                    "technologies": [
+                        {{"identifier": "proxy_ssl", "pivot": {{"status": "online"}},
+                         "metadata": [{{"name": "port", "value": "{https}"}}]}},
+                        {{"identifier": "socks", "pivot": {{"status": "online"}},
+                         "metadata": [{{"name": "port", "value": "{socks}"}}]}}
+                    ]
+                }}"#
+            )
+        };
+        let json = format!(
+            "[{}, {}]",
+            server("127.0.0.1", format!("{closed},{open}"), open),
+            server("127.0.0.2", closed.to_string(), closed)
+        );
+        let roots: Vec<crate::structure::Root> = serde_json::from_str(&json).unwrap();
+        let endpoints = |v: Vec<crate::ProxyInfo>| {
+            v.into_iter()
+                .map(|v| (v.hostname, v.port))
+                .collect::<Vec<_>>()
+        };
+
+        let mut proxy = Proxy::from_roots(roots.clone(), &Technology::ProxySsl, false);
+        assert_eq!(proxy.proxies("user", "pass")[0].port, closed);
+        proxy.probe_ports(Duration::from_secs(1)).await;
+        assert_eq!(
+            endpoints(proxy.proxies("user", "pass")),
+            [("127.0.0.1".to_string(), open)]
+        );
+
+        let mut socks = Socks5::from_roots(roots, false);
+        socks.probe_ports(Duration::from_secs(1)).await;
+        assert_eq!(
+            endpoints(socks.proxies("user", "pass")),
+            [("127.0.0.1".to_string(), open)]
+        );
+    }
+
+    #[cfg(all(feature = "socks5", feature = "https-proxy"))]
+    #[test]
+    fn malformed() {
+        use crate::DedicatedIp;
+
+        let json = r#"[{
+            "status": "online", "hostname": "nowhere1.nordvpn.com", "load": 5,
+            "services": [{"identifier": "proxy"}],
+            "groups": [{"identifier": "legacy_dedicated_ip"}],
+            "locations": [],
+            "technologies": [
+                {"identifier": "socks", "pivot": {"status": "online"}, "metadata": []},
+                {"identifier": "proxy_ssl", "pivot": {"status": "online"},
+                 "metadata": [{"name": "proxy_hostname", "value": "nowhere1.nordvpn.com"}]}
+            ]
+        }, {
+            "status": "online", "hostname": "de2.nordvpn.com", "load": 10,
+            "services": [{"identifier": "proxy"}],
+            "groups": [{"identifier": "legacy_dedicated_ip"}],
+            "locations": [{"country": {"code": "DE", "city": {"name": "Berlin", "hub_score": 0}}}],
+            "technologies": [
+                {"identifier": "socks", "pivot": {"status": "online"}, "metadata": []},
+                {"identifier": "proxy_ssl", "pivot": {"status": "online"}, "metadata": []}
+            ]
+        }]"#;
+        let roots: Vec<crate::structure::Root> = serde_json::from_str(json).unwrap();
+        let proxy = Proxy::from_roots(roots.clone(), &Technology::ProxySsl, false);
+        assert_eq!(proxy.invalid(), ["nowhere1.nordvpn.com"]);
+        let proxies = proxy.proxies("user", "pass");
+        assert_eq!(proxies.len(), 1);
+        assert_eq!(proxies[0].hostname, "de2.nordvpn.com");
+        assert!(proxies[0].inferred);
+        let socks = Socks5::from_roots(roots.clone(), false);
+        assert_eq!(socks.invalid(), ["nowhere1.nordvpn.com"]);
+        assert!(!socks.proxies("user", "pass")[0].inferred);
+        let dedicated = DedicatedIp::from_roots(roots, false).proxies("user", "pass");
+        assert_eq!(dedicated.len(), 2);
+        assert!(dedicated.iter().all(|v| v.hostname == "de2.nordvpn.com"));
+    }
+
+    #[cfg(feature = "socks5")]
+    #[test]
+    fn status() {
+        use crate::Status;
+
+        let json = r#"[{
+            "status": "Online", "hostname": "de1.nordvpn.com", "load": 10,
+            "services": [{"identifier": "proxy"}],
+            "locations": [{"country": {"code": "DE", "city": {"name": "Berlin", "hub_score": 0}}}],
+            "technologies": [{"identifier": "socks", "pivot": {"status": "ONLINE"}, "metadata": []}]
+        }, {
+            "status": "maintenance", "hostname": "de2.nordvpn.com", "load": 0,
+            "services": [{"identifier": "proxy"}],
+            "locations": [{"country": {"code": "DE", "city": {"name": "Berlin", "hub_score": 0}}}],
+            "technologies": [{"identifier": "socks", "pivot": {"status": "online"}, "metadata": []}]
+        }]"#;
+        let roots: Vec<crate::structure::Root> = serde_json::from_str(json).unwrap();
+        assert_eq!(roots[0].status, Status::Online);
+        assert_eq!(
+            serde_json::to_string(&roots[1].status).unwrap(),
+            "\"maintenance\""
+        );
+        let online = Socks5::from_roots(roots.clone(), false).proxies("user", "pass");
+        assert_eq!(online.len(), 1);
+        let all = Socks5::from_roots(roots.clone(), true).proxies("user", "pass");
+        assert_eq!(all[1].status, Status::Maintenance);
+        let socks = Socks5::from_roots(roots, true);
+        assert_eq!(socks.status_counts()[&Status::Maintenance], 1);
+        let credentials = crate::Credentials::new("user", "pass");
+        let best = socks.best(&credentials).unwrap();
+        assert_eq!(best.hostname, "de1.nordvpn.com");
+        assert_eq!(
+            socks.get("DE2", &credentials).unwrap().hostname,
+            "de2.nordvpn.com"
+        );
+        assert!(socks.get("de3.nordvpn.com", &credentials).is_none());
+    }
+
+    #[cfg(all(feature = "socks5", feature = "https-proxy"))]
+    #[test]
+    fn fixture() {
+        use crate::{City, Country};
+
+        let json = include_str!("../fixtures/servers.json");
+        let https = Proxy::from_json_str(json).unwrap().proxies("user", "pass");
+        let hostnames = https
+            .iter()
+            .map(|v| v.hostname.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            hostnames,
+            [
+                "de1057.nordvpn.com",
+                "nl912.nordvpn.com",
+                "us5063.nordvpn.com"
+            ]
+        );
+        assert_eq!(https[2].city, City::NewYork);
+        let socks = Socks5::from_json_str(json).unwrap().proxies("user", "pass");
+        assert_eq!(socks.len(), 2);
+        assert_eq!(socks[0].country, Country::DE);
+        assert_eq!(socks[0].city, City::Frankfurt);
+        assert!(Socks5::from_json_str("{}").is_err());
+        let roots = super::parse(json.as_bytes().to_vec()).unwrap();
+        assert_eq!(roots[4].hostname, "jp612.nordvpn.com");
+        assert!(super::parse(b"[{}]".to_vec()).is_err());
+    }
+
+    #[test]
+    fn technology() {
+        let known: Technology = serde_json::from_str("\"openvpn_udp\"").unwrap();
+        assert_eq!(known, Technology::OpenvpnUdp);
+        let new: Technology = serde_json::from_str("\"quantum_udp\"").unwrap();
+        assert_eq!(new, Technology::Other("quantum_udp".to_string()));
+        assert_eq!(serde_json::to_string(&new).unwrap(), "\"quantum_udp\"");
+        assert_eq!(
+            Technology::ProxySslCybersec.to_string(),
+            "proxy_ssl_cybersec"
+        );
+        assert!(Technology::Socks.is_proxy() && !known.is_proxy());
+    }
+
+    #[tokio::test]
+    async fn conversions() {
+        use std::net::SocketAddr;
+
+        use crate::{Country, Credentials, NordProxyExt, pool::tests::proxy};
+
+        let info = proxy("127.0.0.1", Country::DE, 0);
+        let addr = SocketAddr::try_from(&info).unwrap();
+        assert_eq!(addr, "127.0.0.1:1080".parse().unwrap());
+        assert_eq!(info.resolve().await.unwrap(), [addr]);
+        assert!(reqwest::Proxy::try_from(&info).is_ok());
+        let client = reqwest::Client::builder()
+            .nord_proxy(&info, &Credentials::new("user", "p@ss:word"))
+            .build();
+        assert!(client.is_ok());
+    }
+
+    #[test]
+    fn custom() {
+        use crate::{City, Country, Credentials, Protocol, ProxyInfo};
+
+        let info = ProxyInfo::new(
+            "relay.example.com",
+            3128,
+            Protocol::Https,
+            &Credentials::new("user", "pass"),
+        )
+        .with_load(12)
+        .with_location(Country::DE, City::Frankfurt);
+        assert_eq!(info.address(), "https://relay.example.com:3128");
+        let info = info.with_port(443);
+        assert_eq!(info.address(), "https://relay.example.com:443");
+        assert_eq!((info.load().get(), info.country()), (12, &Country::DE));
+        assert!(info.status().usable(false) && !info.inferred());
+        assert!(info.socks_addr(&Credentials::new("user", "pass")).is_none());
+
+        let credentials = Credentials::new("user", "pass");
+        let info = ProxyInfo::new("relay.example.com", 1080, Protocol::Socks5, &credentials);
+        let (addr, auth) = info.socks_addr(&credentials).unwrap();
+        assert_eq!(addr, ("relay.example.com", 1080));
+        assert_eq!((auth.username, auth.password), ("user", "pass"));
+    }
+
+    #[test]
+    fn country_serde() {
+        use crate::{City, Country};
+        let de: Country = serde_json::from_str("\"DE\"").unwrap();
+        assert_eq!(de, Country::DE);
+        let xx: Country = serde_json::from_str("\"XX\"").unwrap();
+        assert_eq!(xx, Country::Other("XX".to_string()));
+        assert_eq!(serde_json::to_string(&Country::DE).unwrap(), "\"DE\"");
+        assert_eq!(serde_json::to_string(&xx).unwrap(), "\"XX\"");
+        let city: City = serde_json::from_str("\"New York\"").unwrap();
+        assert_eq!(city, City::NewYork);
+        assert_eq!(
+            serde_json::to_string(&City::NewYork).unwrap(),
+            "\"New York\""
+        );
     }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
-#[serde(untagged)]
 pub enum Country {
     #[serde(rename = "EE")]
     EE,
@@ -425,11 +2050,11 @@ pub enum Country {
     IS,
     #[serde(rename = "SR")]
     SR,
+    #[serde(untagged)]
     Other(String),
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
-#[serde(untagged)]
 pub enum City {
     #[serde(rename = "Sofia")]
     Sofia,
@@ -791,5 +2416,31 @@ pub enum City {
     Bordeaux,
     Charleston,
     Honolulu,
+    #[serde(untagged)]
     Other(String),
 }
+
+impl FromStr for Country {
+    type Err = Infallible;
+
+    /// Parses an ISO 3166-1 alpha-2 code, case-insensitively.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(
+            Country::deserialize(StrDeserializer::<serde::de::value::Error>::new(
+                &s.to_uppercase(),
+            ))
+            .unwrap_or_else(|_| Country::Other(s.to_uppercase())),
+        )
+    }
+}
+
+impl FromStr for City {
+    type Err = Infallible;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(
+            City::deserialize(StrDeserializer::<serde::de::value::Error>::new(s))
+                .unwrap_or_else(|_| City::Other(s.to_string())),
+        )
+    }
+}
@@ -0,0 +1,165 @@
+//! Rotation over a [`Pool`] as `reqwest-middleware` middleware.
+//!
+//! ```no_run
+//! # async fn run(pool: std::sync::Arc<nord_proxy::pool::Pool>) -> reqwest_middleware::Result<()> {
+//! use nord_proxy::{
+//!     Country,
+//!     middleware::{PreferCountry, Rotate},
+//! };
+//!
+//! let client = reqwest_middleware::ClientBuilder::new(reqwest::Client::new())
+//!     .with(Rotate::new(pool))
+//!     .build();
+//! // Through the next endpoint of the pool.
+//! client.get("https://example.com").send().await?;
+//! // Through an endpoint in Germany.
+//! client
+//!     .get("https://example.de")
+//!     .with_extension(PreferCountry(Country::DE))
+//!     .send()
+//!     .await?;
+//! # Ok(())
+//! # }
+//! ```
+use std::{fmt, io, sync::Arc};
+
+use http::Extensions;
+use reqwest::{Request, Response};
+use reqwest_middleware::{Error, Middleware, Next, Result};
+
+use crate::{
+    Country, ProxyInfo,
+    pool::{InFlight, MemoryState, Pool, State},
+};
+
+/// Sends every request through the next endpoint of a [`Pool`], or through one in the country
+/// of its [`PreferCountry`] extension.
+///
+/// Endpoints are taken with [`Pool::acquire`], so the pool's [`Pool::max_in_flight`] and
+/// [`Pool::rate_limit`] apply. Requests go out with the endpoint's [`Pool::client_for`] instead of
+/// the client the middleware was added to, so this has to be the last middleware.
+pub struct Rotate<S = MemoryState> {
+    pool: Arc<Pool<S>>,
+}
+
+/// Sends a request through an endpoint in the country instead of the next one in rotation,
+/// failing it if none is available there. Attach it with `RequestBuilder::with_extension`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PreferCountry(pub Country);
+
+impl<S: State> Rotate<S> {
+    pub fn new(pool: Arc<Pool<S>>) -> Self {
+        Rotate { pool }
+    }
+
+    pub fn pool(&self) -> &Pool<S> {
+        &self.pool
+    }
+
+    async fn endpoint(&self, extensions: &Extensions) -> Result<(&ProxyInfo, InFlight)> {
+        let (acquired, error) = match extensions.get::<PreferCountry>() {
+            Some(PreferCountry(country)) => (
+                self.pool.acquire_in(country).await,
+                format!("no endpoint available in {country:?}"),
+            ),
+            None => (
+                self.pool.acquire().await,
+                "no endpoint available".to_string(),
+            ),
+        };
+        acquired.ok_or_else(|| Error::middleware(io::Error::other(error)))
+    }
+}
+
+#[async_trait::async_trait]
+impl<S: State + 'static> Middleware for Rotate<S> {
+    async fn handle(
+        &self,
+        req: Request,
+        extensions: &mut Extensions,
+        _next: Next<'_>,
+    ) -> Result<Response> {
+        let (proxy, _slot) = self.endpoint(extensions).await?;
+        let client = self.pool.client_for(proxy)?;
+        Ok(client
+            .execute(req)
+            .await
+            .inspect_err(|_| self.pool.record_failure(&proxy.hostname))?)
+    }
+}
+
+impl<S: fmt::Debug> fmt::Debug for Rotate<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Rotate").field("pool", &self.pool).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::Arc,
+        time::{Duration, Instant},
+    };
+
+    use reqwest_middleware::{ClientBuilder, Error};
+
+    use super::{PreferCountry, Rotate};
+    use crate::{
+        Country,
+        pool::{Pool, Rate, tests::proxy},
+    };
+
+    #[tokio::test]
+    async fn prefer_country() {
+        let pool = Arc::new(Pool::new(vec![
+            proxy("de1.nordvpn.com", Country::DE, 10),
+            proxy("127.0.0.1", Country::NL, 20),
+        ]));
+        let client = ClientBuilder::new(reqwest::Client::new())
+            .with(Rotate::new(pool.clone()))
+            .build();
+        let sent = client
+            .get("http://127.0.0.1:1/")
+            .with_extension(PreferCountry(Country::NL))
+            .send()
+            .await;
+        assert!(matches!(sent, Err(Error::Reqwest(_))));
+        let requests = pool
+            .usage_report()
+            .0
+            .into_iter()
+            .map(|v| (v.hostname, v.requests))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            requests,
+            [
+                ("de1.nordvpn.com".to_string(), 0),
+                ("127.0.0.1".to_string(), 1)
+            ]
+        );
+
+        let sent = client
+            .get("http://127.0.0.1:1/")
+            .with_extension(PreferCountry(Country::US))
+            .send()
+            .await;
+        assert!(matches!(sent, Err(Error::Middleware(_))));
+    }
+
+    #[tokio::test]
+    async fn rate_limit() {
+        let pool = Pool::new(vec![proxy("127.0.0.1", Country::NL, 20)]).rate_limit(Rate {
+            requests: 1,
+            per: Duration::from_millis(100),
+        });
+        let client = ClientBuilder::new(reqwest::Client::new())
+            .with(Rotate::new(Arc::new(pool)))
+            .build();
+        let started = Instant::now();
+        for _ in 0..3 {
+            let sent = client.get("http://127.0.0.1:1/").send().await;
+            assert!(matches!(sent, Err(Error::Reqwest(_))));
+        }
+        assert!(started.elapsed() >= Duration::from_millis(150));
+    }
+}
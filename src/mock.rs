@@ -0,0 +1,230 @@
+//! A local stand-in for the NordVPN API, so the fetch and filter pipeline can be tested without
+//! network access.
+//!
+//! Requests made to the API within [`MockApi::run`] go to a server on a local port that answers
+//! every path with its canned [`Response`].
+// The tests running against the mock list SOCKS5 servers.
+#![cfg_attr(not(feature = "socks5"), allow(dead_code))]
+use std::{collections::HashMap, sync::Arc};
+
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+};
+use tokio_util::task::AbortOnDropHandle;
+
+#[derive(Debug, Clone)]
+pub(crate) enum Response {
+    /// `200 OK` with the body as is, which need not be valid JSON.
+    Body(String),
+    /// The items a page at a time, honoring the `limit` (`0` for all) and `offset` parameters.
+    Paginated(Vec<serde_json::Value>),
+    Status(u16),
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct MockApi {
+    routes: HashMap<String, Response>,
+}
+
+impl MockApi {
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    /// Answers requests for `path`, e.g. `/v1/servers`, with `response`. Other paths get a 404.
+    pub(crate) fn route(mut self, path: &str, response: Response) -> Self {
+        self.routes.insert(path.to_string(), response);
+        self
+    }
+
+    /// Runs `test` with its API requests going to the mock.
+    ///
+    /// Tasks spawned by `test` itself talk to the real API, as the mock is scoped to its task.
+    pub(crate) async fn run<F: Future>(self, test: F) -> F::Output {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        let routes = Arc::new(self.routes);
+        let _server = AbortOnDropHandle::new(tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let routes = routes.clone();
+                tokio::spawn(async move { serve(stream, &routes).await });
+            }
+        }));
+        crate::with_api_base(base, test).await
+    }
+}
+
+async fn serve(mut stream: TcpStream, routes: &HashMap<String, Response>) -> std::io::Result<()> {
+    let mut head = Vec::new();
+    while !head.ends_with(b"\r\n\r\n") {
+        if stream.read_u8().await.map(|v| head.push(v)).is_err() {
+            return Ok(());
+        }
+    }
+    let head = String::from_utf8_lossy(&head);
+    let target = head.split(' ').nth(1).unwrap_or("/");
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let param = |name: &str| {
+        query
+            .split('&')
+            .find_map(|v| v.strip_prefix(name)?.strip_prefix('='))
+            .and_then(|v| v.parse::<usize>().ok())
+            .unwrap_or(0)
+    };
+    let (status, body) = match routes.get(path) {
+        Some(Response::Body(v)) => (200, v.clone()),
+        Some(Response::Paginated(items)) => {
+            let limit = match param("limit") {
+                0 => usize::MAX,
+                v => v,
+            };
+            let page = items.iter().skip(param("offset")).take(limit);
+            (200, serde_json::to_string(&page.collect::<Vec<_>>())?)
+        }
+        Some(Response::Status(v)) => (*v, String::new()),
+        None => (404, String::new()),
+    };
+    let response = format!(
+        "HTTP/1.1 {status} Mock\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    );
+    stream.write_all(response.as_bytes()).await
+}
+
+#[cfg(all(test, feature = "socks5"))]
+mod tests {
+    use std::io;
+
+    use super::{MockApi, Response};
+    use crate::{Country, Obfuscated, ProxyTrait, Socks5};
+
+    const SERVERS: &str = include_str!("../fixtures/servers.json");
+
+    fn hostnames(socks: &Socks5) -> Vec<String> {
+        socks
+            .proxies("user", "pass")
+            .into_iter()
+            .map(|v| v.hostname)
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn servers() {
+        let mock = MockApi::new().route("/v1/servers", Response::Body(SERVERS.to_string()));
+        mock.run(async {
+            let socks = Socks5::builder().build().await;
+            assert_eq!(
+                hostnames(&socks),
+                ["de1057.nordvpn.com", "nl912.nordvpn.com"]
+            );
+            let socks = Socks5::builder().hostnames(["nl*"]).build().await;
+            assert_eq!(hostnames(&socks), ["nl912.nordvpn.com"]);
+            let socks = Socks5::builder().obfuscated(Obfuscated::Only).build().await;
+            assert!(hostnames(&socks).is_empty());
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    async fn countries() {
+        let ids = r#"[{"id": 81, "code": "DE"}, {"id": 153, "code": "NL"}]"#;
+        let mock = MockApi::new()
+            .route("/v1/servers", Response::Body(SERVERS.to_string()))
+            .route("/v1/servers/countries", Response::Body(ids.to_string()));
+        mock.run(async {
+            let socks = Socks5::builder()
+                .countries([Country::DE])
+                .try_build()
+                .await
+                .unwrap();
+            // The mock answers every country with all of its servers.
+            assert_eq!(
+                hostnames(&socks),
+                ["de1057.nordvpn.com", "nl912.nordvpn.com"]
+            );
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    async fn paginated() {
+        let items = serde_json::from_str(SERVERS).unwrap();
+        let mock = MockApi::new().route("/v1/servers/recommendations", Response::Paginated(items));
+        mock.run(async {
+            let socks = Socks5::builder().recommended(1).build().await;
+            assert_eq!(hostnames(&socks), ["de1057.nordvpn.com"]);
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    async fn malformed() {
+        let legacy = r#"[{"domain": "se1.nordvpn.com", "flag": "SE", "load": 5,
+                         "features": {"socks": true}}]"#;
+        let mock = MockApi::new()
+            .route(
+                "/v1/servers",
+                Response::Body(r#"[{"hostname": 42}]"#.to_string()),
+            )
+            .route("/server", Response::Body(legacy.to_string()));
+        mock.run(async {
+            let error = crate::get_info("https://api.nordvpn.com/v1/servers?limit=0")
+                .await
+                .unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+            let socks = Socks5::builder().legacy_fallback(true).build().await;
+            assert_eq!(hostnames(&socks), ["se1.nordvpn.com"]);
+        })
+        .await;
+    }
+
+    #[cfg(feature = "embedded-fallback")]
+    #[tokio::test]
+    async fn embedded() {
+        let mock = MockApi::new().route("/v1/servers", Response::Status(503));
+        mock.run(async {
+            let socks = Socks5::builder().build().await;
+            assert!(!hostnames(&socks).is_empty());
+            assert!(socks.age() > std::time::Duration::ZERO);
+        })
+        .await;
+    }
+
+    #[cfg(not(feature = "embedded-fallback"))]
+    #[tokio::test]
+    async fn errors() {
+        let mut servers: Vec<serde_json::Value> = serde_json::from_str(SERVERS).unwrap();
+        servers[1]["technologies"][0]["identifier"] = "quantum_udp".into();
+        let mock = MockApi::new()
+            .route(
+                "/v1/servers",
+                Response::Body(serde_json::to_string(&servers).unwrap()),
+            )
+            .route("/v1/servers/recommendations", Response::Status(503));
+        mock.run(async {
+            let error = Socks5::builder()
+                .parse_mode(crate::ParseMode::Strict)
+                .try_build()
+                .await
+                .unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+            assert!(Socks5::builder().try_build().await.is_ok());
+            assert!(Socks5::builder().recommended(1).try_build().await.is_err());
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    async fn empty() {
+        let mock = MockApi::new()
+            .route("/v1/servers", Response::Body("[]".to_string()))
+            .route("/server", Response::Status(503));
+        mock.run(async {
+            assert!(hostnames(&Socks5::builder().build().await).is_empty());
+            let socks = Socks5::builder().legacy_fallback(true).build().await;
+            assert!(hostnames(&socks).is_empty());
+        })
+        .await;
+    }
+}
@@ -0,0 +1,510 @@
+//! Continuous monitoring of proxy endpoints.
+//!
+//! A [`Monitor`] periodically probes endpoints and records latency/availability
+//! [`Sample`]s into a [`Store`], which can later be queried for [`Stats`] and [`Trend`]s.
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+use tokio::{sync::Semaphore, task::JoinSet};
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    ProxyInfo, dial,
+    webhook::{Round, Webhook},
+};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Sample {
+    pub hostname: String,
+    pub timestamp: SystemTime,
+    /// `None` if the endpoint could not be reached.
+    pub latency: Option<Duration>,
+}
+
+/// Storage backend for samples recorded by a [`Monitor`].
+pub trait Store: Send + Sync {
+    fn record(&self, sample: Sample);
+    /// All samples for `hostname` taken at or after `since`, oldest first.
+    fn samples(&self, hostname: &str, since: SystemTime) -> Vec<Sample>;
+}
+
+/// In-memory [`Store`], optionally dropping samples older than a retention period.
+#[derive(Default)]
+pub struct MemoryStore {
+    retention: Option<Duration>,
+    samples: Mutex<HashMap<String, Vec<Sample>>>,
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_retention(retention: Duration) -> Self {
+        MemoryStore {
+            retention: Some(retention),
+            ..Default::default()
+        }
+    }
+}
+
+impl Store for MemoryStore {
+    fn record(&self, sample: Sample) {
+        let mut samples = self.samples.lock().unwrap();
+        let entry = samples.entry(sample.hostname.clone()).or_default();
+        if let Some(cutoff) = self.retention.and_then(|v| sample.timestamp.checked_sub(v)) {
+            entry.retain(|v| v.timestamp >= cutoff);
+        }
+        entry.push(sample);
+    }
+
+    fn samples(&self, hostname: &str, since: SystemTime) -> Vec<Sample> {
+        self.samples
+            .lock()
+            .unwrap()
+            .get(hostname)
+            .map(|v| v.iter().filter(|v| v.timestamp >= since).cloned().collect())
+            .unwrap_or_default()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Stats {
+    pub samples: usize,
+    /// Fraction of samples in which the endpoint was reachable, `0.0..=1.0`.
+    pub availability: f64,
+    /// Mean latency of the successful samples.
+    pub mean_latency: Option<Duration>,
+}
+
+impl Stats {
+    pub fn from_samples(samples: &[Sample]) -> Option<Self> {
+        if samples.is_empty() {
+            return None;
+        }
+        let latencies = samples.iter().filter_map(|v| v.latency).collect::<Vec<_>>();
+        Some(Stats {
+            samples: samples.len(),
+            availability: latencies.len() as f64 / samples.len() as f64,
+            mean_latency: (!latencies.is_empty())
+                .then(|| latencies.iter().sum::<Duration>() / latencies.len() as u32),
+        })
+    }
+}
+
+/// Comparison of two consecutive windows of equal length.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Trend {
+    pub previous: Stats,
+    pub current: Stats,
+}
+
+impl Trend {
+    /// Availability dropped by more than 10 percentage points or mean latency grew by more than 50%.
+    pub fn degraded(&self) -> bool {
+        if self.previous.availability - self.current.availability > 0.1 {
+            return true;
+        }
+        match (self.previous.mean_latency, self.current.mean_latency) {
+            (Some(previous), Some(current)) => current > previous + previous / 2,
+            (Some(_), None) => true,
+            _ => false,
+        }
+    }
+}
+
+/// Measures how long it takes to open a TCP connection to the endpoint.
+pub async fn probe(hostname: &str, port: u16, timeout: Duration) -> Option<Duration> {
+    let start = Instant::now();
+    match tokio::time::timeout(timeout, dial::connect(hostname, port)).await {
+        Ok(Ok(_)) => Some(start.elapsed()),
+        _ => None,
+    }
+}
+
+/// The subset of `ports` that `hostname` accepts TCP connections on from this network.
+pub async fn reachable_ports(hostname: &str, ports: &[u16], timeout: Duration) -> Vec<u16> {
+    let mut set = JoinSet::new();
+    for (i, port) in ports.iter().copied().enumerate() {
+        let hostname = hostname.to_string();
+        set.spawn(async move { (i, port, probe(&hostname, port, timeout).await.is_some()) });
+    }
+    let mut reachable = set
+        .join_all()
+        .await
+        .into_iter()
+        .filter(|v| v.2)
+        .collect::<Vec<_>>();
+    reachable.sort_unstable();
+    reachable.into_iter().map(|v| v.1).collect()
+}
+
+/// [`reachable_ports`] of every `(hostname, ports)` of `endpoints`, probing at most
+/// `concurrency` hostnames at once, in the order of `endpoints`.
+#[cfg(any(feature = "socks5", feature = "https-proxy"))]
+pub(crate) async fn reachable_ports_of(
+    endpoints: Vec<(String, Vec<u16>)>,
+    timeout: Duration,
+    concurrency: usize,
+) -> Vec<Vec<u16>> {
+    let semaphore = Arc::new(Semaphore::new(concurrency.max(1)));
+    let mut set = JoinSet::new();
+    for (i, (hostname, ports)) in endpoints.into_iter().enumerate() {
+        let semaphore = semaphore.clone();
+        set.spawn(async move {
+            let _permit = semaphore.acquire().await.unwrap();
+            (i, reachable_ports(&hostname, &ports, timeout).await)
+        });
+    }
+    let mut reachable = set.join_all().await;
+    reachable.sort_unstable_by_key(|v| v.0);
+    reachable.into_iter().map(|v| v.1).collect()
+}
+
+pub struct Monitor<S = MemoryStore> {
+    store: S,
+    interval: Duration,
+    timeout: Duration,
+    concurrency: usize,
+    webhook: Option<Webhook>,
+    shutdown: CancellationToken,
+    half_life: Duration,
+    refreshing: tokio::sync::Mutex<()>,
+}
+
+impl Monitor<MemoryStore> {
+    pub fn new() -> Self {
+        Self::with_store(MemoryStore::new())
+    }
+}
+
+impl Default for Monitor<MemoryStore> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<S: Store> Monitor<S> {
+    pub fn with_store(store: S) -> Self {
+        Monitor {
+            store,
+            interval: Duration::from_secs(300),
+            timeout: Duration::from_secs(5),
+            concurrency: 64,
+            webhook: None,
+            shutdown: CancellationToken::new(),
+            half_life: Duration::from_secs(300),
+            refreshing: tokio::sync::Mutex::new(()),
+        }
+    }
+
+    /// Time between two probe rounds in [`Monitor::run`].
+    pub fn interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Maximum number of endpoints probed at the same time.
+    pub fn concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+
+    /// Notifies `webhook` from [`Monitor::run`] when the healthy capacity degrades.
+    pub fn webhook(mut self, webhook: Webhook) -> Self {
+        self.webhook = Some(webhook);
+        self
+    }
+
+    /// Makes [`Monitor::run`] return once `token` is cancelled. A round in progress is
+    /// finished first, so its samples and alerts aren't lost.
+    pub fn shutdown(mut self, token: CancellationToken) -> Self {
+        self.shutdown = token;
+        self
+    }
+
+    /// Age at which a sample counts half as much towards [`Monitor::latency`] as a new one, and
+    /// after which [`Monitor::sorted_by_latency`] probes the endpoint again. 5 minutes by default.
+    pub fn half_life(mut self, half_life: Duration) -> Self {
+        self.half_life = half_life;
+        self
+    }
+
+    pub fn store(&self) -> &S {
+        &self.store
+    }
+
+    /// Latency of `hostname` averaged over its samples of the last four half-lives, each
+    /// weighted by half for every [`Monitor::half_life`] of age. Failed probes count as the
+    /// probe timeout. `None` without samples or if the latest one failed.
+    pub fn latency(&self, hostname: &str) -> Option<Duration> {
+        let samples = self.store.samples(hostname, ago(self.half_life * 4));
+        samples.last()?.latency?;
+        let (mut sum, mut weights) = (0.0, 0.0);
+        for v in &samples {
+            let age = v.timestamp.elapsed().unwrap_or_default();
+            let weight = 0.5f64.powf(age.as_secs_f64() / self.half_life.as_secs_f64());
+            sum += weight * v.latency.unwrap_or(self.timeout).as_secs_f64();
+            weights += weight;
+        }
+        Some(Duration::from_secs_f64(sum / weights))
+    }
+
+    /// `proxies` by [`Monitor::latency`], lowest first and unreachable ones last.
+    ///
+    /// Only endpoints without samples of the last four half-lives are probed before sorting,
+    /// so repeated calls are cheap. Endpoints whose latest sample is older than one half-life
+    /// are sorted by what is known while being probed again in the background.
+    pub async fn sorted_by_latency<'a>(
+        self: &Arc<Self>,
+        proxies: &'a [ProxyInfo],
+    ) -> Vec<&'a ProxyInfo>
+    where
+        S: 'static,
+    {
+        let (mut missing, mut stale) = (Vec::new(), Vec::new());
+        for v in proxies {
+            match self
+                .store
+                .samples(&v.hostname, ago(self.half_life * 4))
+                .last()
+            {
+                None => missing.push(v.clone()),
+                Some(sample) if sample.timestamp < ago(self.half_life) => stale.push(v.clone()),
+                Some(_) => {}
+            }
+        }
+        if !stale.is_empty() {
+            let monitor = self.clone();
+            tokio::spawn(async move {
+                // Calls meanwhile sort by the old samples instead of probing the same endpoints.
+                if let Ok(_guard) = monitor.refreshing.try_lock() {
+                    debug!("refreshing {} stale latencies", stale.len());
+                    monitor.probe(&stale).await;
+                }
+            });
+        }
+        self.probe(&missing).await;
+        let mut sorted = proxies
+            .iter()
+            .map(|v| (self.latency(&v.hostname), v))
+            .collect::<Vec<_>>();
+        sorted.sort_by_key(|(latency, _)| (latency.is_none(), *latency));
+        sorted.into_iter().map(|(_, v)| v).collect()
+    }
+
+    /// Probes every endpoint once and records the results.
+    ///
+    /// Cancellation-safe: dropping the future aborts the probes still running, keeping the
+    /// samples recorded so far.
+    pub async fn probe(&self, proxies: &[ProxyInfo]) {
+        let semaphore = Arc::new(Semaphore::new(self.concurrency));
+        let mut set = JoinSet::new();
+        for v in proxies {
+            let (hostname, port, timeout) = (v.hostname.clone(), v.port, self.timeout);
+            let semaphore = semaphore.clone();
+            set.spawn(async move {
+                let _permit = semaphore.acquire().await.unwrap();
+                let latency = probe(&hostname, port, timeout).await;
+                Sample {
+                    hostname,
+                    timestamp: SystemTime::now(),
+                    latency,
+                }
+            });
+        }
+        while let Some(sample) = set.join_next().await {
+            if let Ok(sample) = sample {
+                self.store.record(sample);
+            }
+        }
+    }
+
+    /// Probes the endpoints every [`Monitor::interval`] until the [`Monitor::shutdown`] token is
+    /// cancelled.
+    pub async fn run(&self, proxies: &[ProxyInfo]) {
+        let mut previous = None;
+        while !self.shutdown.is_cancelled() {
+            self.probe(proxies).await;
+            debug!("probed {} endpoints", proxies.len());
+            if let Some(webhook) = &self.webhook {
+                let round = self.round(proxies);
+                for alert in webhook.alerts(previous.as_ref(), &round) {
+                    info!("alert: {alert:?}");
+                    if let Err(v) = webhook.send(&alert).await {
+                        warn!("sending webhook alert failed: {v}");
+                    }
+                }
+                previous = Some(round);
+            }
+            let _ = self
+                .shutdown
+                .run_until_cancelled(tokio::time::sleep(self.interval))
+                .await;
+        }
+    }
+
+    fn round(&self, proxies: &[ProxyInfo]) -> Round {
+        let mut round = Round::default();
+        for v in proxies {
+            if self.healthy(&v.hostname) == Some(true) {
+                round.healthy += 1;
+                *round.countries.entry(v.country.clone()).or_default() += 1;
+            }
+        }
+        round
+    }
+
+    /// Whether the latest sample of the last two intervals reached the endpoint,
+    /// `None` if it was not probed in that time.
+    pub fn healthy(&self, hostname: &str) -> Option<bool> {
+        self.store
+            .samples(hostname, ago(self.interval * 2))
+            .last()
+            .map(|v| v.latency.is_some())
+    }
+
+    /// Statistics over the samples of the last `window`.
+    pub fn stats(&self, hostname: &str, window: Duration) -> Option<Stats> {
+        Stats::from_samples(&self.store.samples(hostname, ago(window)))
+    }
+
+    /// Compares the last `window` against the `window` before it,
+    /// e.g. `monitor.trend("de1057.nordvpn.com", WEEK).is_some_and(|v| v.degraded())`.
+    pub fn trend(&self, hostname: &str, window: Duration) -> Option<Trend> {
+        let split = ago(window);
+        let (previous, current): (Vec<_>, Vec<_>) = self
+            .store
+            .samples(hostname, ago(window * 2))
+            .into_iter()
+            .partition(|v| v.timestamp < split);
+        Some(Trend {
+            previous: Stats::from_samples(&previous)?,
+            current: Stats::from_samples(&current)?,
+        })
+    }
+}
+
+fn ago(duration: Duration) -> SystemTime {
+    SystemTime::now()
+        .checked_sub(duration)
+        .unwrap_or(UNIX_EPOCH)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::Arc,
+        time::{Duration, SystemTime},
+    };
+
+    use super::{MemoryStore, Monitor, Sample, Store};
+    use crate::{Country, pool::tests::proxy};
+
+    fn sample(age: u64, latency: Option<u64>) -> Sample {
+        sample_of("de1057.nordvpn.com", age, latency)
+    }
+
+    fn sample_of(hostname: &str, age: u64, latency: Option<u64>) -> Sample {
+        Sample {
+            hostname: hostname.to_string(),
+            timestamp: SystemTime::now() - Duration::from_secs(age),
+            latency: latency.map(Duration::from_millis),
+        }
+    }
+
+    #[test]
+    fn trend() {
+        let monitor = Monitor::new();
+        for v in [
+            sample(150, Some(40)),
+            sample(120, Some(60)),
+            sample(50, Some(100)),
+            sample(20, None),
+        ] {
+            monitor.store().record(v);
+        }
+        let trend = monitor
+            .trend("de1057.nordvpn.com", Duration::from_secs(100))
+            .unwrap();
+        assert_eq!(trend.previous.mean_latency, Some(Duration::from_millis(50)));
+        assert_eq!(trend.current.availability, 0.5);
+        assert!(trend.degraded());
+        assert!(
+            monitor
+                .trend("de1.nordvpn.com", Duration::from_secs(100))
+                .is_none()
+        );
+    }
+
+    #[tokio::test]
+    async fn ports() {
+        let open = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let closed = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let (open_port, closed_port) = (
+            open.local_addr().unwrap().port(),
+            closed.local_addr().unwrap().port(),
+        );
+        drop(closed);
+        assert_eq!(
+            super::reachable_ports(
+                "127.0.0.1",
+                &[closed_port, open_port],
+                Duration::from_secs(1)
+            )
+            .await,
+            [open_port]
+        );
+    }
+
+    #[tokio::test]
+    async fn latency() {
+        let monitor = Arc::new(Monitor::new().half_life(Duration::from_secs(60)));
+        for v in [
+            sample_of("de1.nordvpn.com", 120, Some(10)),
+            sample_of("de1.nordvpn.com", 0, Some(70)),
+            sample_of("de2.nordvpn.com", 10, Some(40)),
+            sample_of("de3.nordvpn.com", 0, None),
+        ] {
+            monitor.store().record(v);
+        }
+        // The sample of two half-lives ago counts a quarter: (10 / 4 + 70) / 1.25.
+        let latency = monitor.latency("de1.nordvpn.com").unwrap();
+        assert!(latency.abs_diff(Duration::from_millis(58)) < Duration::from_millis(1));
+        assert!(monitor.latency("de3.nordvpn.com").is_none());
+
+        let proxies =
+            ["de1", "de2", "de3"].map(|v| proxy(&format!("{v}.nordvpn.com"), Country::DE, 10));
+        let sorted = monitor.sorted_by_latency(&proxies).await;
+        let hostnames = sorted
+            .iter()
+            .map(|v| v.hostname.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            hostnames,
+            ["de2.nordvpn.com", "de1.nordvpn.com", "de3.nordvpn.com"]
+        );
+    }
+
+    #[test]
+    fn retention() {
+        let store = MemoryStore::with_retention(Duration::from_secs(60));
+        store.record(sample(120, Some(10)));
+        store.record(sample(0, Some(10)));
+        assert_eq!(
+            store
+                .samples("de1057.nordvpn.com", SystemTime::UNIX_EPOCH)
+                .len(),
+            1
+        );
+    }
+}
@@ -0,0 +1,1454 @@
+//! Endpoint selection over a list of proxies.
+//!
+//! Bans, usage counters and sticky sessions are kept in a [`State`], which is in-memory by
+//! default and can be shared between processes with the `redis` feature. The in-memory state
+//! can be saved to and restored from a [`StateStore`] to survive restarts.
+use std::{
+    collections::{HashMap, HashSet},
+    fmt, io,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicUsize, Ordering},
+    },
+    time::{Duration, SystemTime},
+};
+
+use reqwest::cookie::Jar;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+use crate::{
+    Country, FilteredProxies, ProxyInfo, SharedCredentials, determinism,
+    geo::{self, Coordinates},
+    monitor::{Monitor, Store},
+    view,
+};
+
+mod accounting;
+mod audit;
+mod bans;
+mod clients;
+mod failover;
+mod profile;
+mod quota;
+mod rate;
+#[cfg(feature = "redis")]
+mod redis;
+mod session;
+mod standby;
+mod store;
+mod weights;
+
+use self::accounting::Accounting;
+pub use self::accounting::{EndpointUsage, UsageReport};
+use self::audit::AuditLog;
+pub use self::audit::{AuditEntry, Reason};
+pub use self::bans::BanStats;
+use self::bans::DomainBans;
+use self::clients::ClientCache;
+pub use self::failover::{BanDetector, Failover, Fetched};
+pub use self::profile::Profile;
+use self::quota::Tracker;
+pub use self::quota::{Quota, Usage};
+use self::rate::Limiter;
+pub use self::rate::Rate;
+#[cfg(feature = "redis")]
+pub use self::redis::RedisState;
+pub use self::session::SessionManager;
+pub use self::standby::Standby;
+pub use self::store::{FileStateStore, PersistedState, StateStore};
+use self::weights::Weights;
+
+pub trait State: Send + Sync {
+    /// Hostnames whose ban has not expired yet.
+    fn banned(&self) -> impl Future<Output = HashSet<String>> + Send;
+    fn ban(&self, hostname: &str, duration: Duration) -> impl Future<Output = ()> + Send;
+    /// Number of times each hostname was handed out.
+    fn usage(&self) -> impl Future<Output = HashMap<String, u64>> + Send;
+    fn increment(&self, hostname: &str) -> impl Future<Output = ()> + Send;
+    fn sticky(&self, session: &str) -> impl Future<Output = Option<String>> + Send;
+    fn set_sticky(
+        &self,
+        session: &str,
+        hostname: &str,
+        ttl: Duration,
+    ) -> impl Future<Output = ()> + Send;
+    fn unstick(&self, session: &str) -> impl Future<Output = ()> + Send;
+}
+
+#[derive(Debug, Default)]
+pub struct MemoryState {
+    inner: Mutex<PersistedState>,
+}
+
+impl MemoryState {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The state last saved to `store`, without the bans and sticky sessions expired since.
+    pub fn load_from(store: &impl StateStore) -> io::Result<Self> {
+        let mut inner = store.load()?;
+        inner.expire(SystemTime::now());
+        inner.domain_bans.clear();
+        inner.stats.clear();
+        Ok(MemoryState {
+            inner: Mutex::new(inner),
+        })
+    }
+
+    /// Saves the bans, usage counters and sticky sessions to `store`, e.g. periodically and on
+    /// shutdown. [`Pool::save_to`] also saves the pool's per-domain bans and usage report.
+    pub fn save_to(&self, store: &impl StateStore) -> io::Result<()> {
+        let mut inner = self.inner.lock().unwrap().clone();
+        inner.expire(SystemTime::now());
+        store.save(&inner)
+    }
+}
+
+impl State for MemoryState {
+    async fn banned(&self) -> HashSet<String> {
+        let now = SystemTime::now();
+        let mut inner = self.inner.lock().unwrap();
+        inner.bans.retain(|_, v| *v > now);
+        inner.bans.keys().cloned().collect()
+    }
+
+    async fn ban(&self, hostname: &str, duration: Duration) {
+        self.inner
+            .lock()
+            .unwrap()
+            .bans
+            .insert(hostname.to_string(), SystemTime::now() + duration);
+    }
+
+    async fn usage(&self) -> HashMap<String, u64> {
+        self.inner.lock().unwrap().usage.clone()
+    }
+
+    async fn increment(&self, hostname: &str) {
+        *self
+            .inner
+            .lock()
+            .unwrap()
+            .usage
+            .entry(hostname.to_string())
+            .or_default() += 1;
+    }
+
+    async fn sticky(&self, session: &str) -> Option<String> {
+        let mut inner = self.inner.lock().unwrap();
+        match inner.sticky.get(session) {
+            Some((hostname, expires)) if *expires > SystemTime::now() => Some(hostname.clone()),
+            Some(_) => {
+                inner.sticky.remove(session);
+                None
+            }
+            None => None,
+        }
+    }
+
+    async fn set_sticky(&self, session: &str, hostname: &str, ttl: Duration) {
+        self.inner.lock().unwrap().sticky.insert(
+            session.to_string(),
+            (hostname.to_string(), SystemTime::now() + ttl),
+        );
+    }
+
+    async fn unstick(&self, session: &str) {
+        self.inner.lock().unwrap().sticky.remove(session);
+    }
+}
+
+/// Lets several pools share one state, e.g. the pool a list refresh replaces and its successor.
+impl<S: State> State for Arc<S> {
+    fn banned(&self) -> impl Future<Output = HashSet<String>> + Send {
+        S::banned(self)
+    }
+
+    fn ban(&self, hostname: &str, duration: Duration) -> impl Future<Output = ()> + Send {
+        S::ban(self, hostname, duration)
+    }
+
+    fn usage(&self) -> impl Future<Output = HashMap<String, u64>> + Send {
+        S::usage(self)
+    }
+
+    fn increment(&self, hostname: &str) -> impl Future<Output = ()> + Send {
+        S::increment(self, hostname)
+    }
+
+    fn sticky(&self, session: &str) -> impl Future<Output = Option<String>> + Send {
+        S::sticky(self, session)
+    }
+
+    fn set_sticky(
+        &self,
+        session: &str,
+        hostname: &str,
+        ttl: Duration,
+    ) -> impl Future<Output = ()> + Send {
+        S::set_sticky(self, session, hostname, ttl)
+    }
+
+    fn unstick(&self, session: &str) -> impl Future<Output = ()> + Send {
+        S::unstick(self, session)
+    }
+}
+
+/// Source of health information for the endpoints of a [`Pool`].
+pub trait Health: Send + Sync {
+    /// `None` if nothing is known about `hostname`.
+    fn healthy(&self, hostname: &str) -> Option<bool>;
+}
+
+impl<S: Store> Health for Monitor<S> {
+    fn healthy(&self, hostname: &str) -> Option<bool> {
+        Monitor::healthy(self, hostname)
+    }
+}
+
+impl<F: Fn(&str) -> Option<bool> + Send + Sync> Health for F {
+    fn healthy(&self, hostname: &str) -> Option<bool> {
+        self(hostname)
+    }
+}
+
+pub struct Pool<S = MemoryState> {
+    proxies: Vec<ProxyInfo>,
+    state: S,
+    health: Option<Arc<dyn Health>>,
+    countries: Vec<Country>,
+    weights: Option<Weights>,
+    quotas: Vec<Quota>,
+    tracker: Tracker,
+    accounts: Vec<SharedCredentials>,
+    rotation: Rotation,
+    next_account: AtomicUsize,
+    max_in_flight: Option<usize>,
+    in_flight: Mutex<HashMap<String, Arc<Semaphore>>>,
+    fetched_at: SystemTime,
+    max_age: Option<Duration>,
+    profiles: Vec<Profile>,
+    clients: Option<ClientCache>,
+    limiter: Option<Limiter>,
+    cookies: Mutex<HashMap<String, Arc<Jar>>>,
+    domain_bans: DomainBans,
+    audit: AuditLog,
+    accounting: Accounting,
+    #[cfg(feature = "stream")]
+    tls: Option<tokio_rustls::rustls::ClientConfig>,
+}
+
+/// One of the [`Pool::max_in_flight`] slots of an endpoint, freed when dropped.
+#[derive(Debug)]
+pub struct InFlight {
+    _permit: Option<OwnedSemaphorePermit>,
+}
+
+/// How [`Pool::proxy_for`] spreads the endpoints over several [`Pool::accounts`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Rotation {
+    /// Every endpoint always uses the same account.
+    #[default]
+    PerProxy,
+    /// Every proxy built uses the next account in turn.
+    PerRequest,
+}
+
+impl Pool<MemoryState> {
+    pub fn new(proxies: Vec<ProxyInfo>) -> Self {
+        Self::with_state(proxies, MemoryState::new())
+    }
+
+    /// Like [`Pool::new`], with the state last saved to `store` by [`Pool::save_to`].
+    pub fn restored(proxies: Vec<ProxyInfo>, store: &impl StateStore) -> io::Result<Self> {
+        let mut saved = store.load()?;
+        saved.expire(SystemTime::now());
+        let domain_bans = std::mem::take(&mut saved.domain_bans);
+        let stats = std::mem::take(&mut saved.stats);
+        let pool = Self::with_state(
+            proxies,
+            MemoryState {
+                inner: Mutex::new(saved),
+            },
+        );
+        pool.domain_bans.restore(&domain_bans);
+        pool.accounting.restore(&stats);
+        Ok(pool)
+    }
+
+    /// Saves the [`MemoryState`], the bans of [`Pool::ban_for`] and the counters of
+    /// [`Pool::usage_report`] to `store`, e.g. periodically and on shutdown.
+    pub fn save_to(&self, store: &impl StateStore) -> io::Result<()> {
+        let mut state = self.state.inner.lock().unwrap().clone();
+        state.expire(SystemTime::now());
+        state.domain_bans = self.domain_bans.persisted();
+        state.stats = self.usage_report().0;
+        store.save(&state)
+    }
+}
+
+impl<S: fmt::Debug> fmt::Debug for Pool<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Pool")
+            .field("proxies", &self.proxies)
+            .field("state", &self.state)
+            .field("countries", &self.countries)
+            .field("weights", &self.weights)
+            .field("quotas", &self.quotas)
+            .field("accounts", &self.accounts)
+            .field("rotation", &self.rotation)
+            .field("max_in_flight", &self.max_in_flight)
+            .field("fetched_at", &self.fetched_at)
+            .field("max_age", &self.max_age)
+            .field("profiles", &self.profiles)
+            .field("clients", &self.clients)
+            .field("limiter", &self.limiter)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<S: State> Pool<S> {
+    pub fn with_state(proxies: Vec<ProxyInfo>, state: S) -> Self {
+        Pool {
+            proxies,
+            state,
+            health: None,
+            countries: Vec::new(),
+            weights: None,
+            quotas: Vec::new(),
+            tracker: Tracker::default(),
+            accounts: Vec::new(),
+            rotation: Rotation::default(),
+            next_account: AtomicUsize::new(0),
+            max_in_flight: None,
+            in_flight: Mutex::default(),
+            fetched_at: SystemTime::now(),
+            max_age: None,
+            profiles: Vec::new(),
+            clients: None,
+            limiter: None,
+            cookies: Mutex::default(),
+            domain_bans: DomainBans::default(),
+            audit: AuditLog::new(256),
+            accounting: Accounting::default(),
+            #[cfg(feature = "stream")]
+            tls: None,
+        }
+    }
+
+    /// Skips endpoints that `health` reports as unhealthy.
+    pub fn health(mut self, health: Arc<dyn Health>) -> Self {
+        self.health = Some(health);
+        self
+    }
+
+    /// Only keeps endpoints whose hostname matches one of the glob `patterns`, e.g.
+    /// `us*.nordvpn.com` or `de105?`, for targets that allow-list specific servers.
+    pub fn hostnames<T: AsRef<str>>(mut self, patterns: impl IntoIterator<Item = T>) -> Self {
+        let patterns = patterns.into_iter().collect::<Vec<_>>();
+        self.proxies.retain(|v| {
+            patterns
+                .iter()
+                .any(|p| view::hostname_matches(p.as_ref(), &v.hostname))
+        });
+        self
+    }
+
+    /// Only hands out endpoints of `countries`, trying them in order and moving on to the next
+    /// country only when the earlier ones have no healthy endpoint left.
+    pub fn prefer_countries(mut self, countries: impl IntoIterator<Item = Country>) -> Self {
+        self.countries = countries.into_iter().collect();
+        self
+    }
+
+    /// Spreads the endpoints handed out over countries by `weights`, where `None` stands for
+    /// every other country, e.g. `[(Some(Country::US), 50), (Some(Country::DE), 30), (None, 20)]`.
+    ///
+    /// Every request goes to the country furthest behind its share among those with an endpoint
+    /// left, so the shares hold over time. Takes precedence over [`Pool::prefer_countries`].
+    pub fn country_weights(
+        mut self,
+        weights: impl IntoIterator<Item = (Option<Country>, u32)>,
+    ) -> Self {
+        self.weights = Some(Weights::new(weights.into_iter().collect()));
+        self
+    }
+
+    /// [`Pool::prefer_countries`] with the `n` countries of the pool nearest to this machine, as
+    /// located by [`Coordinates::detect`]. Leaves the preference unchanged if that fails.
+    pub async fn prefer_nearest(self, n: usize) -> Self {
+        match Coordinates::detect().await {
+            Ok(v) => self.prefer_nearest_to(v, n).await,
+            Err(v) => {
+                warn!("detecting coordinates failed: {v}");
+                self
+            }
+        }
+    }
+
+    /// Like [`Pool::prefer_nearest`], for coordinates from another geo lookup.
+    pub async fn prefer_nearest_to(self, origin: Coordinates, n: usize) -> Self {
+        match geo::nearest_countries(origin, usize::MAX).await {
+            Ok(v) => {
+                let countries = v
+                    .into_iter()
+                    .filter(|v| self.proxies.iter().any(|vv| &vv.country == v))
+                    .take(n)
+                    .collect::<Vec<_>>();
+                self.prefer_countries(countries)
+            }
+            Err(v) => {
+                warn!("listing countries failed: {v}");
+                self
+            }
+        }
+    }
+
+    /// Endpoints over `quota` are skipped until enough of their traffic has left the window.
+    /// Requests are counted when an endpoint is handed out, bytes via [`Pool::record_bytes`].
+    pub fn quota(mut self, quota: Quota) -> Self {
+        self.quotas.push(quota);
+        self
+    }
+
+    /// When the list of the pool was fetched, e.g. the `fetched_at` of a [`Proxy`](crate::Proxy),
+    /// if not just before the pool was created.
+    pub fn fetched_at(mut self, fetched_at: SystemTime) -> Self {
+        self.fetched_at = fetched_at;
+        self
+    }
+
+    /// Age after which the list counts as [outdated](Pool::is_outdated), which makes a gateway
+    /// with a refresh replace the pool.
+    pub fn max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = Some(max_age);
+        self
+    }
+
+    /// Time since the list of the pool was fetched.
+    pub fn age(&self) -> Duration {
+        self.fetched_at.elapsed().unwrap_or_default()
+    }
+
+    pub fn is_outdated(&self) -> bool {
+        self.max_age.is_some_and(|v| self.age() > v)
+    }
+
+    /// Credentials [`Pool::proxy_for`] builds proxies with, so rotated credentials apply
+    /// without rebuilding the pool.
+    pub fn credentials(self, credentials: SharedCredentials) -> Self {
+        self.accounts([credentials], Rotation::PerProxy)
+    }
+
+    /// Like [`Pool::credentials`] for the service credentials of several accounts, spreading
+    /// the endpoints over them according to `rotation`.
+    pub fn accounts(
+        mut self,
+        accounts: impl IntoIterator<Item = impl Into<SharedCredentials>>,
+        rotation: Rotation,
+    ) -> Self {
+        self.accounts = accounts.into_iter().map(Into::into).collect();
+        self.rotation = rotation;
+        self
+    }
+
+    /// The reqwest proxy for `proxy`, using the current credentials of one of the
+    /// [`Pool::accounts`] if set.
+    pub fn proxy_for(&self, proxy: &ProxyInfo) -> reqwest::Proxy {
+        match self.account_for(proxy) {
+            Some(v) => proxy.proxy_with(&v.get()),
+            None => proxy.proxy.clone(),
+        }
+    }
+
+    pub(crate) fn account_for(&self, proxy: &ProxyInfo) -> Option<&SharedCredentials> {
+        if self.accounts.is_empty() {
+            return None;
+        }
+        let i = match self.rotation {
+            Rotation::PerProxy => slot(&proxy.hostname, self.accounts.len()),
+            Rotation::PerRequest => {
+                self.next_account.fetch_add(1, Ordering::Relaxed) % self.accounts.len()
+            }
+        };
+        self.accounts.get(i)
+    }
+
+    /// Header profiles to spread over the endpoints, see [`Pool::profile_for`].
+    pub fn profiles(mut self, profiles: impl IntoIterator<Item = Profile>) -> Self {
+        self.profiles = profiles.into_iter().collect();
+        self
+    }
+
+    /// The profile of [`Pool::profiles`] assigned to `proxy`, the same one every time, so
+    /// clients keep their identity per exit IP.
+    pub fn profile_for(&self, proxy: &ProxyInfo) -> Option<&Profile> {
+        self.profiles
+            .get(slot(&proxy.hostname, self.profiles.len().max(1)))
+    }
+
+    /// Limits the requests in flight through one endpoint to `limit`, counted by the
+    /// [`InFlight`] slots [`Pool::acquire`] hands out. Endpoints without a free slot are skipped.
+    pub fn max_in_flight(mut self, limit: usize) -> Self {
+        self.max_in_flight = Some(limit);
+        self
+    }
+
+    /// Keeps the requests through every endpoint under `rate`, with [`Pool::acquire`] waiting
+    /// for the endpoint it picked to have a token left.
+    pub fn rate_limit(mut self, rate: Rate) -> Self {
+        self.limiter = Some(Limiter::new(rate));
+        self
+    }
+
+    fn semaphore(&self, hostname: &str) -> Option<Arc<Semaphore>> {
+        let limit = self.max_in_flight?;
+        let mut in_flight = self.in_flight.lock().unwrap();
+        Some(
+            in_flight
+                .entry(hostname.to_string())
+                .or_insert_with(|| Arc::new(Semaphore::new(limit)))
+                .clone(),
+        )
+    }
+
+    fn saturated(&self, hostname: &str) -> bool {
+        self.semaphore(hostname)
+            .is_some_and(|v| v.available_permits() == 0)
+    }
+
+    /// Keeps the clients built by [`Pool::client_for`] for reuse, at most `capacity` of them
+    /// and none unused for longer than `idle`.
+    ///
+    /// A cached client keeps the account it was built with, even with
+    /// [`Rotation::PerRequest`].
+    pub fn cache_clients(mut self, capacity: usize, idle: Duration) -> Self {
+        self.clients = Some(ClientCache::new(capacity, idle));
+        self
+    }
+
+    /// Builds the clients of [`Pool::client_for`] with `config`, e.g. a
+    /// [`CertificatePins::tls_config`](crate::stream::CertificatePins::tls_config) so that HTTPS
+    /// endpoints are checked against their pins.
+    #[cfg(feature = "stream")]
+    pub fn tls_config(mut self, config: tokio_rustls::rustls::ClientConfig) -> Self {
+        self.tls = Some(config);
+        self
+    }
+
+    /// A client going through `proxy` with its [`Pool::proxy_for`] credentials,
+    /// [`Pool::profile_for`] headers and [`Pool::cookies_for`] jar.
+    pub fn client_for(&self, proxy: &ProxyInfo) -> reqwest::Result<reqwest::Client> {
+        let build = || {
+            let mut builder = reqwest::Client::builder()
+                .proxy(self.proxy_for(proxy))
+                .cookie_provider(self.cookies_for(proxy));
+            #[cfg(feature = "stream")]
+            if let Some(v) = &self.tls {
+                builder = builder.tls_backend_preconfigured(v.clone());
+            }
+            if let Some(v) = self.profile_for(proxy)
+                && let Ok(headers) = v.headers()
+            {
+                builder = builder.default_headers(headers);
+            }
+            builder.build()
+        };
+        match &self.clients {
+            Some(v) => v.get_or_build(&proxy.hostname, build),
+            None => build(),
+        }
+    }
+
+    /// The cookie jar of `proxy`, used by its [`Pool::client_for`] clients so that cookies
+    /// never cross from one exit to another.
+    pub fn cookies_for(&self, proxy: &ProxyInfo) -> Arc<Jar> {
+        self.cookies
+            .lock()
+            .unwrap()
+            .entry(proxy.hostname.clone())
+            .or_default()
+            .clone()
+    }
+
+    /// Adds `bytes` transferred through `hostname` to its quota usage.
+    pub fn record_bytes(&self, hostname: &str, bytes: u64) {
+        self.accounting.bytes(hostname, bytes);
+        self.track(hostname, Usage { requests: 0, bytes });
+    }
+
+    /// Counts a failed request through `hostname` in the [`Pool::usage_report`].
+    pub fn record_failure(&self, hostname: &str) {
+        self.accounting.failed(hostname);
+    }
+
+    /// Requests, failures, bytes and last use of every endpoint since the pool was created.
+    pub fn usage_report(&self) -> UsageReport {
+        self.accounting
+            .report(self.proxies.iter().map(|v| v.hostname.as_str()))
+    }
+
+    /// Traffic through `hostname` within the last `window`.
+    pub fn usage(&self, hostname: &str, window: Duration) -> Usage {
+        self.tracker.usage(hostname, window)
+    }
+
+    fn track(&self, hostname: &str, usage: Usage) {
+        let retention = self
+            .quotas
+            .iter()
+            .map(|v| v.per)
+            .max()
+            .unwrap_or(Duration::from_secs(3600));
+        self.tracker.add(hostname, usage, retention);
+    }
+
+    fn usable(&self, proxy: &ProxyInfo, banned: &HashSet<String>) -> bool {
+        proxy.status.usable(false)
+            && !banned.contains(&proxy.hostname)
+            && self
+                .health
+                .as_ref()
+                .and_then(|v| v.healthy(&proxy.hostname))
+                != Some(false)
+            && !self.tracker.exceeded(&proxy.hostname, &self.quotas)
+            && !self.saturated(&proxy.hostname)
+    }
+
+    async fn hand_out(&self, proxy: &ProxyInfo) {
+        self.state.increment(&proxy.hostname).await;
+        self.accounting.used(&proxy.hostname);
+        if let Some(weights) = &self.weights {
+            weights.record(&proxy.country);
+        }
+        self.track(
+            &proxy.hostname,
+            Usage {
+                requests: 1,
+                bytes: 0,
+            },
+        );
+    }
+
+    fn available(&self, banned: &HashSet<String>) -> Vec<&ProxyInfo> {
+        let healthy = |v: &&ProxyInfo| self.usable(v, banned);
+        if let Some(weights) = &self.weights {
+            return weights.select(self.proxies.iter().filter(healthy).collect());
+        }
+        if self.countries.is_empty() {
+            return self.proxies.iter().filter(healthy).collect();
+        }
+        self.countries
+            .iter()
+            .map(|c| {
+                self.proxies
+                    .iter()
+                    .filter(|v| &v.country == c)
+                    .filter(healthy)
+                    .collect::<Vec<_>>()
+            })
+            .find(|v| !v.is_empty())
+            .unwrap_or_default()
+    }
+
+    /// Number of endpoints [`Pool::next`] could currently hand out.
+    pub async fn available_count(&self) -> usize {
+        let banned = self.state.banned().await;
+        self.proxies
+            .iter()
+            .filter(|v| self.usable(v, &banned))
+            .count()
+    }
+
+    pub fn proxies(&self) -> &[ProxyInfo] {
+        &self.proxies
+    }
+
+    pub fn view(&self) -> FilteredProxies<'_> {
+        FilteredProxies::new(&self.proxies)
+    }
+
+    pub fn state(&self) -> &S {
+        &self.state
+    }
+
+    /// Hands out the least used endpoint that is healthy and not banned.
+    pub async fn next(&self) -> Option<&ProxyInfo> {
+        let proxy = self.candidate(None).await?;
+        self.hand_out(proxy).await;
+        Some(proxy)
+    }
+
+    /// Like [`Pool::next`], skipping the endpoints banned for `domain` with [`Pool::ban_for`].
+    pub async fn next_for(&self, domain: &str) -> Option<&ProxyInfo> {
+        let mut banned = self.state.banned().await;
+        banned.extend(self.domain_bans.banned(domain));
+        let available = self.available(&banned);
+        let proxy = self.least_used(available).await?;
+        self.hand_out(proxy).await;
+        Some(proxy)
+    }
+
+    /// Like [`Pool::next`], also taking one of the endpoint's [`Pool::max_in_flight`] slots
+    /// for the duration of the request and waiting for its [`Pool::rate_limit`].
+    pub async fn acquire(&self) -> Option<(&ProxyInfo, InFlight)> {
+        self.acquire_from(None).await
+    }
+
+    /// Like [`Pool::acquire`], limited to endpoints in `country` as with [`Pool::next_in`].
+    pub async fn acquire_in(&self, country: &Country) -> Option<(&ProxyInfo, InFlight)> {
+        self.acquire_from(Some(country)).await
+    }
+
+    async fn acquire_from(&self, country: Option<&Country>) -> Option<(&ProxyInfo, InFlight)> {
+        let (proxy, in_flight) = loop {
+            let proxy = self.candidate(country).await?;
+            let Some(semaphore) = self.semaphore(&proxy.hostname) else {
+                break (proxy, InFlight { _permit: None });
+            };
+            // Another task may have taken the last slot since `next` checked it.
+            if let Ok(permit) = semaphore.try_acquire_owned() {
+                break (
+                    proxy,
+                    InFlight {
+                        _permit: Some(permit),
+                    },
+                );
+            }
+        };
+        // Only counted once the slot is taken, so that losing a race does not count as a use.
+        self.hand_out(proxy).await;
+        if let Some(limiter) = &self.limiter {
+            let wait = limiter.reserve(&proxy.hostname);
+            if !wait.is_zero() {
+                tokio::time::sleep(wait).await;
+            }
+        }
+        Some((proxy, in_flight))
+    }
+
+    /// Splits the usable endpoints into `n` disjoint sets, one per worker, each with a similar
+    /// mix of countries and loads, so that workers never share an exit.
+    pub async fn partition(&self, n: usize) -> Vec<Vec<&ProxyInfo>> {
+        if n == 0 {
+            return Vec::new();
+        }
+        let banned = self.state.banned().await;
+        let mut available = self
+            .proxies
+            .iter()
+            .filter(|v| self.usable(v, &banned))
+            .collect::<Vec<_>>();
+        available.sort_by(|a, b| determinism::by_load(a, b));
+        // Every country's endpoints are dealt out least loaded first, carrying on with the next
+        // worker where the previous country stopped.
+        let mut parts = vec![Vec::new(); n];
+        let spread = crate::by_country(available).into_iter().flatten();
+        for (i, v) in spread.enumerate() {
+            parts[i % n].push(v);
+        }
+        parts
+    }
+
+    /// Like [`Pool::next`], limited to endpoints in `country` regardless of
+    /// [`Pool::prefer_countries`].
+    pub async fn next_in(&self, country: &Country) -> Option<&ProxyInfo> {
+        let proxy = self.candidate(Some(country)).await?;
+        self.hand_out(proxy).await;
+        Some(proxy)
+    }
+
+    /// The endpoint [`Pool::next`], or [`Pool::next_in`] with a `country`, would hand out.
+    async fn candidate(&self, country: Option<&Country>) -> Option<&ProxyInfo> {
+        let banned = self.state.banned().await;
+        let available = match country {
+            Some(country) => self
+                .proxies
+                .iter()
+                .filter(|v| &v.country == country && self.usable(v, &banned))
+                .collect(),
+            None => self.available(&banned),
+        };
+        self.least_used(available).await
+    }
+
+    async fn least_used<'a>(&self, available: Vec<&'a ProxyInfo>) -> Option<&'a ProxyInfo> {
+        let usage = self.state.usage().await;
+        available.into_iter().min_by(|a, b| {
+            let used = |v: &ProxyInfo| usage.get(&v.hostname).copied().unwrap_or_default();
+            used(a)
+                .cmp(&used(b))
+                .then_with(|| determinism::by_load(a, b))
+        })
+    }
+
+    /// Returns the endpoint bound to `session`, binding a new one if there is none or it became
+    /// unusable. New bindings are recorded in the [`Pool::audit_log`].
+    pub async fn sticky(&self, session: &str, ttl: Duration) -> Option<&ProxyInfo> {
+        let bound = self.state.sticky(session).await;
+        if let Some(hostname) = &bound
+            && let Some(proxy) = self.get(hostname)
+            && self.usable(proxy, &self.state.banned().await)
+        {
+            self.hand_out(proxy).await;
+            return Some(proxy);
+        }
+        let proxy = self.next().await?;
+        self.state.set_sticky(session, &proxy.hostname, ttl).await;
+        let (from, reason) = match bound {
+            Some(v) if self.get(&v).is_none() => (Some(v), Reason::Removed),
+            Some(v) if self.tracker.exceeded(&v, &self.quotas) => (Some(v), Reason::Quota),
+            Some(v) if self.saturated(&v) => (Some(v), Reason::Saturated),
+            Some(v) => (Some(v), Reason::Failure),
+            None => match self.audit.last(session) {
+                Some((v, true)) => (Some(v), Reason::Ended),
+                Some((v, false)) => (Some(v), Reason::Time),
+                None => (None, Reason::New),
+            },
+        };
+        self.audit.record(session, from, &proxy.hostname, reason);
+        Some(proxy)
+    }
+
+    /// Unbinds `session`, so that its next binding is audited as [`Reason::Ended`].
+    pub(crate) async fn end_sticky(&self, session: &str) {
+        self.state.unstick(session).await;
+        self.audit.end(session);
+    }
+
+    /// Keeps the latest `capacity` entries of the [`Pool::audit_log`], 256 by default.
+    pub fn audit_capacity(mut self, capacity: usize) -> Self {
+        self.audit = AuditLog::new(capacity);
+        self
+    }
+
+    /// Sessions bound to new endpoints by [`Pool::sticky`], oldest first, for finding out why
+    /// a session's exit IP changed.
+    pub fn audit_log(&self) -> Vec<AuditEntry> {
+        self.audit.entries()
+    }
+
+    pub async fn ban(&self, hostname: &str, duration: Duration) {
+        debug!("banning {hostname} for {duration:?}");
+        self.state.ban(hostname, duration).await;
+    }
+
+    /// Bans `hostname` for requests to `domain` only, e.g. after the site blocked it, for the
+    /// [`Pool::domain_cooldown`] of `domain` if there is one and `duration` otherwise.
+    pub fn ban_for(&self, hostname: &str, domain: &str, duration: Duration) {
+        let duration = self.domain_bans.cooldown(domain).unwrap_or(duration);
+        debug!("banning {hostname} for {domain} for {duration:?}");
+        self.domain_bans.ban(hostname, domain, duration);
+    }
+
+    /// How long endpoints blocked by `domain` are banned for it, for sites that forgive
+    /// sooner or later than others.
+    pub fn domain_cooldown(mut self, domain: &str, cooldown: Duration) -> Self {
+        self.domain_bans.set_cooldown(domain, cooldown);
+        self
+    }
+
+    /// Endpoints currently banned for `domain` and the bans so far.
+    pub fn ban_stats(&self, domain: &str) -> BanStats {
+        self.domain_bans.stats(domain)
+    }
+
+    /// Domains `hostname` is currently banned for, sorted.
+    pub fn banned_domains(&self, hostname: &str) -> Vec<String> {
+        self.domain_bans.domains(hostname)
+    }
+
+    pub fn get(&self, hostname: &str) -> Option<&ProxyInfo> {
+        self.proxies.iter().find(|v| v.hostname == hostname)
+    }
+}
+
+/// One of `len` slots, the same for a hostname across runs, releases and platforms, to tie
+/// accounts and profiles to endpoints; the 64-bit FNV-1a hash of the hostname modulo `len`.
+fn slot(hostname: &str, len: usize) -> usize {
+    let hash = hostname.bytes().fold(0xcbf2_9ce4_8422_2325_u64, |hash, v| {
+        (hash ^ u64::from(v)).wrapping_mul(0x0100_0000_01b3)
+    });
+    (hash % len as u64) as usize
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::{
+        collections::{HashMap, HashSet},
+        sync::{
+            Arc,
+            atomic::{AtomicUsize, Ordering},
+        },
+        time::{Duration, SystemTime},
+    };
+
+    use reqwest::{
+        Url,
+        cookie::{CookieStore, Jar},
+    };
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    use super::{
+        BanDetector, FileStateStore, MemoryState, Pool, Profile, Quota, Rate, Reason, Rotation,
+        SessionManager, Standby, State, rate::Limiter,
+    };
+    use crate::{City, Country, Credentials, Load, Protocol, ProxyInfo, Status};
+
+    pub(crate) fn proxy(hostname: &str, country: Country, load: u32) -> ProxyInfo {
+        ProxyInfo {
+            load: Load::new(load),
+            country,
+            city: City::Other(String::new()),
+            hostname: hostname.to_string(),
+            port: 1080,
+            protocol: Protocol::Socks5,
+            status: Status::Online,
+            inferred: false,
+            groups: Default::default(),
+            proxy: reqwest::Proxy::all(format!("socks5h://{hostname}:1080")).unwrap(),
+            credentials: Credentials::new("", ""),
+        }
+    }
+
+    #[tokio::test]
+    async fn rotation() {
+        let pool = Pool::new(vec![
+            proxy("de1.nordvpn.com", Country::DE, 10),
+            proxy("de2.nordvpn.com", Country::DE, 20),
+            proxy("nl1.nordvpn.com", Country::NL, 30),
+        ]);
+        let mut seen = Vec::new();
+        for _ in 0..3 {
+            seen.push(pool.next().await.unwrap().hostname.clone());
+        }
+        assert_eq!(
+            seen,
+            ["de1.nordvpn.com", "de2.nordvpn.com", "nl1.nordvpn.com"]
+        );
+
+        pool.ban("de1.nordvpn.com", Duration::from_secs(60)).await;
+        let sticky = pool
+            .sticky("session", Duration::from_secs(60))
+            .await
+            .unwrap();
+        assert_ne!(sticky.hostname, "de1.nordvpn.com");
+        for _ in 0..3 {
+            assert_eq!(
+                pool.sticky("session", Duration::from_secs(60))
+                    .await
+                    .unwrap()
+                    .hostname,
+                sticky.hostname
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn preferred_countries() {
+        let pool = Pool::new(vec![
+            proxy("nl1.nordvpn.com", Country::NL, 10),
+            proxy("fr1.nordvpn.com", Country::FR, 10),
+            proxy("de1.nordvpn.com", Country::DE, 10),
+        ])
+        .prefer_countries([Country::DE, Country::NL])
+        .health(Arc::new(|v: &str| Some(v != "de1.nordvpn.com")));
+        for _ in 0..3 {
+            assert_eq!(pool.next().await.unwrap().country, Country::NL);
+        }
+        assert_eq!(
+            pool.next_in(&Country::FR).await.unwrap().hostname,
+            "fr1.nordvpn.com"
+        );
+        assert!(pool.next_in(&Country::DE).await.is_none());
+        pool.ban("nl1.nordvpn.com", Duration::from_secs(60)).await;
+        assert!(pool.next().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn country_weights() {
+        let pool = Pool::new(vec![
+            proxy("us1.nordvpn.com", Country::US, 10),
+            proxy("de1.nordvpn.com", Country::DE, 10),
+            proxy("fr1.nordvpn.com", Country::FR, 10),
+            proxy("jp1.nordvpn.com", Country::JP, 10),
+        ])
+        .country_weights([(Some(Country::US), 50), (Some(Country::DE), 30), (None, 20)]);
+        let mut counts = HashMap::new();
+        for _ in 0..100 {
+            *counts
+                .entry(pool.next().await.unwrap().country.clone())
+                .or_insert(0) += 1;
+        }
+        assert_eq!(counts[&Country::US], 50);
+        assert_eq!(counts[&Country::DE], 30);
+        assert_eq!(counts[&Country::FR] + counts[&Country::JP], 20);
+        pool.ban("us1.nordvpn.com", Duration::from_secs(60)).await;
+        assert_eq!(pool.next().await.unwrap().country, Country::DE);
+    }
+
+    #[tokio::test]
+    async fn quota() {
+        let pool = Pool::new(vec![
+            proxy("de1.nordvpn.com", Country::DE, 10),
+            proxy("de2.nordvpn.com", Country::DE, 20),
+        ])
+        .quota(Quota::requests(2, Duration::from_secs(3600)))
+        .quota(Quota::bytes(1000, Duration::from_secs(60)));
+        pool.record_bytes("de2.nordvpn.com", 1000);
+        for _ in 0..2 {
+            assert_eq!(pool.next().await.unwrap().hostname, "de1.nordvpn.com");
+        }
+        assert!(pool.next().await.is_none());
+        assert_eq!(
+            pool.usage("de1.nordvpn.com", Duration::from_secs(60))
+                .requests,
+            2
+        );
+    }
+
+    #[tokio::test]
+    async fn usage_report() {
+        let pool = Pool::new(vec![
+            proxy("de1.nordvpn.com", Country::DE, 10),
+            proxy("de2.nordvpn.com", Country::DE, 20),
+        ]);
+        pool.next().await.unwrap();
+        pool.record_failure("de1.nordvpn.com");
+        pool.record_bytes("de1.nordvpn.com", 512);
+        let report = pool.usage_report();
+        assert_eq!(
+            (
+                report.0[0].requests,
+                report.0[0].failures,
+                report.0[0].bytes
+            ),
+            (1, 1, 512)
+        );
+        assert!(report.0[0].last_used.is_some() && report.0[1].last_used.is_none());
+        let csv = report.to_csv();
+        assert!(
+            csv.starts_with("hostname,requests,failures,bytes,last_used\nde1.nordvpn.com,1,1,512,")
+        );
+        assert!(csv.ends_with("\nde2.nordvpn.com,0,0,0,\n"));
+        let text = report.to_prometheus();
+        assert!(text.contains("nord_proxy_failures_total{hostname=\"de1.nordvpn.com\"} 1\n"));
+        assert!(!text.contains("nord_proxy_last_used_seconds{hostname=\"de2.nordvpn.com\"}"));
+    }
+
+    #[test]
+    fn accounts() {
+        let accounts = [Credentials::new("a", "1"), Credentials::new("b", "2")];
+        let de = proxy("de1.nordvpn.com", Country::DE, 10);
+        let pool = Pool::new(vec![de.clone()]).accounts(accounts.clone(), Rotation::PerProxy);
+        let first = pool.account_for(&de).unwrap().get();
+        for _ in 0..3 {
+            assert_eq!(pool.account_for(&de).unwrap().get(), first);
+        }
+        let pool = Pool::new(vec![de.clone()]).accounts(accounts, Rotation::PerRequest);
+        let used = (0..4)
+            .map(|_| pool.account_for(&de).unwrap().get().username().to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(used, ["a", "b", "a", "b"]);
+        assert!(Pool::new(vec![de.clone()]).account_for(&de).is_none());
+    }
+
+    #[tokio::test]
+    async fn in_flight() {
+        let pool = Pool::new(vec![
+            proxy("de1.nordvpn.com", Country::DE, 10),
+            proxy("de2.nordvpn.com", Country::DE, 20),
+        ])
+        .max_in_flight(1);
+        let (first, slot) = pool.acquire().await.unwrap();
+        let (second, _slot) = pool.acquire().await.unwrap();
+        assert_ne!(first.hostname, second.hostname);
+        assert!(pool.acquire().await.is_none());
+        let requests = |pool: &Pool| {
+            pool.usage_report()
+                .0
+                .into_iter()
+                .map(|v| v.requests)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(requests(&pool), [1, 1]);
+        drop(slot);
+        assert_eq!(pool.acquire().await.unwrap().0.hostname, first.hostname);
+        assert_eq!(requests(&pool), [2, 1]);
+        assert!(pool.acquire_in(&Country::NL).await.is_none());
+    }
+
+    #[tokio::test]
+    async fn partition() {
+        let pool = Pool::new(vec![
+            proxy("de1.nordvpn.com", Country::DE, 10),
+            proxy("de2.nordvpn.com", Country::DE, 20),
+            proxy("de3.nordvpn.com", Country::DE, 30),
+            proxy("nl1.nordvpn.com", Country::NL, 15),
+            proxy("nl2.nordvpn.com", Country::NL, 25),
+        ]);
+        let parts = pool.partition(2).await;
+        let hostnames = |i: usize| {
+            parts[i]
+                .iter()
+                .map(|v| v.hostname.as_str())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            hostnames(0),
+            ["de1.nordvpn.com", "de3.nordvpn.com", "nl2.nordvpn.com"]
+        );
+        assert_eq!(hostnames(1), ["de2.nordvpn.com", "nl1.nordvpn.com"]);
+        assert!(pool.partition(0).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn rate_limit() {
+        let limiter = Limiter::new(Rate::per_second(2));
+        assert_eq!(limiter.reserve("de1.nordvpn.com"), Duration::ZERO);
+        assert_eq!(limiter.reserve("de1.nordvpn.com"), Duration::ZERO);
+        let wait = limiter.reserve("de1.nordvpn.com");
+        assert!(wait > Duration::from_millis(400) && wait <= Duration::from_millis(500));
+        assert_eq!(limiter.reserve("de2.nordvpn.com"), Duration::ZERO);
+
+        let pool = Pool::new(vec![proxy("de1.nordvpn.com", Country::DE, 10)])
+            .rate_limit(Rate::per_second(1000));
+        for _ in 0..3 {
+            assert!(pool.acquire().await.is_some());
+        }
+    }
+
+    #[test]
+    fn outdated() {
+        let hour_ago = SystemTime::now() - Duration::from_secs(3600);
+        let pool = Pool::new(Vec::new()).fetched_at(hour_ago);
+        assert!(pool.age() >= Duration::from_secs(3600));
+        assert!(!pool.is_outdated());
+        assert!(pool.max_age(Duration::from_secs(600)).is_outdated());
+        assert!(
+            !Pool::new(Vec::new())
+                .max_age(Duration::from_secs(600))
+                .is_outdated()
+        );
+    }
+
+    #[test]
+    fn slot() {
+        // Pinned, as a different hash would move every endpoint to another account and profile.
+        assert_eq!(super::slot("de1.nordvpn.com", 7), 6);
+        assert_eq!(super::slot("us9591.nordvpn.com", 7), 1);
+        assert_eq!(super::slot("", 3), 2);
+    }
+
+    #[test]
+    fn profiles() {
+        let proxies = (0..8)
+            .map(|i| proxy(&format!("de{i}.nordvpn.com"), Country::DE, 10))
+            .collect::<Vec<_>>();
+        let pool = Pool::new(proxies.clone());
+        assert!(pool.profile_for(&proxies[0]).is_none());
+        let pool = pool.profiles([
+            Profile::new("Mozilla/5.0 (X11; Linux x86_64)", "de-DE,de;q=0.9"),
+            Profile::new("Mozilla/5.0 (Macintosh)", "en-US,en;q=0.9"),
+        ]);
+        let first = pool.profile_for(&proxies[0]).unwrap();
+        assert_eq!(pool.profile_for(&proxies[0]), Some(first));
+        assert!(proxies.iter().any(|v| pool.profile_for(v) != Some(first)));
+        assert_eq!(first.headers().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn client_cache() {
+        let proxies = (0..3)
+            .map(|i| proxy(&format!("de{i}.nordvpn.com"), Country::DE, 10))
+            .collect::<Vec<_>>();
+        let pool = Pool::new(proxies.clone()).cache_clients(2, Duration::from_secs(60));
+        for v in &proxies {
+            pool.client_for(v).unwrap();
+        }
+        pool.client_for(&proxies[2]).unwrap();
+        assert_eq!(pool.clients.as_ref().unwrap().len(), 2);
+
+        let pool = Pool::new(proxies.clone()).cache_clients(10, Duration::ZERO);
+        for v in &proxies {
+            pool.client_for(v).unwrap();
+        }
+        assert_eq!(pool.clients.as_ref().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn persisted_state() {
+        let path = std::env::temp_dir().join(format!("nord-proxy-state-{}", std::process::id()));
+        let store = FileStateStore::new(&path);
+        let state = MemoryState::load_from(&store).unwrap();
+        state.ban("de1.nordvpn.com", Duration::from_secs(60)).await;
+        state.ban("de2.nordvpn.com", Duration::ZERO).await;
+        state.increment("de3.nordvpn.com").await;
+        state
+            .set_sticky("login", "de3.nordvpn.com", Duration::from_secs(60))
+            .await;
+        state.save_to(&store).unwrap();
+
+        let restored = MemoryState::load_from(&store).unwrap();
+        assert_eq!(
+            restored.banned().await,
+            HashSet::from(["de1.nordvpn.com".to_string()])
+        );
+        assert_eq!(restored.usage().await["de3.nordvpn.com"], 1);
+        assert_eq!(
+            restored.sticky("login").await.as_deref(),
+            Some("de3.nordvpn.com")
+        );
+
+        let proxies = vec![
+            proxy("de1.nordvpn.com", Country::DE, 10),
+            proxy("de2.nordvpn.com", Country::DE, 20),
+        ];
+        let pool = Pool::restored(proxies.clone(), &store).unwrap();
+        pool.ban_for("de1.nordvpn.com", "example.com", Duration::from_secs(60));
+        pool.next().await.unwrap();
+        pool.record_failure("de2.nordvpn.com");
+        pool.save_to(&store).unwrap();
+
+        let restored = Pool::restored(proxies, &store).unwrap();
+        assert_eq!(restored.ban_stats("example.com").banned, 1);
+        assert_eq!(restored.usage_report(), pool.usage_report());
+        assert!(restored.state().banned().await.contains("de1.nordvpn.com"));
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn sessions() {
+        let pool = Arc::new(Pool::new(vec![
+            proxy("de1.nordvpn.com", Country::DE, 10),
+            proxy("de2.nordvpn.com", Country::DE, 20),
+        ]));
+        let bound = Arc::new(AtomicUsize::new(0));
+        let counter = bound.clone();
+        let sessions = SessionManager::new(pool, Duration::from_secs(60)).on_bind(move |_, _| {
+            counter.fetch_add(1, Ordering::Relaxed);
+        });
+        let first = sessions.get("login").await.unwrap().hostname.clone();
+        for _ in 0..3 {
+            assert_eq!(sessions.get("login").await.unwrap().hostname, first);
+        }
+        assert_eq!(bound.load(Ordering::Relaxed), 1);
+
+        sessions.end("login").await;
+        sessions.get("login").await.unwrap();
+        assert_eq!(bound.load(Ordering::Relaxed), 2);
+
+        assert!(sessions.restore("login", "de2.nordvpn.com").await);
+        assert!(!sessions.restore("login", "nl1.nordvpn.com").await);
+        let proxy = sessions.get("login").await.unwrap();
+        assert_eq!(proxy.hostname, "de2.nordvpn.com");
+
+        sessions
+            .pool()
+            .ban("de2.nordvpn.com", Duration::from_secs(60))
+            .await;
+        sessions.get("login").await.unwrap();
+        let reasons = sessions
+            .pool()
+            .audit_log()
+            .into_iter()
+            .map(|v| (v.from, v.reason))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            reasons,
+            [
+                (None, Reason::New),
+                (Some(first), Reason::Ended),
+                (Some("de2.nordvpn.com".to_string()), Reason::Failure),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn sticky_reasons() {
+        let pool = Pool::new(vec![
+            proxy("de1.nordvpn.com", Country::DE, 10),
+            proxy("de2.nordvpn.com", Country::DE, 20),
+        ])
+        .max_in_flight(1);
+        let ttl = Duration::from_secs(60);
+        assert_eq!(
+            pool.sticky("login", ttl).await.unwrap().hostname,
+            "de1.nordvpn.com"
+        );
+        let _slot = pool
+            .semaphore("de1.nordvpn.com")
+            .unwrap()
+            .try_acquire_owned()
+            .unwrap();
+        assert_eq!(
+            pool.sticky("login", ttl).await.unwrap().hostname,
+            "de2.nordvpn.com"
+        );
+
+        pool.state()
+            .set_sticky("search", "gone.nordvpn.com", ttl)
+            .await;
+        pool.sticky("search", ttl).await.unwrap();
+        let reasons = pool
+            .audit_log()
+            .into_iter()
+            .map(|v| (v.session, v.reason))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            reasons,
+            [
+                ("login".to_string(), Reason::New),
+                ("login".to_string(), Reason::Saturated),
+                ("search".to_string(), Reason::Removed),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn domain_bans() {
+        let pool = Pool::new(vec![
+            proxy("de1.nordvpn.com", Country::DE, 10),
+            proxy("de2.nordvpn.com", Country::DE, 20),
+        ]);
+        let pool = pool.domain_cooldown("strict.com", Duration::ZERO);
+        pool.ban_for("de1.nordvpn.com", "Example.com", Duration::from_secs(60));
+        pool.ban_for("de1.nordvpn.com", "strict.com", Duration::from_secs(60));
+        let stats = pool.ban_stats("example.com");
+        assert_eq!((stats.banned, stats.total), (1, 1));
+        assert_eq!(pool.ban_stats("strict.com").banned, 0);
+        assert_eq!(pool.banned_domains("de1.nordvpn.com"), ["example.com"]);
+        for _ in 0..3 {
+            let proxy = pool.next_for("example.com").await.unwrap();
+            assert_eq!(proxy.hostname, "de2.nordvpn.com");
+        }
+        assert_eq!(
+            pool.next_for("other.com").await.unwrap().hostname,
+            "de1.nordvpn.com"
+        );
+
+        let detector = |status: reqwest::StatusCode, body: &[u8]| {
+            status == reqwest::StatusCode::FORBIDDEN || body.windows(7).any(|v| v == b"captcha")
+        };
+        assert!(detector.banned(reqwest::StatusCode::OK, b"<div id=captcha>"));
+        assert!(!detector.banned(reqwest::StatusCode::OK, b"<html>"));
+    }
+
+    #[tokio::test]
+    async fn standby_refill() {
+        let listener = tokio::net::TcpListener::bind("0.0.0.0:0").await.unwrap();
+        let unused = tokio::net::TcpListener::bind("0.0.0.0:0").await.unwrap();
+        let (open, closed) = (
+            listener.local_addr().unwrap().port(),
+            unused.local_addr().unwrap().port(),
+        );
+        drop(unused);
+        let proxies = [
+            ("127.0.0.1", 10, closed),
+            ("127.0.0.2", 20, closed),
+            ("127.0.0.3", 30, open),
+            ("127.0.0.4", 40, open),
+            ("127.0.0.5", 50, open),
+        ]
+        .map(|(hostname, load, port)| {
+            let mut v = proxy(hostname, Country::DE, load);
+            v.port = port;
+            v
+        });
+        let standby = Standby::new(Arc::new(Pool::new(proxies.to_vec())), 2)
+            .timeout(Duration::from_millis(500))
+            .concurrency(2);
+        standby.refill().await;
+        let hostnames = standby
+            .standby(&Country::DE)
+            .iter()
+            .map(|v| v.hostname.clone())
+            .collect::<Vec<_>>();
+        assert_eq!(hostnames, ["127.0.0.3", "127.0.0.4"]);
+    }
+
+    #[tokio::test]
+    async fn standby() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let closed = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let mut up = proxy("127.0.0.1", Country::DE, 20);
+        up.port = listener.local_addr().unwrap().port();
+        let mut down = proxy("localhost", Country::DE, 10);
+        down.port = closed.local_addr().unwrap().port();
+        drop(closed);
+        let standby = Standby::new(Arc::new(Pool::new(vec![up, down])), 2)
+            .timeout(Duration::from_millis(500));
+        standby.refill().await;
+        let hostnames =
+            |v: Vec<&ProxyInfo>| v.iter().map(|v| v.hostname.clone()).collect::<Vec<_>>();
+        assert_eq!(hostnames(standby.standby(&Country::DE)), ["127.0.0.1"]);
+
+        let active = standby.pool().get("localhost").unwrap();
+        let promoted = standby
+            .failover(active, Duration::from_secs(60))
+            .await
+            .unwrap();
+        assert_eq!(promoted.hostname, "127.0.0.1");
+        assert!(standby.standby(&Country::DE).is_empty());
+        assert_eq!(standby.pool().usage_report().0[1].failures, 1);
+        standby.refill().await;
+        assert_eq!(hostnames(standby.standby(&Country::DE)), ["127.0.0.1"]);
+        standby
+            .pool()
+            .ban("127.0.0.1", Duration::from_secs(60))
+            .await;
+        assert!(standby.promote(&Country::DE).await.is_none());
+    }
+
+    #[tokio::test]
+    async fn cookies() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let mut buf = [0; 1024];
+                let _ = stream.read(&mut buf).await;
+                let _ = stream
+                    .write_all(
+                        b"HTTP/1.1 200 OK\r\n\
+                        Set-Cookie: session=abc; Path=/account\r\n\
+                        Set-Cookie: stale=1; Expires=Thu, 01 Jan 1970 00:00:00 GMT\r\n\
+                        Content-Length: 0\r\nConnection: close\r\n\r\n",
+                    )
+                    .await;
+            }
+        });
+        let mut proxies = [
+            proxy("de1.nordvpn.com", Country::DE, 10),
+            proxy("de2.nordvpn.com", Country::DE, 20),
+        ];
+        proxies[0].proxy = reqwest::Proxy::http(format!("http://{addr}")).unwrap();
+        let pool = Pool::new(proxies.to_vec());
+        let client = pool.client_for(&proxies[0]).unwrap();
+        client
+            .get("http://example.com/account/login")
+            .send()
+            .await
+            .unwrap();
+
+        let jar = pool.cookies_for(&proxies[0]);
+        let cookies = |jar: &Jar, url| {
+            jar.cookies(&Url::parse(url).unwrap())
+                .map(|v| v.to_str().unwrap().to_string())
+        };
+        assert_eq!(
+            cookies(&jar, "http://example.com/account/settings").unwrap(),
+            "session=abc"
+        );
+        assert!(cookies(&jar, "http://example.com/").is_none());
+        assert!(Arc::ptr_eq(&jar, &pool.cookies_for(&proxies[0])));
+        assert!(
+            cookies(
+                &pool.cookies_for(&proxies[1]),
+                "http://example.com/account/settings"
+            )
+            .is_none()
+        );
+    }
+}
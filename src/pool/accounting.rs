@@ -0,0 +1,171 @@
+use std::{
+    collections::HashMap,
+    fmt::Write,
+    sync::Mutex,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+
+/// How one endpoint was used since the pool was created, see
+/// [`Pool::usage_report`](super::Pool::usage_report).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EndpointUsage {
+    pub hostname: String,
+    /// Times the endpoint was handed out.
+    pub requests: u64,
+    /// Failures reported with [`Pool::record_failure`](super::Pool::record_failure).
+    pub failures: u64,
+    /// Bytes reported with [`Pool::record_bytes`](super::Pool::record_bytes).
+    pub bytes: u64,
+    pub last_used: Option<SystemTime>,
+}
+
+/// Usage of every endpoint of a pool, for billing and compliance reviews.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(transparent)]
+pub struct UsageReport(pub Vec<EndpointUsage>);
+
+impl UsageReport {
+    /// `hostname,requests,failures,bytes,last_used` with a header row, `last_used` in seconds
+    /// since the epoch and empty if never used.
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from("hostname,requests,failures,bytes,last_used\n");
+        for v in &self.0 {
+            let last_used = v.last_used.map(unix).map(|v| v.to_string());
+            writeln!(
+                csv,
+                "{},{},{},{},{}",
+                v.hostname,
+                v.requests,
+                v.failures,
+                v.bytes,
+                last_used.unwrap_or_default()
+            )
+            .unwrap();
+        }
+        csv
+    }
+
+    /// The counters in the Prometheus text exposition format, labelled by hostname.
+    pub fn to_prometheus(&self) -> String {
+        let mut text = String::new();
+        let metrics: [(&str, &str, &str, Metric); 4] = [
+            (
+                "nord_proxy_requests_total",
+                "counter",
+                "Times the endpoint was handed out.",
+                |v| Some(v.requests),
+            ),
+            (
+                "nord_proxy_failures_total",
+                "counter",
+                "Failures reported for the endpoint.",
+                |v| Some(v.failures),
+            ),
+            (
+                "nord_proxy_bytes_total",
+                "counter",
+                "Bytes relayed through the endpoint.",
+                |v| Some(v.bytes),
+            ),
+            (
+                "nord_proxy_last_used_seconds",
+                "gauge",
+                "When the endpoint was last handed out, in seconds since the epoch.",
+                |v| v.last_used.map(unix),
+            ),
+        ];
+        for (name, kind, help, value) in metrics {
+            writeln!(text, "# HELP {name} {help}\n# TYPE {name} {kind}").unwrap();
+            for v in &self.0 {
+                if let Some(value) = value(v) {
+                    writeln!(text, "{name}{{hostname=\"{}\"}} {value}", v.hostname).unwrap();
+                }
+            }
+        }
+        text
+    }
+}
+
+type Metric = fn(&EndpointUsage) -> Option<u64>;
+
+fn unix(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+struct Counters {
+    requests: u64,
+    failures: u64,
+    bytes: u64,
+    last_used: Option<SystemTime>,
+}
+
+/// Totals per hostname, unlike the windowed counts of the quota tracker.
+#[derive(Debug, Default)]
+pub(crate) struct Accounting {
+    counters: Mutex<HashMap<String, Counters>>,
+}
+
+impl Accounting {
+    fn update(&self, hostname: &str, f: impl FnOnce(&mut Counters)) {
+        f(self
+            .counters
+            .lock()
+            .unwrap()
+            .entry(hostname.to_string())
+            .or_default());
+    }
+
+    pub(crate) fn used(&self, hostname: &str) {
+        self.update(hostname, |v| {
+            v.requests += 1;
+            v.last_used = Some(SystemTime::now());
+        });
+    }
+
+    pub(crate) fn failed(&self, hostname: &str) {
+        self.update(hostname, |v| v.failures += 1);
+    }
+
+    pub(crate) fn bytes(&self, hostname: &str, bytes: u64) {
+        self.update(hostname, |v| v.bytes += bytes);
+    }
+
+    /// Replaces the counters of the endpoints in `stats`, e.g. with the ones saved last run.
+    pub(crate) fn restore(&self, stats: &[EndpointUsage]) {
+        let mut counters = self.counters.lock().unwrap();
+        for v in stats {
+            counters.insert(
+                v.hostname.clone(),
+                Counters {
+                    requests: v.requests,
+                    failures: v.failures,
+                    bytes: v.bytes,
+                    last_used: v.last_used,
+                },
+            );
+        }
+    }
+
+    pub(crate) fn report<'a>(&self, hostnames: impl Iterator<Item = &'a str>) -> UsageReport {
+        let counters = self.counters.lock().unwrap();
+        UsageReport(
+            hostnames
+                .map(|hostname| {
+                    let v = counters.get(hostname).copied().unwrap_or_default();
+                    EndpointUsage {
+                        hostname: hostname.to_string(),
+                        requests: v.requests,
+                        failures: v.failures,
+                        bytes: v.bytes,
+                        last_used: v.last_used,
+                    }
+                })
+                .collect(),
+        )
+    }
+}
@@ -0,0 +1,112 @@
+use std::{collections::VecDeque, sync::Mutex, time::SystemTime};
+
+use serde::Serialize;
+
+/// Why a session was bound to another endpoint.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Reason {
+    /// The session had no endpoint before.
+    New,
+    /// Its endpoint was banned, reported unhealthy or went offline.
+    Failure,
+    /// Its endpoint had no [`InFlight`](super::InFlight) slot left.
+    Saturated,
+    /// Its endpoint is no longer in the pool, e.g. after a list refresh.
+    Removed,
+    /// Its endpoint ran out of [`Quota`](super::Quota).
+    Quota,
+    /// Its binding expired.
+    Time,
+    /// It was ended with [`SessionManager::end`](super::SessionManager::end).
+    Ended,
+}
+
+/// One binding of a sticky session, see [`Pool::audit_log`](super::Pool::audit_log).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct AuditEntry {
+    pub at: SystemTime,
+    pub session: String,
+    pub from: Option<String>,
+    pub to: String,
+    pub reason: Reason,
+}
+
+/// The latest [`AuditEntry`]s, oldest dropped first.
+#[derive(Debug)]
+pub(crate) struct AuditLog {
+    capacity: usize,
+    entries: Mutex<VecDeque<Logged>>,
+}
+
+#[derive(Debug)]
+struct Logged {
+    entry: AuditEntry,
+    /// Whether the binding was ended on purpose rather than left to expire.
+    ended: bool,
+}
+
+impl AuditLog {
+    pub(crate) fn new(capacity: usize) -> Self {
+        AuditLog {
+            capacity,
+            entries: Mutex::default(),
+        }
+    }
+
+    pub(crate) fn record(&self, session: &str, from: Option<String>, to: &str, reason: Reason) {
+        debug!(
+            "session {session} moved from {} to {to}: {reason:?}",
+            from.as_deref().unwrap_or("nowhere")
+        );
+        let mut entries = self.entries.lock().unwrap();
+        if entries.len() >= self.capacity {
+            entries.pop_front();
+        }
+        if self.capacity > 0 {
+            entries.push_back(Logged {
+                entry: AuditEntry {
+                    at: SystemTime::now(),
+                    session: session.to_string(),
+                    from,
+                    to: to.to_string(),
+                    reason,
+                },
+                ended: false,
+            });
+        }
+    }
+
+    /// Notes that the last binding of `session` was ended on purpose.
+    pub(crate) fn end(&self, session: &str) {
+        let mut entries = self.entries.lock().unwrap();
+        if let Some(v) = entries
+            .iter_mut()
+            .rev()
+            .find(|v| v.entry.session == session)
+        {
+            v.ended = true;
+        }
+    }
+
+    /// Endpoint `session` was last bound to, as far as the log goes back, and whether that
+    /// binding was ended.
+    pub(crate) fn last(&self, session: &str) -> Option<(String, bool)> {
+        self.entries
+            .lock()
+            .unwrap()
+            .iter()
+            .rev()
+            .find(|v| v.entry.session == session)
+            .map(|v| (v.entry.to.clone(), v.ended))
+    }
+
+    pub(crate) fn entries(&self) -> Vec<AuditEntry> {
+        self.entries
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|v| v.entry.clone())
+            .collect()
+    }
+}
@@ -0,0 +1,114 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Mutex,
+    time::{Duration, Instant, SystemTime},
+};
+
+/// Endpoints blocked by one target domain, which stay usable for all others.
+#[derive(Debug, Default)]
+pub(crate) struct DomainBans {
+    domains: Mutex<HashMap<String, Domain>>,
+    cooldowns: HashMap<String, Duration>,
+}
+
+#[derive(Debug, Default)]
+struct Domain {
+    /// Expiry by hostname.
+    bans: HashMap<String, Instant>,
+    total: u64,
+}
+
+/// How often endpoints were blocked by a domain, see [`Pool::ban_stats`](super::Pool::ban_stats).
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct BanStats {
+    /// Endpoints currently banned for the domain.
+    pub banned: usize,
+    /// Bans since the pool was created, including expired ones.
+    pub total: u64,
+}
+
+impl DomainBans {
+    pub(crate) fn set_cooldown(&mut self, domain: &str, cooldown: Duration) {
+        self.cooldowns.insert(domain.to_ascii_lowercase(), cooldown);
+    }
+
+    pub(crate) fn cooldown(&self, domain: &str) -> Option<Duration> {
+        self.cooldowns.get(&domain.to_ascii_lowercase()).copied()
+    }
+
+    pub(crate) fn ban(&self, hostname: &str, domain: &str, duration: Duration) {
+        let mut domains = self.domains.lock().unwrap();
+        let domain = domains.entry(domain.to_ascii_lowercase()).or_default();
+        domain
+            .bans
+            .insert(hostname.to_string(), Instant::now() + duration);
+        domain.total += 1;
+    }
+
+    /// Hostnames whose ban for `domain` has not expired yet.
+    pub(crate) fn banned(&self, domain: &str) -> HashSet<String> {
+        self.with(domain, |v| v.bans.keys().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    pub(crate) fn stats(&self, domain: &str) -> BanStats {
+        self.with(domain, |v| BanStats {
+            banned: v.bans.len(),
+            total: v.total,
+        })
+        .unwrap_or_default()
+    }
+
+    /// Domains `hostname` is currently banned for.
+    pub(crate) fn domains(&self, hostname: &str) -> Vec<String> {
+        let now = Instant::now();
+        let domains = self.domains.lock().unwrap();
+        let mut blocked = domains
+            .iter()
+            .filter(|(_, v)| v.bans.get(hostname).is_some_and(|v| *v > now))
+            .map(|(k, _)| k.clone())
+            .collect::<Vec<_>>();
+        blocked.sort();
+        blocked
+    }
+
+    /// The bans that have not expired yet by domain, with their expiry as wall-clock time.
+    pub(crate) fn persisted(&self) -> HashMap<String, HashMap<String, SystemTime>> {
+        let (now, wall) = (Instant::now(), SystemTime::now());
+        let domains = self.domains.lock().unwrap();
+        domains
+            .iter()
+            .map(|(domain, v)| {
+                let bans = v
+                    .bans
+                    .iter()
+                    .filter(|(_, v)| **v > now)
+                    .map(|(hostname, v)| (hostname.clone(), wall + (*v - now)))
+                    .collect::<HashMap<_, _>>();
+                (domain.clone(), bans)
+            })
+            .filter(|(_, v)| !v.is_empty())
+            .collect()
+    }
+
+    /// Bans `hostname` for `domain` until a wall-clock expiry from [`DomainBans::persisted`].
+    pub(crate) fn restore(&self, bans: &HashMap<String, HashMap<String, SystemTime>>) {
+        let now = SystemTime::now();
+        for (domain, bans) in bans {
+            for (hostname, expires) in bans {
+                if let Ok(left) = expires.duration_since(now) {
+                    self.ban(hostname, domain, left);
+                }
+            }
+        }
+    }
+
+    /// Calls `f` with the bookkeeping of `domain` after forgetting its expired bans.
+    fn with<T>(&self, domain: &str, f: impl FnOnce(&Domain) -> T) -> Option<T> {
+        let now = Instant::now();
+        let mut domains = self.domains.lock().unwrap();
+        let domain = domains.get_mut(&domain.to_ascii_lowercase())?;
+        domain.bans.retain(|_, v| *v > now);
+        Some(f(domain))
+    }
+}
@@ -0,0 +1,54 @@
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+/// Clients built for endpoints, least recently used ones dropped first, so their connection
+/// pools don't pile up when rotating over thousands of endpoints.
+#[derive(Debug)]
+pub(crate) struct ClientCache {
+    capacity: usize,
+    idle: Duration,
+    entries: Mutex<HashMap<String, (reqwest::Client, Instant)>>,
+}
+
+impl ClientCache {
+    pub(crate) fn new(capacity: usize, idle: Duration) -> Self {
+        ClientCache {
+            capacity: capacity.max(1),
+            idle,
+            entries: Mutex::default(),
+        }
+    }
+
+    pub(crate) fn get_or_build(
+        &self,
+        hostname: &str,
+        build: impl FnOnce() -> reqwest::Result<reqwest::Client>,
+    ) -> reqwest::Result<reqwest::Client> {
+        let now = Instant::now();
+        let mut entries = self.entries.lock().unwrap();
+        if let Some((client, used)) = entries.get_mut(hostname) {
+            *used = now;
+            return Ok(client.clone());
+        }
+        let client = build()?;
+        entries.retain(|_, (_, used)| now.duration_since(*used) < self.idle);
+        if entries.len() >= self.capacity
+            && let Some(oldest) = entries
+                .iter()
+                .min_by_key(|(_, (_, used))| *used)
+                .map(|(k, _)| k.clone())
+        {
+            entries.remove(&oldest);
+        }
+        entries.insert(hostname.to_string(), (client.clone(), now));
+        Ok(client)
+    }
+
+    #[cfg(test)]
+    pub(crate) fn len(&self) -> usize {
+        self.entries.lock().unwrap().len()
+    }
+}
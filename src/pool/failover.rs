@@ -0,0 +1,119 @@
+use std::{io, time::Duration};
+
+use reqwest::{Method, RequestBuilder, StatusCode, Url, header::HeaderMap};
+
+use super::{Pool, State};
+use crate::ProxyInfo;
+
+/// Tells from a response whether the target blocks the endpoint it went through, e.g. by a
+/// 403 or a captcha page.
+pub trait BanDetector: Send + Sync {
+    fn banned(&self, status: StatusCode, body: &[u8]) -> bool;
+}
+
+impl<F: Fn(StatusCode, &[u8]) -> bool + Send + Sync> BanDetector for F {
+    fn banned(&self, status: StatusCode, body: &[u8]) -> bool {
+        self(status, body)
+    }
+}
+
+/// A response read by [`Failover::send`], with the endpoint it went through.
+#[derive(Debug)]
+pub struct Fetched<'a> {
+    pub proxy: &'a ProxyInfo,
+    pub status: StatusCode,
+    pub headers: HeaderMap,
+    pub body: Vec<u8>,
+}
+
+/// Sends requests through a [`Pool`], banning the endpoint for the target domain and moving on
+/// to the next one whenever the [`BanDetector`] fires.
+pub struct Failover<'a, S> {
+    pool: &'a Pool<S>,
+    detector: Box<dyn BanDetector>,
+    attempts: usize,
+    cooldown: Duration,
+}
+
+impl<S: State> Pool<S> {
+    pub fn failover(&self, detector: impl BanDetector + 'static) -> Failover<'_, S> {
+        Failover {
+            pool: self,
+            detector: Box::new(detector),
+            attempts: 3,
+            cooldown: Duration::from_secs(600),
+        }
+    }
+}
+
+impl<'a, S: State> Failover<'a, S> {
+    /// Endpoints tried per request, 3 by default.
+    pub fn attempts(mut self, attempts: usize) -> Self {
+        self.attempts = attempts.max(1);
+        self
+    }
+
+    /// How long an endpoint stays banned for the domain that blocked it, 10 minutes by default.
+    /// A [`Pool::domain_cooldown`] takes precedence.
+    pub fn cooldown(mut self, cooldown: Duration) -> Self {
+        self.cooldown = cooldown;
+        self
+    }
+
+    pub async fn get(&self, url: &str) -> io::Result<Option<Fetched<'a>>> {
+        self.send(Method::GET, url, |v| v).await
+    }
+
+    /// Sends the request built by `customize` through endpoints not banned for the domain of
+    /// `url`, with their [`Pool::client_for`] clients and [`Pool::cookies_for`] jars.
+    ///
+    /// `None` if every endpoint tried was blocked or none is left. Transport errors move on to
+    /// the next endpoint as well; the last one is returned if no attempt got a response.
+    pub async fn send(
+        &self,
+        method: Method,
+        url: &str,
+        customize: impl Fn(RequestBuilder) -> RequestBuilder,
+    ) -> io::Result<Option<Fetched<'a>>> {
+        let url = Url::parse(url).map_err(|v| io::Error::new(io::ErrorKind::InvalidInput, v))?;
+        let domain = url.host_str().unwrap_or_default().to_string();
+        let mut error = None;
+        for _ in 0..self.attempts {
+            let Some(proxy) = self.pool.next_for(&domain).await else {
+                break;
+            };
+            let client = self.pool.client_for(proxy).map_err(io::Error::other)?;
+            let request = customize(client.request(method.clone(), url.clone()));
+            let response = match request.send().await {
+                Ok(v) => v,
+                Err(v) => {
+                    debug!("request through {} failed: {v}", proxy.hostname);
+                    error = Some(v);
+                    continue;
+                }
+            };
+            let (status, headers) = (response.status(), response.headers().clone());
+            let body = match response.bytes().await {
+                Ok(v) => v.to_vec(),
+                Err(v) => {
+                    error = Some(v);
+                    continue;
+                }
+            };
+            if self.detector.banned(status, &body) {
+                self.pool.ban_for(&proxy.hostname, &domain, self.cooldown);
+                continue;
+            }
+            return Ok(Some(Fetched {
+                proxy,
+                status,
+                headers,
+                body,
+            }));
+        }
+        match error {
+            Some(v) => Err(io::Error::other(v)),
+            None => Ok(None),
+        }
+    }
+}
@@ -0,0 +1,28 @@
+use reqwest::header::{ACCEPT_LANGUAGE, HeaderMap, HeaderValue, InvalidHeaderValue, USER_AGENT};
+
+/// Headers a client presents itself with, kept the same for every request through one endpoint.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Profile {
+    pub user_agent: String,
+    pub accept_language: String,
+}
+
+impl Profile {
+    pub fn new(user_agent: impl Into<String>, accept_language: impl Into<String>) -> Self {
+        Profile {
+            user_agent: user_agent.into(),
+            accept_language: accept_language.into(),
+        }
+    }
+
+    /// For `ClientBuilder::default_headers` or `RequestBuilder::headers`.
+    pub fn headers(&self) -> Result<HeaderMap, InvalidHeaderValue> {
+        let mut headers = HeaderMap::new();
+        headers.insert(USER_AGENT, HeaderValue::from_str(&self.user_agent)?);
+        headers.insert(
+            ACCEPT_LANGUAGE,
+            HeaderValue::from_str(&self.accept_language)?,
+        );
+        Ok(headers)
+    }
+}
@@ -0,0 +1,93 @@
+use std::{
+    collections::HashMap,
+    ops::AddAssign,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+/// Upper bound on the traffic through one endpoint within a sliding window.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Quota {
+    pub requests: Option<u64>,
+    pub bytes: Option<u64>,
+    pub per: Duration,
+}
+
+impl Quota {
+    /// e.g. `Quota::requests(500, Duration::from_secs(3600))`.
+    pub fn requests(max: u64, per: Duration) -> Self {
+        Quota {
+            requests: Some(max),
+            bytes: None,
+            per,
+        }
+    }
+
+    pub fn bytes(max: u64, per: Duration) -> Self {
+        Quota {
+            requests: None,
+            bytes: Some(max),
+            per,
+        }
+    }
+
+    fn exceeded(&self, usage: Usage) -> bool {
+        self.requests.is_some_and(|v| usage.requests >= v)
+            || self.bytes.is_some_and(|v| usage.bytes >= v)
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Usage {
+    pub requests: u64,
+    pub bytes: u64,
+}
+
+impl AddAssign for Usage {
+    fn add_assign(&mut self, rhs: Self) {
+        self.requests += rhs.requests;
+        self.bytes += rhs.bytes;
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct Tracker {
+    events: Mutex<HashMap<String, Vec<(Instant, Usage)>>>,
+}
+
+impl Tracker {
+    /// Records `usage` and forgets events older than `retention`.
+    pub fn add(&self, hostname: &str, usage: Usage, retention: Duration) {
+        let now = Instant::now();
+        let mut events = self.events.lock().unwrap();
+        let entry = events.entry(hostname.to_string()).or_default();
+        if let Some(cutoff) = now.checked_sub(retention) {
+            entry.retain(|v| v.0 >= cutoff);
+        }
+        entry.push((now, usage));
+    }
+
+    pub fn usage(&self, hostname: &str, window: Duration) -> Usage {
+        let cutoff = Instant::now().checked_sub(window);
+        let mut usage = Usage::default();
+        for (at, v) in self
+            .events
+            .lock()
+            .unwrap()
+            .get(hostname)
+            .into_iter()
+            .flatten()
+        {
+            if cutoff.is_none_or(|c| *at >= c) {
+                usage += *v;
+            }
+        }
+        usage
+    }
+
+    pub fn exceeded(&self, hostname: &str, quotas: &[Quota]) -> bool {
+        quotas
+            .iter()
+            .any(|v| v.exceeded(self.usage(hostname, v.per)))
+    }
+}
@@ -0,0 +1,68 @@
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+/// Requests allowed through one endpoint, as a token bucket that holds up to `requests` tokens
+/// and refills them evenly over `per`, so short bursts are fine.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rate {
+    pub requests: u32,
+    pub per: Duration,
+}
+
+impl Rate {
+    pub fn per_second(requests: u32) -> Self {
+        Rate {
+            requests,
+            per: Duration::from_secs(1),
+        }
+    }
+
+    pub fn per_minute(requests: u32) -> Self {
+        Rate {
+            requests,
+            per: Duration::from_secs(60),
+        }
+    }
+
+    fn tokens_per_sec(&self) -> f64 {
+        f64::from(self.requests.max(1)) / self.per.as_secs_f64()
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct Limiter {
+    rate: Rate,
+    buckets: Mutex<HashMap<String, (f64, Instant)>>,
+}
+
+impl Limiter {
+    pub(crate) fn new(rate: Rate) -> Self {
+        Limiter {
+            rate,
+            buckets: Mutex::default(),
+        }
+    }
+
+    /// Takes a token of `hostname`, returning how long to wait before it may be used.
+    ///
+    /// The bucket goes negative while waiting, so concurrent callers queue up instead of all
+    /// waking up for the same token.
+    pub(crate) fn reserve(&self, hostname: &str) -> Duration {
+        let now = Instant::now();
+        let capacity = f64::from(self.rate.requests.max(1));
+        let mut buckets = self.buckets.lock().unwrap();
+        let (tokens, updated) = buckets
+            .entry(hostname.to_string())
+            .or_insert((capacity, now));
+        let refilled = now.duration_since(*updated).as_secs_f64() * self.rate.tokens_per_sec();
+        *tokens = (*tokens + refilled).min(capacity) - 1.0;
+        *updated = now;
+        match *tokens < 0.0 {
+            true => Duration::from_secs_f64(-*tokens / self.rate.tokens_per_sec()),
+            false => Duration::ZERO,
+        }
+    }
+}
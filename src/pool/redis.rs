@@ -0,0 +1,120 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use ::redis::{AsyncCommands, RedisResult, aio::MultiplexedConnection};
+
+use super::State;
+
+/// [`State`] stored in Redis so that several processes share one pool.
+///
+/// Keys are namespaced by `prefix`: bans live in the sorted set `{prefix}:bans` scored by their
+/// expiry, usage counters in the hash `{prefix}:usage` and sessions in `{prefix}:sticky:{session}`.
+/// Redis errors are treated like missing data so that a flaky connection degrades selection
+/// instead of failing it.
+#[derive(Clone)]
+pub struct RedisState {
+    conn: MultiplexedConnection,
+    prefix: String,
+}
+
+impl RedisState {
+    pub async fn connect(url: &str, prefix: &str) -> RedisResult<Self> {
+        let conn = ::redis::Client::open(url)?
+            .get_multiplexed_async_connection()
+            .await?;
+        Ok(Self::with_connection(conn, prefix))
+    }
+
+    pub fn with_connection(conn: MultiplexedConnection, prefix: &str) -> Self {
+        RedisState {
+            conn,
+            prefix: prefix.to_string(),
+        }
+    }
+
+    fn key(&self, name: &str) -> String {
+        format!("{}:{name}", self.prefix)
+    }
+}
+
+impl fmt::Debug for RedisState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RedisState")
+            .field("prefix", &self.prefix)
+            .finish_non_exhaustive()
+    }
+}
+
+fn now() -> f64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs_f64()
+}
+
+impl State for RedisState {
+    async fn banned(&self) -> HashSet<String> {
+        let mut conn = self.conn.clone();
+        let (key, now) = (self.key("bans"), now());
+        let _: RedisResult<()> = conn.zrembyscore(&key, "-inf", now).await;
+        conn.zrangebyscore(&key, format!("({now}"), "+inf")
+            .await
+            .unwrap_or_default()
+    }
+
+    async fn ban(&self, hostname: &str, duration: Duration) {
+        let _: RedisResult<()> = self
+            .conn
+            .clone()
+            .zadd(self.key("bans"), hostname, now() + duration.as_secs_f64())
+            .await;
+    }
+
+    async fn usage(&self) -> HashMap<String, u64> {
+        self.conn
+            .clone()
+            .hgetall(self.key("usage"))
+            .await
+            .unwrap_or_default()
+    }
+
+    async fn increment(&self, hostname: &str) {
+        let _: RedisResult<()> = self
+            .conn
+            .clone()
+            .hincr(self.key("usage"), hostname, 1)
+            .await;
+    }
+
+    async fn sticky(&self, session: &str) -> Option<String> {
+        self.conn
+            .clone()
+            .get(self.key(&format!("sticky:{session}")))
+            .await
+            .ok()
+            .flatten()
+    }
+
+    async fn set_sticky(&self, session: &str, hostname: &str, ttl: Duration) {
+        let _: RedisResult<()> = self
+            .conn
+            .clone()
+            .set_ex(
+                self.key(&format!("sticky:{session}")),
+                hostname,
+                ttl.as_secs().max(1),
+            )
+            .await;
+    }
+
+    async fn unstick(&self, session: &str) {
+        let _: RedisResult<()> = self
+            .conn
+            .clone()
+            .del(self.key(&format!("sticky:{session}")))
+            .await;
+    }
+}
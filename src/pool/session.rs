@@ -0,0 +1,84 @@
+use std::{fmt, sync::Arc, time::Duration};
+
+use super::{MemoryState, Pool, State};
+use crate::ProxyInfo;
+
+type Hook = Box<dyn Fn(&str, &ProxyInfo) + Send + Sync>;
+
+/// Keeps logical sessions, e.g. a login → browse → download flow, on one endpoint of a [`Pool`]
+/// for as long as they're in use.
+///
+/// The bindings live in the pool's [`State`], so with the `redis` feature they are shared
+/// between processes and survive restarts.
+pub struct SessionManager<S = MemoryState> {
+    pool: Arc<Pool<S>>,
+    ttl: Duration,
+    on_bind: Option<Hook>,
+}
+
+impl<S: State> SessionManager<S> {
+    /// Sessions unused for `ttl` are forgotten.
+    pub fn new(pool: Arc<Pool<S>>, ttl: Duration) -> Self {
+        SessionManager {
+            pool,
+            ttl,
+            on_bind: None,
+        }
+    }
+
+    /// Calls `f` whenever a session is bound to a new endpoint, e.g. to persist the binding
+    /// elsewhere or to start the flow over.
+    pub fn on_bind(mut self, f: impl Fn(&str, &ProxyInfo) + Send + Sync + 'static) -> Self {
+        self.on_bind = Some(Box::new(f));
+        self
+    }
+
+    pub fn pool(&self) -> &Pool<S> {
+        &self.pool
+    }
+
+    /// The endpoint of `session`, binding one if it has none or its endpoint became unusable.
+    /// Every use extends the session by the TTL.
+    pub async fn get(&self, session: &str) -> Option<&ProxyInfo> {
+        let state = self.pool.state();
+        let bound = state.sticky(session).await;
+        let proxy = self.pool.sticky(session, self.ttl).await?;
+        match bound.as_deref() == Some(proxy.hostname.as_str()) {
+            true => state.set_sticky(session, &proxy.hostname, self.ttl).await,
+            false => {
+                debug!("bound session {session} to {}", proxy.hostname);
+                if let Some(f) = &self.on_bind {
+                    f(session, proxy);
+                }
+            }
+        }
+        Some(proxy)
+    }
+
+    /// Binds `session` to `hostname`, e.g. restoring a binding saved by
+    /// [`SessionManager::on_bind`]. `false` if the pool has no such endpoint.
+    pub async fn restore(&self, session: &str, hostname: &str) -> bool {
+        if self.pool.get(hostname).is_none() {
+            return false;
+        }
+        self.pool
+            .state()
+            .set_sticky(session, hostname, self.ttl)
+            .await;
+        true
+    }
+
+    /// Ends `session`, so that its next use is bound to a fresh endpoint.
+    pub async fn end(&self, session: &str) {
+        self.pool.end_sticky(session).await;
+    }
+}
+
+impl<S: fmt::Debug> fmt::Debug for SessionManager<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SessionManager")
+            .field("pool", &self.pool)
+            .field("ttl", &self.ttl)
+            .finish_non_exhaustive()
+    }
+}
@@ -0,0 +1,222 @@
+use std::{
+    collections::HashMap,
+    fmt,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use tokio::{sync::Semaphore, task::JoinSet};
+use tokio_util::sync::CancellationToken;
+
+use super::{MemoryState, Pool, State};
+use crate::{Country, ProxyInfo, determinism, monitor};
+
+/// Keeps up to `per_country` endpoints of every country of a [`Pool`] verified as reachable, so
+/// one can take over without a probe when an active endpoint fails.
+pub struct Standby<S = MemoryState> {
+    pool: Arc<Pool<S>>,
+    per_country: usize,
+    interval: Duration,
+    timeout: Duration,
+    concurrency: usize,
+    shutdown: CancellationToken,
+    /// Hostnames by country, the longest verified first.
+    ready: Mutex<HashMap<Country, Vec<String>>>,
+}
+
+impl<S: State> Standby<S> {
+    pub fn new(pool: Arc<Pool<S>>, per_country: usize) -> Self {
+        Standby {
+            pool,
+            per_country,
+            interval: Duration::from_secs(30),
+            timeout: Duration::from_secs(3),
+            concurrency: 64,
+            shutdown: CancellationToken::new(),
+            ready: Mutex::default(),
+        }
+    }
+
+    /// Time between two checks of the standby endpoints, 30 seconds by default.
+    pub fn interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Time an endpoint has to accept a connection in, 3 seconds by default.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Maximum number of endpoints probed at the same time.
+    pub fn concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+
+    /// Stops [`Standby::run`] once `token` is cancelled.
+    pub fn shutdown(mut self, token: CancellationToken) -> Self {
+        self.shutdown = token;
+        self
+    }
+
+    pub fn pool(&self) -> &Pool<S> {
+        &self.pool
+    }
+
+    /// The standby endpoints of `country`, next to be promoted first.
+    pub fn standby(&self, country: &Country) -> Vec<&ProxyInfo> {
+        let ready = self.ready.lock().unwrap();
+        ready
+            .get(country)
+            .into_iter()
+            .flatten()
+            .filter_map(|v| self.pool.get(v))
+            .collect()
+    }
+
+    /// Checks the standby endpoints every [`Standby::interval`] until the
+    /// [`Standby::shutdown`] token is cancelled.
+    pub async fn run(&self) {
+        while !self.shutdown.is_cancelled() {
+            self.refill().await;
+            let _ = self
+                .shutdown
+                .run_until_cancelled(tokio::time::sleep(self.interval))
+                .await;
+        }
+    }
+
+    /// Probes the standby endpoints, dropping the ones that became unusable or unreachable, and
+    /// tops every country up with reachable endpoints, least loaded first.
+    ///
+    /// Every round probes as many candidates of each country as it is missing, all countries
+    /// at once, until every country is full or out of candidates.
+    pub async fn refill(&self) {
+        let banned = self.pool.state().banned().await;
+        let usable = |v: &ProxyInfo| self.pool.usable(v, &banned);
+        let current = self.ready.lock().unwrap().clone();
+        let mut ready = HashMap::<Country, Vec<String>>::new();
+        let semaphore = Arc::new(Semaphore::new(self.concurrency));
+        let verified = current
+            .values()
+            .flatten()
+            .filter_map(|v| self.pool.get(v).filter(|v| usable(v)))
+            .collect::<Vec<_>>();
+        for (proxy, latency) in self.probe(&verified, &semaphore).await {
+            match latency {
+                Some(_) => ready
+                    .entry(proxy.country.clone())
+                    .or_default()
+                    .push(proxy.hostname.clone()),
+                None => debug!("standby endpoint {} is unreachable", proxy.hostname),
+            }
+        }
+        let mut sorted = self
+            .pool
+            .proxies
+            .iter()
+            .filter(|v| usable(v))
+            .filter(|v| {
+                !ready
+                    .get(&v.country)
+                    .is_some_and(|r| r.contains(&v.hostname))
+            })
+            .collect::<Vec<_>>();
+        sorted.sort_by(|a, b| determinism::by_load(a, b));
+        let mut candidates = HashMap::<Country, Vec<&ProxyInfo>>::new();
+        for v in sorted {
+            candidates.entry(v.country.clone()).or_default().push(v);
+        }
+        loop {
+            let mut round = Vec::new();
+            for (country, v) in &mut candidates {
+                let missing = self
+                    .per_country
+                    .saturating_sub(ready.get(country).map_or(0, Vec::len));
+                round.extend(v.drain(..missing.min(v.len())));
+            }
+            if round.is_empty() {
+                break;
+            }
+            for (proxy, latency) in self.probe(&round, &semaphore).await {
+                if latency.is_some() {
+                    ready
+                        .entry(proxy.country.clone())
+                        .or_default()
+                        .push(proxy.hostname.clone());
+                }
+            }
+        }
+        *self.ready.lock().unwrap() = ready;
+    }
+
+    /// Probes `proxies` concurrently, at most as many at once as `semaphore` has permits, and
+    /// returns their latencies in the order of `proxies`.
+    async fn probe<'a>(
+        &self,
+        proxies: &[&'a ProxyInfo],
+        semaphore: &Arc<Semaphore>,
+    ) -> Vec<(&'a ProxyInfo, Option<Duration>)> {
+        let mut set = JoinSet::new();
+        for (i, v) in proxies.iter().enumerate() {
+            let (hostname, port, timeout) = (v.hostname.clone(), v.port, self.timeout);
+            let semaphore = semaphore.clone();
+            set.spawn(async move {
+                let _permit = semaphore.acquire().await.unwrap();
+                (i, monitor::probe(&hostname, port, timeout).await)
+            });
+        }
+        let mut latencies = set.join_all().await;
+        latencies.sort_by_key(|(i, _)| *i);
+        latencies
+            .into_iter()
+            .map(|(i, latency)| (proxies[i], latency))
+            .collect()
+    }
+
+    /// Hands out the next standby endpoint of `country` without probing it, skipping the ones
+    /// that became unusable. `None` if there is none left until the next [`Standby::refill`].
+    pub async fn promote(&self, country: &Country) -> Option<&ProxyInfo> {
+        let banned = self.pool.state().banned().await;
+        let proxy = {
+            let mut ready = self.ready.lock().unwrap();
+            let standby = ready.get_mut(country)?;
+            let i = standby.iter().position(|v| {
+                self.pool
+                    .get(v)
+                    .is_some_and(|v| self.pool.usable(v, &banned))
+            });
+            let hostname = standby.drain(..=i?).next_back()?;
+            self.pool.get(&hostname)?
+        };
+        debug!("promoting standby endpoint {}", proxy.hostname);
+        self.pool.hand_out(proxy).await;
+        Some(proxy)
+    }
+
+    /// Bans `failed` from the pool for `duration` and replaces it with a standby endpoint of its
+    /// country, or with the pool's next endpoint there if none is on standby.
+    pub async fn failover(&self, failed: &ProxyInfo, duration: Duration) -> Option<&ProxyInfo> {
+        self.pool.ban(&failed.hostname, duration).await;
+        self.pool.record_failure(&failed.hostname);
+        match self.promote(&failed.country).await {
+            Some(v) => Some(v),
+            None => self.pool.next_in(&failed.country).await,
+        }
+    }
+}
+
+impl<S: fmt::Debug> fmt::Debug for Standby<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Standby")
+            .field("pool", &self.pool)
+            .field("per_country", &self.per_country)
+            .field("interval", &self.interval)
+            .field("timeout", &self.timeout)
+            .field("concurrency", &self.concurrency)
+            .field("ready", &self.ready)
+            .finish_non_exhaustive()
+    }
+}
@@ -0,0 +1,72 @@
+use std::{collections::HashMap, fs, io, path::PathBuf, time::SystemTime};
+
+use serde::{Deserialize, Serialize};
+
+use super::EndpointUsage;
+
+/// The part of a [`Pool`](super::Pool) that outlives the process, see
+/// [`Pool::save_to`](super::Pool::save_to).
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PersistedState {
+    /// When the ban of each hostname expires.
+    pub bans: HashMap<String, SystemTime>,
+    /// Times each hostname was handed out.
+    pub usage: HashMap<String, u64>,
+    /// Hostname and expiry by sticky session.
+    pub sticky: HashMap<String, (String, SystemTime)>,
+    /// When the ban of each hostname expires, by the domain it is banned for.
+    #[serde(default)]
+    pub domain_bans: HashMap<String, HashMap<String, SystemTime>>,
+    /// The [`Pool::usage_report`](super::Pool::usage_report) counters.
+    #[serde(default)]
+    pub stats: Vec<EndpointUsage>,
+}
+
+impl PersistedState {
+    /// Drops the bans and sticky sessions that expired by `now`.
+    pub(crate) fn expire(&mut self, now: SystemTime) {
+        self.bans.retain(|_, v| *v > now);
+        self.sticky.retain(|_, (_, v)| *v > now);
+        for bans in self.domain_bans.values_mut() {
+            bans.retain(|_, v| *v > now);
+        }
+        self.domain_bans.retain(|_, v| !v.is_empty());
+    }
+}
+
+/// Keeps a pool's state across restarts, implemented for files and, with the `sqlite` feature,
+/// for `SqliteStore`.
+pub trait StateStore: Send + Sync {
+    /// The state last saved, empty if there is none.
+    fn load(&self) -> io::Result<PersistedState>;
+    fn save(&self, state: &PersistedState) -> io::Result<()>;
+}
+
+/// Stores the state as JSON in a file, replacing it atomically on every save.
+#[derive(Debug, Clone)]
+pub struct FileStateStore {
+    path: PathBuf,
+}
+
+impl FileStateStore {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        FileStateStore { path: path.into() }
+    }
+}
+
+impl StateStore for FileStateStore {
+    fn load(&self) -> io::Result<PersistedState> {
+        match fs::read(&self.path) {
+            Ok(v) => Ok(serde_json::from_slice(&v)?),
+            Err(v) if v.kind() == io::ErrorKind::NotFound => Ok(PersistedState::default()),
+            Err(v) => Err(v),
+        }
+    }
+
+    fn save(&self, state: &PersistedState) -> io::Result<()> {
+        let mut tmp = self.path.clone().into_os_string();
+        tmp.push(".tmp");
+        fs::write(&tmp, serde_json::to_vec(state)?)?;
+        fs::rename(tmp, &self.path)
+    }
+}
@@ -0,0 +1,54 @@
+use std::sync::Mutex;
+
+use crate::{Country, ProxyInfo};
+
+/// Shares of the traffic per country, see [`Pool::country_weights`](super::Pool::country_weights).
+#[derive(Debug)]
+pub(crate) struct Weights {
+    /// `None` stands for every country not listed.
+    shares: Vec<(Option<Country>, u32)>,
+    handed_out: Mutex<Vec<u64>>,
+}
+
+impl Weights {
+    pub(crate) fn new(shares: Vec<(Option<Country>, u32)>) -> Self {
+        let handed_out = Mutex::new(vec![0; shares.len()]);
+        Weights { shares, handed_out }
+    }
+
+    fn bucket(&self, country: &Country) -> Option<usize> {
+        self.shares
+            .iter()
+            .position(|(v, _)| v.as_ref() == Some(country))
+            .or_else(|| self.shares.iter().position(|(v, _)| v.is_none()))
+    }
+
+    /// Narrows `available` down to the country furthest behind its share, skipping countries
+    /// without an available endpoint. Endpoints of countries without a share are dropped.
+    pub(crate) fn select<'a>(&self, available: Vec<&'a ProxyInfo>) -> Vec<&'a ProxyInfo> {
+        let mut buckets = vec![Vec::new(); self.shares.len()];
+        for v in available {
+            if let Some(i) = self.bucket(&v.country) {
+                buckets[i].push(v);
+            }
+        }
+        let handed_out = self.handed_out.lock().unwrap();
+        let total = handed_out.iter().sum::<u64>() + 1;
+        let weights = self.shares.iter().map(|(_, v)| u64::from(*v)).sum::<u64>();
+        // Deficit of every bucket after the next request, scaled by `weights` to stay integral.
+        let behind = |i: usize| {
+            (u64::from(self.shares[i].1) * total) as i128 - (handed_out[i] * weights) as i128
+        };
+        let best = (0..buckets.len())
+            .filter(|&i| !buckets[i].is_empty() && self.shares[i].1 > 0)
+            .max_by_key(|&i| (behind(i), std::cmp::Reverse(i)));
+        best.map(|i| std::mem::take(&mut buckets[i]))
+            .unwrap_or_default()
+    }
+
+    pub(crate) fn record(&self, country: &Country) {
+        if let Some(i) = self.bucket(country) {
+            self.handed_out.lock().unwrap()[i] += 1;
+        }
+    }
+}
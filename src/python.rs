@@ -0,0 +1,129 @@
+//! Python bindings, built into a `nord_proxy` extension module with
+//! `maturin build --features python`.
+use pyo3::{exceptions::PyRuntimeError, prelude::*};
+
+use crate::{
+    Country, Credentials, Load, Proxy, ProxyInfo, ProxyTrait, Socks5, determinism, export, snapshot,
+};
+
+/// A fetched proxy list together with the credentials its proxies use.
+#[pyclass(name = "ProxyList", module = "nord_proxy", frozen)]
+#[derive(Clone)]
+struct ProxyList {
+    proxies: Vec<ProxyInfo>,
+    credentials: Credentials,
+}
+
+impl ProxyList {
+    fn filter(&self, f: impl Fn(&ProxyInfo) -> bool) -> Self {
+        ProxyList {
+            proxies: self.proxies.iter().filter(|v| f(v)).cloned().collect(),
+            credentials: self.credentials.clone(),
+        }
+    }
+}
+
+#[pymethods]
+impl ProxyList {
+    /// Fetches the SOCKS5 endpoints, raising `RuntimeError` if the list can't be fetched.
+    #[staticmethod]
+    fn socks5(py: Python<'_>, username: &str, password: &str) -> PyResult<Self> {
+        let credentials = Credentials::new(username, password);
+        let socks5 = py
+            .detach(|| block_on(Socks5::builder().try_build()))?
+            .map_err(|v| PyRuntimeError::new_err(v.to_string()))?;
+        Ok(ProxyList {
+            proxies: socks5.proxies_with(&credentials),
+            credentials,
+        })
+    }
+
+    /// Fetches the HTTPS endpoints, raising `RuntimeError` if the list can't be fetched.
+    #[staticmethod]
+    #[pyo3(signature = (username, password, threat_protection = false))]
+    fn https(
+        py: Python<'_>,
+        username: &str,
+        password: &str,
+        threat_protection: bool,
+    ) -> PyResult<Self> {
+        let credentials = Credentials::new(username, password);
+        let proxy = py
+            .detach(|| {
+                block_on(
+                    Proxy::builder()
+                        .threat_protection(threat_protection)
+                        .try_build(),
+                )
+            })?
+            .map_err(|v| PyRuntimeError::new_err(v.to_string()))?;
+        Ok(ProxyList {
+            proxies: proxy.proxies_with(&credentials),
+            credentials,
+        })
+    }
+
+    /// The endpoints in the country with the ISO code `code`.
+    fn country(&self, code: &str) -> Self {
+        let country = code.parse::<Country>().unwrap_or_else(|v| match v {});
+        self.filter(|v| v.country == country)
+    }
+
+    fn max_load(&self, load: u32) -> Self {
+        self.filter(|v| v.load <= Load::new(load))
+    }
+
+    /// The `n` least loaded endpoints.
+    fn best(&self, n: usize) -> Self {
+        let mut list = self.clone();
+        list.proxies.sort_by(determinism::by_load);
+        list.proxies.truncate(n);
+        list
+    }
+
+    fn __len__(&self) -> usize {
+        self.proxies.len()
+    }
+
+    /// `scheme://hostname:port` of every endpoint.
+    fn addresses(&self) -> Vec<String> {
+        self.proxies.iter().map(ProxyInfo::address).collect()
+    }
+
+    /// The endpoints without credentials as a JSON array.
+    fn to_json(&self) -> String {
+        serde_json::to_string(&snapshot::Snapshot::new(&self.proxies).entries)
+            .expect("entries serialize to JSON")
+    }
+
+    #[pyo3(signature = (direct = Vec::new()))]
+    fn proxifier(&self, direct: Vec<String>) -> String {
+        let direct = direct.iter().map(String::as_str).collect::<Vec<_>>();
+        export::proxifier(&self.proxies, &self.credentials, &direct)
+    }
+
+    #[pyo3(signature = (patterns = Vec::new()))]
+    fn foxyproxy(&self, patterns: Vec<String>) -> String {
+        let patterns = patterns.iter().map(String::as_str).collect::<Vec<_>>();
+        export::foxyproxy(&self.proxies, &self.credentials, &patterns)
+    }
+
+    /// One line per endpoint, see [`export::render`].
+    fn render(&self, template: &str) -> String {
+        export::render(template, &self.proxies, &self.credentials)
+    }
+}
+
+/// Runs `future` to completion on a runtime of its own, as Python callers have none.
+fn block_on<F: Future>(future: F) -> PyResult<F::Output> {
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .map_err(|v| PyRuntimeError::new_err(v.to_string()))?;
+    Ok(runtime.block_on(future))
+}
+
+#[pymodule]
+fn nord_proxy(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<ProxyList>()
+}
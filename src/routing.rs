@@ -0,0 +1,148 @@
+//! Routing the requests of one reqwest client through different endpoints by destination.
+use reqwest::Url;
+
+use crate::{Country, Credentials, ProxyInfo, determinism};
+
+/// Where requests to matching hosts go.
+#[derive(Debug, Clone)]
+pub enum Route {
+    Direct,
+    /// Through the least loaded endpoint in the country.
+    Country(Country),
+    Proxy(Box<ProxyInfo>),
+}
+
+/// Host patterns and their [`Route`], tried in order.
+#[derive(Debug, Clone)]
+pub struct Routes {
+    rules: Vec<(String, Route)>,
+    fallback: Route,
+}
+
+impl Default for Routes {
+    fn default() -> Self {
+        Routes {
+            rules: Vec::new(),
+            fallback: Route::Direct,
+        }
+    }
+}
+
+impl Routes {
+    /// A table sending everything [`Route::Direct`] until rules are added.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Routes hosts matching `pattern`, where `*.example.com` matches every subdomain.
+    pub fn route(mut self, pattern: impl Into<String>, route: Route) -> Self {
+        self.rules.push((pattern.into(), route));
+        self
+    }
+
+    /// Route of the hosts no rule matches.
+    pub fn fallback(mut self, route: Route) -> Self {
+        self.fallback = route;
+        self
+    }
+
+    /// A [`reqwest::Proxy::custom`] sending each request the way its host is routed, picking
+    /// the endpoints of [`Route::Country`] from `proxies`. A country without endpoints is
+    /// routed directly.
+    pub fn proxy(&self, proxies: &[ProxyInfo], credentials: &Credentials) -> reqwest::Proxy {
+        let table = self.table(proxies, credentials);
+        reqwest::Proxy::custom(move |url| table.target(url.host_str()?))
+    }
+
+    fn table(&self, proxies: &[ProxyInfo], credentials: &Credentials) -> Table {
+        let target = |route: &Route| {
+            let proxy = match route {
+                Route::Direct => None,
+                Route::Country(country) => proxies
+                    .iter()
+                    .filter(|v| &v.country == country)
+                    .min_by(|a, b| determinism::by_load(a, b)),
+                Route::Proxy(v) => Some(v.as_ref()),
+            };
+            proxy.map(|v| url(v, credentials))
+        };
+        Table {
+            rules: self
+                .rules
+                .iter()
+                .map(|(pattern, route)| (pattern.clone(), target(route)))
+                .collect(),
+            fallback: target(&self.fallback),
+        }
+    }
+}
+
+/// [`Routes`] with the endpoints picked.
+struct Table {
+    rules: Vec<(String, Option<Url>)>,
+    fallback: Option<Url>,
+}
+
+impl Table {
+    fn target(&self, host: &str) -> Option<Url> {
+        self.rules
+            .iter()
+            .find(|(pattern, _)| matches(pattern, host))
+            .map_or(&self.fallback, |(_, v)| v)
+            .clone()
+    }
+}
+
+/// Custom proxies take their credentials from the URL.
+fn url(proxy: &ProxyInfo, credentials: &Credentials) -> Url {
+    let mut url = Url::parse(&proxy.address()).expect("endpoint addresses are valid URLs");
+    let _ = url.set_username(credentials.username());
+    let _ = url.set_password(Some(credentials.password()));
+    url
+}
+
+/// Whether `host` matches `pattern`, a hostname or `*.` followed by a domain.
+pub(crate) fn matches(pattern: &str, host: &str) -> bool {
+    match pattern.strip_prefix('*') {
+        Some(suffix) => host.len() > suffix.len() && host.ends_with(suffix),
+        None => pattern.eq_ignore_ascii_case(host),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Route, Routes};
+    use crate::{Country, Credentials, pool::tests::proxy};
+
+    #[test]
+    fn table() {
+        let proxies = [
+            proxy("de1.nordvpn.com", Country::DE, 30),
+            proxy("de2.nordvpn.com", Country::DE, 10),
+            proxy("us1.nordvpn.com", Country::US, 10),
+        ];
+        let routes = Routes::new()
+            .route("*.example.de", Route::Country(Country::DE))
+            .route("api.example.com", Route::Direct)
+            .route("*.example.jp", Route::Country(Country::JP))
+            .fallback(Route::Proxy(Box::new(proxies[2].clone())));
+        let table = routes.table(&proxies, &Credentials::new("user", "p@ss"));
+        let host = |v| {
+            table
+                .target(v)
+                .and_then(|v| v.host_str().map(str::to_string))
+        };
+        assert_eq!(host("www.example.de").unwrap(), "de2.nordvpn.com");
+        assert_eq!(host("API.example.com"), None);
+        assert_eq!(host("www.example.jp"), None);
+        assert_eq!(host("example.de").unwrap(), "us1.nordvpn.com");
+        let url = table.target("example.org").unwrap();
+        assert_eq!((url.username(), url.password()), ("user", Some("p%40ss")));
+        assert!(
+            reqwest::Client::builder()
+                .proxy(routes.proxy(&proxies, &Credentials::new("user", "pass")))
+                .build()
+                .is_ok()
+        );
+    }
+}
@@ -0,0 +1,223 @@
+//! Read-only HTTP API over a proxy list.
+//!
+//! | Route          | Response                                          |
+//! |----------------|---------------------------------------------------|
+//! | `GET /proxies` | healthy [`Entry`]s, `?country=DE` to filter        |
+//! | `GET /health`  | `{"status": "ok", "proxies": .., "healthy": ..}`  |
+//! | `GET /stats`   | proxy count, healthy count and load per country   |
+use std::{
+    collections::HashMap,
+    convert::Infallible,
+    pin::pin,
+    sync::{Arc, RwLock},
+};
+
+use http_body_util::Full;
+use hyper::{
+    Method, Request, Response, StatusCode, body::Bytes, server::conn::http1, service::service_fn,
+};
+use hyper_util::rt::TokioIo;
+use serde::Serialize;
+use tokio::{
+    net::{TcpListener, ToSocketAddrs},
+    task::JoinSet,
+};
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    Country,
+    monitor::{MemoryStore, Monitor, Store},
+    snapshot::{Entry, Snapshot},
+};
+
+pub struct Server<S = MemoryStore> {
+    snapshot: RwLock<Snapshot>,
+    monitor: Option<Arc<Monitor<S>>>,
+    shutdown: CancellationToken,
+}
+
+#[derive(Serialize)]
+struct Health {
+    status: &'static str,
+    proxies: usize,
+    healthy: usize,
+}
+
+#[derive(Serialize)]
+struct Stats<'a> {
+    proxies: usize,
+    healthy: usize,
+    countries: HashMap<&'a Country, CountryStats>,
+}
+
+#[derive(Default, Serialize)]
+struct CountryStats {
+    proxies: usize,
+    healthy: usize,
+    average_load: f64,
+}
+
+impl Server<MemoryStore> {
+    pub fn new(snapshot: Snapshot) -> Self {
+        Server {
+            snapshot: RwLock::new(snapshot),
+            monitor: None,
+            shutdown: CancellationToken::new(),
+        }
+    }
+}
+
+impl<S: Store + 'static> Server<S> {
+    /// Entries the monitor last saw as unreachable are left out of `/proxies`.
+    pub fn with_monitor(snapshot: Snapshot, monitor: Arc<Monitor<S>>) -> Self {
+        Server {
+            snapshot: RwLock::new(snapshot),
+            monitor: Some(monitor),
+            shutdown: CancellationToken::new(),
+        }
+    }
+
+    /// Makes [`Server::serve`] stop accepting connections once `token` is cancelled and return
+    /// after the requests in progress are answered.
+    pub fn shutdown(mut self, token: CancellationToken) -> Self {
+        self.shutdown = token;
+        self
+    }
+
+    pub fn update(&self, snapshot: Snapshot) {
+        *self.snapshot.write().unwrap() = snapshot;
+    }
+
+    fn healthy(&self, entry: &Entry) -> bool {
+        self.monitor
+            .as_ref()
+            .and_then(|v| v.healthy(&entry.hostname))
+            .unwrap_or(true)
+    }
+
+    pub async fn serve(self: Arc<Self>, addr: impl ToSocketAddrs) -> std::io::Result<()> {
+        let listener = TcpListener::bind(addr).await?;
+        let mut connections = JoinSet::new();
+        loop {
+            let stream = tokio::select! {
+                v = listener.accept() => v?.0,
+                Some(_) = connections.join_next() => continue,
+                _ = self.shutdown.cancelled() => break,
+            };
+            let server = self.clone();
+            connections.spawn(async move {
+                let shutdown = server.shutdown.clone();
+                let service = service_fn(move |req| {
+                    let response = server.handle(&req);
+                    async move { Ok::<_, Infallible>(response) }
+                });
+                let connection =
+                    http1::Builder::new().serve_connection(TokioIo::new(stream), service);
+                let mut connection = pin!(connection);
+                tokio::select! {
+                    _ = connection.as_mut() => {}
+                    _ = shutdown.cancelled() => {
+                        connection.as_mut().graceful_shutdown();
+                        let _ = connection.await;
+                    }
+                }
+            });
+        }
+        while connections.join_next().await.is_some() {}
+        Ok(())
+    }
+
+    pub(crate) fn handle<B>(&self, req: &Request<B>) -> Response<Full<Bytes>> {
+        if req.method() != Method::GET {
+            return status(StatusCode::METHOD_NOT_ALLOWED);
+        }
+        let snapshot = self.snapshot.read().unwrap();
+        match req.uri().path() {
+            "/proxies" => {
+                let country =
+                    url::form_urlencoded::parse(req.uri().query().unwrap_or("").as_bytes())
+                        .find(|(k, _)| k == "country")
+                        .map(|(_, v)| v.parse::<Country>().unwrap());
+                json(
+                    &snapshot
+                        .entries
+                        .iter()
+                        .filter(|v| country.as_ref().is_none_or(|c| &v.country == c))
+                        .filter(|v| self.healthy(v))
+                        .collect::<Vec<_>>(),
+                )
+            }
+            "/health" => json(&Health {
+                status: "ok",
+                proxies: snapshot.entries.len(),
+                healthy: snapshot.entries.iter().filter(|v| self.healthy(v)).count(),
+            }),
+            "/stats" => {
+                let mut countries = HashMap::<_, CountryStats>::new();
+                for v in &snapshot.entries {
+                    let stats = countries.entry(&v.country).or_default();
+                    stats.proxies += 1;
+                    stats.healthy += self.healthy(v) as usize;
+                    stats.average_load += f64::from(v.load.get());
+                }
+                for v in countries.values_mut() {
+                    v.average_load /= v.proxies as f64;
+                }
+                json(&Stats {
+                    proxies: snapshot.entries.len(),
+                    healthy: countries.values().map(|v| v.healthy).sum(),
+                    countries,
+                })
+            }
+            _ => status(StatusCode::NOT_FOUND),
+        }
+    }
+}
+
+fn json(value: &impl Serialize) -> Response<Full<Bytes>> {
+    Response::builder()
+        .header("content-type", "application/json")
+        .body(Full::new(serde_json::to_vec(value).unwrap().into()))
+        .unwrap()
+}
+
+fn status(status: StatusCode) -> Response<Full<Bytes>> {
+    Response::builder()
+        .status(status)
+        .body(Full::default())
+        .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use http_body_util::BodyExt;
+    use hyper::{Request, StatusCode};
+
+    use super::Server;
+    use crate::{City, Country, pool::tests::proxy, snapshot::Snapshot};
+
+    #[tokio::test]
+    async fn routes() {
+        let mut de = proxy("de1.nordvpn.com", Country::DE, 10);
+        de.city = City::Frankfurt;
+        let server = Server::new(Snapshot::new(&[
+            de,
+            proxy("nl1.nordvpn.com", Country::NL, 30),
+        ]));
+
+        let response = server.handle(&Request::get("/proxies?country=de").body(()).unwrap());
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let entries: Vec<serde_json::Value> = serde_json::from_slice(&body).unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0]["hostname"], "de1.nordvpn.com");
+        assert_eq!(entries[0]["city"], "Frankfurt");
+
+        let response = server.handle(&Request::get("/stats").body(()).unwrap());
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let stats: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(stats["countries"]["NL"]["average_load"], 30.0);
+
+        let response = server.handle(&Request::get("/nope").body(()).unwrap());
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+}
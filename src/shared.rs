@@ -0,0 +1,307 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    fmt, io,
+    pin::Pin,
+    sync::{Arc, RwLock},
+    time::{Duration, SystemTime},
+};
+
+use tokio::sync::{Mutex, OnceCell};
+use tokio_util::task::AbortOnDropHandle;
+
+#[cfg(feature = "https-proxy")]
+use crate::Proxy;
+#[cfg(feature = "socks5")]
+use crate::Socks5;
+use crate::{Credentials, Load, ProxyInfo, ProxyTrait, api};
+
+/// Age after which [`SharedProxies::global`] refreshes its list.
+const GLOBAL_MAX_AGE: Duration = Duration::from_secs(15 * 60);
+/// Time [`SharedProxies::global`] waits after a failed refresh before trying again.
+const GLOBAL_RETRY: Duration = Duration::from_secs(60);
+/// Loads kept per endpoint for [`SharedProxies::load_history`].
+const HISTORY: usize = 32;
+
+type Fetch =
+    dyn Fn() -> Pin<Box<dyn Future<Output = io::Result<Vec<ProxyInfo>>> + Send>> + Send + Sync;
+
+/// Cloneable handle to a proxy list for sharing between tasks and threads.
+///
+/// Every clone sees a refresh at once; lists handed out before keep their contents.
+#[derive(Clone)]
+pub struct SharedProxies {
+    current: Arc<RwLock<Current>>,
+    fetch: Option<Arc<Fetch>>,
+}
+
+struct Current {
+    proxies: Arc<[ProxyInfo]>,
+    fetched_at: SystemTime,
+    /// When the last fetch was attempted, successful or not.
+    attempted_at: SystemTime,
+    stale: bool,
+    history: HashMap<String, VecDeque<(SystemTime, Load)>>,
+}
+
+impl Current {
+    fn new(proxies: Vec<ProxyInfo>) -> Self {
+        let mut current = Current {
+            proxies: Arc::new([]),
+            fetched_at: SystemTime::now(),
+            attempted_at: SystemTime::now(),
+            stale: false,
+            history: HashMap::new(),
+        };
+        current.replace(proxies);
+        current
+    }
+
+    /// Swaps in a freshly fetched list, adding its loads to the history of the endpoints still
+    /// listed.
+    fn replace(&mut self, proxies: Vec<ProxyInfo>) {
+        let now = SystemTime::now();
+        let mut history = HashMap::with_capacity(proxies.len());
+        for v in &proxies {
+            let mut loads = self.history.remove(&v.hostname).unwrap_or_default();
+            if loads.len() == HISTORY {
+                loads.pop_front();
+            }
+            loads.push_back((now, v.load));
+            history.insert(v.hostname.clone(), loads);
+        }
+        *self = Current {
+            proxies: proxies.into(),
+            fetched_at: now,
+            attempted_at: now,
+            stale: false,
+            history,
+        };
+    }
+}
+
+impl SharedProxies {
+    pub fn new(proxies: Vec<ProxyInfo>) -> Self {
+        SharedProxies {
+            current: Arc::new(RwLock::new(Current::new(proxies))),
+            fetch: None,
+        }
+    }
+
+    /// Fetches the list with `fetch`, which [`SharedProxies::refresh`] calls again, e.g.
+    /// `|| async { Ok(Socks5::builder().try_build().await?.proxies_with(&credentials)) }`.
+    ///
+    /// Fails if the first fetch does, as there is no list to fall back to yet.
+    pub async fn fetching<F: Future<Output = io::Result<Vec<ProxyInfo>>> + Send + 'static>(
+        fetch: impl Fn() -> F + Send + Sync + 'static,
+    ) -> io::Result<Self> {
+        let fetch: Arc<Fetch> = Arc::new(move || Box::pin(fetch()));
+        Ok(SharedProxies {
+            current: Arc::new(RwLock::new(Current::new(fetch().await?))),
+            fetch: Some(fetch),
+        })
+    }
+
+    /// Process-wide list of the enabled SOCKS5 and HTTPS endpoints, for applications that
+    /// don't want to pass a handle around. It is fetched on first use with
+    /// [`Credentials::from_env`] and refreshed by the first call after it is 15 minutes old,
+    /// or a minute after the last failed refresh while the API is down.
+    pub async fn global() -> io::Result<&'static SharedProxies> {
+        static GLOBAL: OnceCell<SharedProxies> = OnceCell::const_new();
+        static REFRESHING: Mutex<()> = Mutex::const_new(());
+        let shared = GLOBAL
+            .get_or_try_init(|| async {
+                let credentials = Credentials::from_env()?;
+                SharedProxies::fetching(move || {
+                    let credentials = credentials.clone();
+                    async move {
+                        let mut proxies = Vec::new();
+                        #[cfg(feature = "socks5")]
+                        proxies.extend(
+                            Socks5::builder()
+                                .try_build()
+                                .await?
+                                .proxies_with(&credentials),
+                        );
+                        #[cfg(feature = "https-proxy")]
+                        proxies.extend(
+                            Proxy::builder()
+                                .try_build()
+                                .await?
+                                .proxies_with(&credentials),
+                        );
+                        Ok(proxies)
+                    }
+                })
+                .await
+            })
+            .await?;
+        // Callers arriving while another one refreshes keep using the current list.
+        if shared.refresh_due(GLOBAL_MAX_AGE, GLOBAL_RETRY)
+            && let Ok(_guard) = REFRESHING.try_lock()
+        {
+            shared.refresh().await;
+        }
+        Ok(shared)
+    }
+
+    /// The current list.
+    pub fn get(&self) -> Arc<[ProxyInfo]> {
+        self.current.read().unwrap().proxies.clone()
+    }
+
+    pub fn set(&self, proxies: Vec<ProxyInfo>) {
+        self.current.write().unwrap().replace(proxies);
+    }
+
+    /// Load of `hostname` in each of the last 32 lists, oldest first.
+    pub fn load_history(&self, hostname: &str) -> Vec<(SystemTime, Load)> {
+        self.current
+            .read()
+            .unwrap()
+            .history
+            .get(hostname)
+            .map(|v| v.iter().copied().collect())
+            .unwrap_or_default()
+    }
+
+    /// Whether the load of `hostname` went up with each of the last `n` lists, to pass over
+    /// servers filling up even while their current load is still low.
+    pub fn load_rising(&self, hostname: &str, n: usize) -> bool {
+        let history = self.load_history(hostname);
+        history.len() > n
+            && history[history.len() - n - 1..]
+                .windows(2)
+                .all(|v| v[1].1 > v[0].1)
+    }
+
+    /// Whether the last [`SharedProxies::refresh`] failed and the current list is left over
+    /// from an earlier one.
+    pub fn is_stale(&self) -> bool {
+        self.current.read().unwrap().stale
+    }
+
+    /// Time since the current list was fetched.
+    pub fn age(&self) -> Duration {
+        self.current
+            .read()
+            .unwrap()
+            .fetched_at
+            .elapsed()
+            .unwrap_or_default()
+    }
+
+    /// Whether the list is older than `max_age` and the last attempt to fetch it was at least
+    /// `retry` ago.
+    fn refresh_due(&self, max_age: Duration, retry: Duration) -> bool {
+        let current = self.current.read().unwrap();
+        let elapsed = |v: SystemTime| v.elapsed().unwrap_or_default();
+        elapsed(current.fetched_at) > max_age && elapsed(current.attempted_at) >= retry
+    }
+
+    /// Fetches the list again and swaps it in for all clones. Does nothing for handles created
+    /// with [`SharedProxies::new`].
+    ///
+    /// A fetch that fails or lists no endpoints keeps serving the current list, so traffic
+    /// keeps flowing during API outages, and marks it [stale](SharedProxies::is_stale). Only the
+    /// list in memory is kept; a process starting during an outage gets the error from
+    /// [`SharedProxies::fetching`].
+    ///
+    /// Cancellation-safe: dropping the future, e.g. in `tokio::select!` with a shutdown signal,
+    /// aborts the fetch and keeps the current list.
+    pub async fn refresh(&self) {
+        let Some(fetch) = &self.fetch else {
+            return;
+        };
+        let fetched = AbortOnDropHandle::new(tokio::spawn(api::keep_base(fetch())))
+            .await
+            .map_err(io::Error::other)
+            .and_then(|v| v)
+            .and_then(|v| match v.is_empty() {
+                true => Err(io::Error::other("no endpoints listed")),
+                false => Ok(v),
+            });
+        let mut current = self.current.write().unwrap();
+        match fetched {
+            Ok(proxies) => current.replace(proxies),
+            Err(v) => {
+                warn!(
+                    "refreshing the proxy list failed, serving the one fetched {:?} ago: {v}",
+                    current.fetched_at.elapsed().unwrap_or_default()
+                );
+                current.stale = true;
+                current.attempted_at = SystemTime::now();
+            }
+        }
+    }
+}
+
+impl fmt::Debug for SharedProxies {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SharedProxies")
+            .field("proxies", &self.get())
+            .field("stale", &self.is_stale())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io,
+        sync::{
+            Arc,
+            atomic::{AtomicU32, Ordering},
+        },
+        time::Duration,
+    };
+
+    use super::SharedProxies;
+    use crate::{Country, pool::tests::proxy};
+
+    #[tokio::test]
+    async fn refresh() {
+        let calls = Arc::new(AtomicU32::new(0));
+        let shared = SharedProxies::fetching({
+            let calls = calls.clone();
+            move || {
+                let load = calls.fetch_add(1, Ordering::SeqCst);
+                async move {
+                    match load {
+                        u32::MAX => Err(io::Error::other("API down")),
+                        _ => Ok(vec![proxy("de1.nordvpn.com", Country::DE, load)]),
+                    }
+                }
+            }
+        })
+        .await
+        .unwrap();
+        let clone = shared.clone();
+        let before = clone.get();
+
+        tokio::spawn(async move { shared.refresh().await })
+            .await
+            .unwrap();
+        assert_eq!(clone.get()[0].load.get(), 1);
+        assert_eq!(before[0].load.get(), 0);
+
+        assert!(!clone.is_stale());
+        let loads = clone.load_history("de1.nordvpn.com");
+        assert_eq!(loads.iter().map(|v| v.1.get()).collect::<Vec<_>>(), [0, 1]);
+        assert!(clone.load_rising("de1.nordvpn.com", 1));
+        assert!(!clone.load_rising("de1.nordvpn.com", 2));
+
+        calls.store(u32::MAX, Ordering::SeqCst);
+        clone.refresh().await;
+        assert!(clone.is_stale());
+        assert_eq!(clone.get()[0].load.get(), 1);
+        assert!(!clone.refresh_due(Duration::ZERO, Duration::from_secs(60)));
+        assert!(clone.refresh_due(Duration::ZERO, Duration::ZERO));
+
+        clone.set(Vec::new());
+        assert!(!clone.is_stale());
+        assert!(clone.get().is_empty());
+
+        let failing = SharedProxies::fetching(|| async { Err(io::Error::other("API down")) });
+        assert!(failing.await.is_err());
+    }
+}
@@ -0,0 +1,332 @@
+use std::{
+    collections::{HashMap, HashSet},
+    time::{Duration, SystemTime},
+};
+#[cfg(feature = "binary")]
+use std::{fs, io, path::Path};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{City, Country, Load, ProxyInfo, Status};
+
+/// Leads the binary format.
+#[cfg(feature = "binary")]
+const MAGIC: &[u8; 4] = b"NPSS";
+/// Bumped whenever [`Snapshot`] or [`Entry`] change shape, keeping the previous shape in a
+/// module [`Snapshot::from_bytes`] migrates from.
+#[cfg(feature = "binary")]
+const VERSION: u16 = 2;
+
+/// Credential-free copy of a proxy list at a point in time.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub taken_at: SystemTime,
+    pub entries: Vec<Entry>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Entry {
+    pub hostname: String,
+    pub port: u16,
+    pub load: Load,
+    #[serde(with = "name")]
+    pub country: Country,
+    #[serde(with = "name")]
+    pub city: City,
+    /// Missing from JSON written before it was added, which only listed online servers.
+    #[serde(default = "online")]
+    pub status: Status,
+}
+
+fn online() -> Status {
+    Status::Online
+}
+
+/// How the servers of a country or city changed between two snapshots, see
+/// [`Snapshot::compare_regions`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct RegionChange {
+    pub country: Country,
+    /// `None` for the country as a whole.
+    pub city: Option<City>,
+    pub added: usize,
+    pub removed: usize,
+    /// Average load in the new snapshot minus in the old one, `None` if either has no servers
+    /// in the region.
+    pub load_delta: Option<f64>,
+}
+
+impl Snapshot {
+    pub fn new(proxies: &[ProxyInfo]) -> Self {
+        Snapshot {
+            taken_at: SystemTime::now(),
+            entries: proxies.iter().map(Entry::from).collect(),
+        }
+    }
+
+    /// Time since the snapshot was taken.
+    pub fn age(&self) -> Duration {
+        self.taken_at.elapsed().unwrap_or_default()
+    }
+
+    /// Servers added and removed and the change of the average load per country, each followed
+    /// by its cities, in the order they first appear in `new` and then `old`.
+    pub fn compare_regions(old: &Snapshot, new: &Snapshot) -> Vec<RegionChange> {
+        let mut countries = Vec::<(&Country, Vec<&City>)>::new();
+        let mut seen = HashSet::new();
+        for v in new.entries.iter().chain(&old.entries) {
+            if seen.insert((&v.country, None)) {
+                countries.push((&v.country, Vec::new()));
+            }
+            if seen.insert((&v.country, Some(&v.city)))
+                && let Some((_, cities)) = countries.iter_mut().find(|(c, _)| *c == &v.country)
+            {
+                cities.push(&v.city);
+            }
+        }
+        let mut changes = Vec::new();
+        for (country, cities) in countries {
+            for city in std::iter::once(None).chain(cities.into_iter().map(Some)) {
+                let (before, old_load) = old.region(country, city);
+                let (after, new_load) = new.region(country, city);
+                changes.push(RegionChange {
+                    country: country.clone(),
+                    city: city.cloned(),
+                    added: after.keys().filter(|v| !before.contains_key(*v)).count(),
+                    removed: before.keys().filter(|v| !after.contains_key(*v)).count(),
+                    load_delta: old_load.zip(new_load).map(|(old, new)| new - old),
+                });
+            }
+        }
+        changes
+    }
+
+    /// Load by hostname of the servers in `country`, or `city` of it, and their average load.
+    fn region(&self, country: &Country, city: Option<&City>) -> (HashMap<&str, Load>, Option<f64>) {
+        let entries = self
+            .entries
+            .iter()
+            .filter(|v| &v.country == country && city.is_none_or(|c| &v.city == c))
+            .map(|v| (v.hostname.as_str(), v.load))
+            .collect::<HashMap<_, _>>();
+        let load = (!entries.is_empty()).then(|| {
+            entries.values().map(|v| f64::from(v.get())).sum::<f64>() / entries.len() as f64
+        });
+        (entries, load)
+    }
+
+    /// Encodes the snapshot in a compact, versioned binary format.
+    #[cfg(feature = "binary")]
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend_from_slice(&VERSION.to_le_bytes());
+        postcard::to_extend(self, bytes).expect("writing to a Vec can't fail")
+    }
+
+    /// Decodes [`Snapshot::to_bytes`], migrating snapshots written by older versions of the
+    /// format. `None` if it was written by a newer one.
+    #[cfg(feature = "binary")]
+    pub fn from_bytes(bytes: &[u8]) -> io::Result<Option<Self>> {
+        let Some(body) = bytes.strip_prefix(MAGIC) else {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "not a binary snapshot",
+            ));
+        };
+        let Some((version, body)) = body.split_first_chunk() else {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "truncated snapshot",
+            ));
+        };
+        let snapshot = match u16::from_le_bytes(*version) {
+            VERSION => postcard::from_bytes(body),
+            1 => postcard::from_bytes::<v1::Snapshot>(body).map(Snapshot::from),
+            _ => return Ok(None),
+        };
+        snapshot
+            .map(Some)
+            .map_err(|v| io::Error::new(io::ErrorKind::InvalidData, v))
+    }
+
+    /// Writes the snapshot to a cache file.
+    #[cfg(feature = "binary")]
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        fs::write(path, self.to_bytes())
+    }
+
+    /// Reads a cache file written by [`Snapshot::save`], `None` if there is none or it is stale.
+    #[cfg(feature = "binary")]
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Option<Self>> {
+        match fs::read(path) {
+            Ok(v) => Self::from_bytes(&v),
+            Err(v) if v.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(v) => Err(v),
+        }
+    }
+}
+
+impl From<&ProxyInfo> for Entry {
+    fn from(v: &ProxyInfo) -> Self {
+        Entry {
+            hostname: v.hostname.clone(),
+            port: v.port,
+            load: v.load,
+            country: v.country.clone(),
+            city: v.city.clone(),
+            status: v.status.clone(),
+        }
+    }
+}
+
+/// Version 1 of the binary format, before entries had a status.
+#[cfg(feature = "binary")]
+mod v1 {
+    use std::time::SystemTime;
+
+    use serde::{Deserialize, Serialize};
+
+    use super::name;
+    use crate::{City, Country, Status};
+
+    #[derive(Serialize, Deserialize)]
+    pub struct Snapshot {
+        pub taken_at: SystemTime,
+        pub entries: Vec<Entry>,
+    }
+
+    #[derive(Serialize, Deserialize)]
+    pub struct Entry {
+        pub hostname: String,
+        pub port: u16,
+        pub load: u32,
+        #[serde(with = "name")]
+        pub country: Country,
+        #[serde(with = "name")]
+        pub city: City,
+    }
+
+    /// Version 1 only stored servers that were online.
+    impl From<Snapshot> for super::Snapshot {
+        fn from(v: Snapshot) -> Self {
+            super::Snapshot {
+                taken_at: v.taken_at,
+                entries: v
+                    .entries
+                    .into_iter()
+                    .map(|v| super::Entry {
+                        hostname: v.hostname,
+                        port: v.port,
+                        load: v.load.into(),
+                        country: v.country,
+                        city: v.city,
+                        status: Status::Online,
+                    })
+                    .collect(),
+            }
+        }
+    }
+}
+
+/// Countries and cities as their names, which also works for formats that aren't
+/// self-describing and so can't tell [`Country::Other`] apart from the named variants.
+mod name {
+    use std::str::FromStr;
+
+    use serde::{
+        Deserialize, Deserializer, Serialize, Serializer, de::Error as _, ser::Error as _,
+    };
+
+    pub fn serialize<T: Serialize, S: Serializer>(v: &T, serializer: S) -> Result<S::Ok, S::Error> {
+        match serde_json::to_value(v) {
+            Ok(serde_json::Value::String(v)) => serializer.serialize_str(&v),
+            _ => Err(S::Error::custom("expected a name")),
+        }
+    }
+
+    pub fn deserialize<'de, T: FromStr, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<T, D::Error> {
+        T::from_str(&String::deserialize(deserializer)?)
+            .map_err(|_| D::Error::custom("invalid name"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(feature = "binary")]
+    use super::{MAGIC, VERSION, v1};
+    use super::{RegionChange, Snapshot};
+    #[cfg(feature = "binary")]
+    use crate::Status;
+    use crate::{City, Country, Load, pool::tests::proxy};
+
+    #[test]
+    fn compare_regions() {
+        let mut proxies = vec![
+            proxy("de1.nordvpn.com", Country::DE, 10),
+            proxy("de2.nordvpn.com", Country::DE, 30),
+            proxy("nl1.nordvpn.com", Country::NL, 50),
+        ];
+        proxies[0].city = City::Frankfurt;
+        proxies[1].city = City::Berlin;
+        let old = Snapshot::new(&proxies);
+        proxies.remove(2);
+        proxies[1].load = Load::new(50);
+        proxies.push(proxy("de3.nordvpn.com", Country::DE, 60));
+        proxies[2].city = City::Berlin;
+        let new = Snapshot::new(&proxies);
+        let changes = Snapshot::compare_regions(&old, &new);
+        let change =
+            |country: Country, city: Option<City>, added, removed, load_delta| RegionChange {
+                country,
+                city,
+                added,
+                removed,
+                load_delta,
+            };
+        assert_eq!(
+            changes,
+            [
+                change(Country::DE, None, 1, 0, Some(20.0)),
+                change(Country::DE, Some(City::Frankfurt), 0, 0, Some(0.0)),
+                change(Country::DE, Some(City::Berlin), 1, 0, Some(25.0)),
+                change(Country::NL, None, 0, 1, None),
+                change(Country::NL, Some(City::Other(String::new())), 0, 1, None),
+            ]
+        );
+    }
+
+    #[cfg(feature = "binary")]
+    #[test]
+    fn binary() {
+        let mut proxies = vec![
+            proxy("de1.nordvpn.com", Country::DE, 10),
+            proxy("xx1.nordvpn.com", Country::Other("XX".to_string()), 20),
+        ];
+        proxies[0].city = City::Frankfurt;
+        let snapshot = Snapshot::new(&proxies);
+        let mut bytes = snapshot.to_bytes();
+        assert_eq!(Snapshot::from_bytes(&bytes).unwrap(), Some(snapshot));
+        bytes[4..6].copy_from_slice(&(VERSION + 1).to_le_bytes());
+        assert_eq!(Snapshot::from_bytes(&bytes).unwrap(), None);
+        assert!(Snapshot::from_bytes(b"{}").is_err());
+
+        let old = v1::Snapshot {
+            taken_at: std::time::UNIX_EPOCH,
+            entries: vec![v1::Entry {
+                hostname: "de1.nordvpn.com".to_string(),
+                port: 1080,
+                load: 10,
+                country: Country::DE,
+                city: City::Frankfurt,
+            }],
+        };
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend_from_slice(&1u16.to_le_bytes());
+        let bytes = postcard::to_extend(&old, bytes).unwrap();
+        let migrated = Snapshot::from_bytes(&bytes).unwrap().unwrap();
+        assert_eq!(migrated.entries[0].hostname, "de1.nordvpn.com");
+        assert_eq!(migrated.entries[0].status, Status::Online);
+    }
+}
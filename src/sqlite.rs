@@ -0,0 +1,390 @@
+//! SQLite persistence for snapshots, monitoring samples and pool state.
+use std::{
+    io,
+    path::Path,
+    sync::Mutex,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use rusqlite::{Connection, OptionalExtension, params};
+
+use crate::{
+    monitor::{Sample, Store},
+    pool::{EndpointUsage, PersistedState, StateStore},
+    snapshot::Snapshot,
+};
+
+/// Aggregated probe results of one endpoint over its whole recorded history.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Totals {
+    pub probes: u64,
+    pub failures: u64,
+    pub last_seen: SystemTime,
+    pub last_latency: Option<Duration>,
+}
+
+pub struct SqliteStore {
+    conn: Mutex<Connection>,
+}
+
+impl SqliteStore {
+    pub fn open(path: impl AsRef<Path>) -> rusqlite::Result<Self> {
+        Self::init(Connection::open(path)?)
+    }
+
+    pub fn in_memory() -> rusqlite::Result<Self> {
+        Self::init(Connection::open_in_memory()?)
+    }
+
+    fn init(conn: Connection) -> rusqlite::Result<Self> {
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS snapshots (
+                id INTEGER PRIMARY KEY,
+                taken_at INTEGER NOT NULL,
+                data TEXT NOT NULL
+            );
+            CREATE TABLE IF NOT EXISTS samples (
+                hostname TEXT NOT NULL,
+                timestamp INTEGER NOT NULL,
+                latency INTEGER
+            );
+            CREATE INDEX IF NOT EXISTS samples_hostname ON samples (hostname, timestamp);
+            CREATE TABLE IF NOT EXISTS totals (
+                hostname TEXT PRIMARY KEY,
+                probes INTEGER NOT NULL,
+                failures INTEGER NOT NULL,
+                last_seen INTEGER NOT NULL,
+                last_latency INTEGER
+            );
+            CREATE TABLE IF NOT EXISTS bans (
+                hostname TEXT PRIMARY KEY,
+                expires INTEGER NOT NULL
+            );
+            CREATE TABLE IF NOT EXISTS usage (
+                hostname TEXT PRIMARY KEY,
+                count INTEGER NOT NULL
+            );
+            CREATE TABLE IF NOT EXISTS sticky (
+                session TEXT PRIMARY KEY,
+                hostname TEXT NOT NULL,
+                expires INTEGER NOT NULL
+            );
+            CREATE TABLE IF NOT EXISTS domain_bans (
+                domain TEXT NOT NULL,
+                hostname TEXT NOT NULL,
+                expires INTEGER NOT NULL,
+                PRIMARY KEY (domain, hostname)
+            );
+            CREATE TABLE IF NOT EXISTS stats (
+                hostname TEXT PRIMARY KEY,
+                requests INTEGER NOT NULL,
+                failures INTEGER NOT NULL,
+                bytes INTEGER NOT NULL,
+                last_used INTEGER
+            );",
+        )?;
+        Ok(SqliteStore {
+            conn: Mutex::new(conn),
+        })
+    }
+
+    pub fn save_snapshot(&self, snapshot: &Snapshot) -> rusqlite::Result<()> {
+        let data = serde_json::to_string(&snapshot.entries)
+            .map_err(|v| rusqlite::Error::ToSqlConversionFailure(Box::new(v)))?;
+        self.conn.lock().unwrap().execute(
+            "INSERT INTO snapshots (taken_at, data) VALUES (?1, ?2)",
+            params![millis(snapshot.taken_at), data],
+        )?;
+        Ok(())
+    }
+
+    pub fn latest_snapshot(&self) -> rusqlite::Result<Option<Snapshot>> {
+        let row = self
+            .conn
+            .lock()
+            .unwrap()
+            .query_row(
+                "SELECT taken_at, data FROM snapshots ORDER BY taken_at DESC, id DESC LIMIT 1",
+                [],
+                |v| Ok((v.get::<_, i64>(0)?, v.get::<_, String>(1)?)),
+            )
+            .optional()?;
+        row.map(|(taken_at, data)| {
+            Ok(Snapshot {
+                taken_at: time(taken_at),
+                entries: serde_json::from_str(&data).map_err(|v| {
+                    rusqlite::Error::FromSqlConversionFailure(
+                        1,
+                        rusqlite::types::Type::Text,
+                        Box::new(v),
+                    )
+                })?,
+            })
+        })
+        .transpose()
+    }
+
+    pub fn totals(&self, hostname: &str) -> rusqlite::Result<Option<Totals>> {
+        self.conn
+            .lock()
+            .unwrap()
+            .query_row(
+                "SELECT probes, failures, last_seen, last_latency FROM totals WHERE hostname = ?1",
+                [hostname],
+                |v| {
+                    Ok(Totals {
+                        probes: v.get(0)?,
+                        failures: v.get(1)?,
+                        last_seen: time(v.get(2)?),
+                        last_latency: v
+                            .get::<_, Option<i64>>(3)?
+                            .map(|v| Duration::from_millis(v as u64)),
+                    })
+                },
+            )
+            .optional()
+    }
+
+    fn try_record(&self, sample: &Sample) -> rusqlite::Result<()> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        let timestamp = millis(sample.timestamp);
+        let latency = sample.latency.map(|v| v.as_millis() as i64);
+        tx.execute(
+            "INSERT INTO samples (hostname, timestamp, latency) VALUES (?1, ?2, ?3)",
+            params![sample.hostname, timestamp, latency],
+        )?;
+        tx.execute(
+            "INSERT INTO totals (hostname, probes, failures, last_seen, last_latency)
+             VALUES (?1, 1, ?2, ?3, ?4)
+             ON CONFLICT (hostname) DO UPDATE SET
+                probes = probes + 1,
+                failures = failures + excluded.failures,
+                last_seen = max(last_seen, excluded.last_seen),
+                last_latency = excluded.last_latency",
+            params![
+                sample.hostname,
+                latency.is_none() as i64,
+                timestamp,
+                latency
+            ],
+        )?;
+        tx.commit()
+    }
+
+    fn try_load_state(&self) -> rusqlite::Result<PersistedState> {
+        let conn = self.conn.lock().unwrap();
+        let mut state = PersistedState::default();
+        let mut stmt = conn.prepare("SELECT hostname, expires FROM bans")?;
+        for v in stmt.query_map([], |v| Ok((v.get(0)?, time(v.get(1)?))))? {
+            let (hostname, expires) = v?;
+            state.bans.insert(hostname, expires);
+        }
+        let mut stmt = conn.prepare("SELECT hostname, count FROM usage")?;
+        for v in stmt.query_map([], |v| Ok((v.get(0)?, v.get(1)?)))? {
+            let (hostname, count) = v?;
+            state.usage.insert(hostname, count);
+        }
+        let mut stmt = conn.prepare("SELECT session, hostname, expires FROM sticky")?;
+        for v in stmt.query_map([], |v| Ok((v.get(0)?, v.get(1)?, time(v.get(2)?))))? {
+            let (session, hostname, expires) = v?;
+            state.sticky.insert(session, (hostname, expires));
+        }
+        let mut stmt = conn.prepare("SELECT domain, hostname, expires FROM domain_bans")?;
+        for v in stmt.query_map([], |v| Ok((v.get(0)?, v.get(1)?, time(v.get(2)?))))? {
+            let (domain, hostname, expires) = v?;
+            state
+                .domain_bans
+                .entry(domain)
+                .or_default()
+                .insert(hostname, expires);
+        }
+        let mut stmt = conn.prepare(
+            "SELECT hostname, requests, failures, bytes, last_used FROM stats ORDER BY hostname",
+        )?;
+        for v in stmt.query_map([], |v| {
+            Ok(EndpointUsage {
+                hostname: v.get(0)?,
+                requests: v.get(1)?,
+                failures: v.get(2)?,
+                bytes: v.get(3)?,
+                last_used: v.get::<_, Option<i64>>(4)?.map(time),
+            })
+        })? {
+            state.stats.push(v?);
+        }
+        Ok(state)
+    }
+
+    fn try_save_state(&self, state: &PersistedState) -> rusqlite::Result<()> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        tx.execute_batch(
+            "DELETE FROM bans; DELETE FROM usage; DELETE FROM sticky;
+             DELETE FROM domain_bans; DELETE FROM stats;",
+        )?;
+        for (hostname, expires) in &state.bans {
+            tx.execute(
+                "INSERT INTO bans (hostname, expires) VALUES (?1, ?2)",
+                params![hostname, millis(*expires)],
+            )?;
+        }
+        for (hostname, count) in &state.usage {
+            tx.execute(
+                "INSERT INTO usage (hostname, count) VALUES (?1, ?2)",
+                params![hostname, count],
+            )?;
+        }
+        for (session, (hostname, expires)) in &state.sticky {
+            tx.execute(
+                "INSERT INTO sticky (session, hostname, expires) VALUES (?1, ?2, ?3)",
+                params![session, hostname, millis(*expires)],
+            )?;
+        }
+        for (domain, bans) in &state.domain_bans {
+            for (hostname, expires) in bans {
+                tx.execute(
+                    "INSERT INTO domain_bans (domain, hostname, expires) VALUES (?1, ?2, ?3)",
+                    params![domain, hostname, millis(*expires)],
+                )?;
+            }
+        }
+        for v in &state.stats {
+            tx.execute(
+                "INSERT INTO stats (hostname, requests, failures, bytes, last_used)
+                 VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![
+                    v.hostname,
+                    v.requests,
+                    v.failures,
+                    v.bytes,
+                    v.last_used.map(millis)
+                ],
+            )?;
+        }
+        tx.commit()
+    }
+}
+
+impl Store for SqliteStore {
+    fn record(&self, sample: Sample) {
+        let _ = self.try_record(&sample);
+    }
+
+    fn samples(&self, hostname: &str, since: SystemTime) -> Vec<Sample> {
+        let conn = self.conn.lock().unwrap();
+        let Ok(mut stmt) = conn.prepare_cached(
+            "SELECT timestamp, latency FROM samples
+             WHERE hostname = ?1 AND timestamp >= ?2 ORDER BY timestamp",
+        ) else {
+            return Vec::new();
+        };
+        stmt.query_map(params![hostname, millis(since)], |v| {
+            Ok(Sample {
+                hostname: hostname.to_string(),
+                timestamp: time(v.get(0)?),
+                latency: v
+                    .get::<_, Option<i64>>(1)?
+                    .map(|v| Duration::from_millis(v as u64)),
+            })
+        })
+        .map(|v| v.filter_map(Result::ok).collect())
+        .unwrap_or_default()
+    }
+}
+
+impl StateStore for SqliteStore {
+    fn load(&self) -> io::Result<PersistedState> {
+        self.try_load_state().map_err(io::Error::other)
+    }
+
+    fn save(&self, state: &PersistedState) -> io::Result<()> {
+        self.try_save_state(state).map_err(io::Error::other)
+    }
+}
+
+fn millis(time: SystemTime) -> i64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|v| v.as_millis() as i64)
+        .unwrap_or_default()
+}
+
+fn time(millis: i64) -> SystemTime {
+    UNIX_EPOCH + Duration::from_millis(millis.max(0) as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, SystemTime};
+
+    use super::SqliteStore;
+    use crate::{
+        City, Country, Load, Status,
+        monitor::{Sample, Store},
+        pool::{EndpointUsage, PersistedState, StateStore},
+        snapshot::{Entry, Snapshot},
+    };
+
+    #[test]
+    fn roundtrip() {
+        let store = SqliteStore::in_memory().unwrap();
+        let snapshot = Snapshot {
+            taken_at: SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000),
+            entries: vec![Entry {
+                hostname: "de1057.nordvpn.com".to_string(),
+                port: 1080,
+                load: Load::new(12),
+                country: Country::DE,
+                city: City::Frankfurt,
+                status: Status::Online,
+            }],
+        };
+        store.save_snapshot(&snapshot).unwrap();
+        assert_eq!(store.latest_snapshot().unwrap(), Some(snapshot));
+
+        for latency in [Some(20), None, Some(40)] {
+            store.record(Sample {
+                hostname: "de1057.nordvpn.com".to_string(),
+                timestamp: SystemTime::now(),
+                latency: latency.map(Duration::from_millis),
+            });
+        }
+        let totals = store.totals("de1057.nordvpn.com").unwrap().unwrap();
+        assert_eq!((totals.probes, totals.failures), (3, 1));
+        assert_eq!(totals.last_latency, Some(Duration::from_millis(40)));
+        assert_eq!(
+            store
+                .samples("de1057.nordvpn.com", SystemTime::UNIX_EPOCH)
+                .len(),
+            3
+        );
+    }
+
+    #[test]
+    fn state() {
+        let store = SqliteStore::in_memory().unwrap();
+        assert_eq!(store.load().unwrap(), PersistedState::default());
+        let expires = SystemTime::UNIX_EPOCH + Duration::from_secs(4_000_000_000);
+        let mut state = PersistedState::default();
+        state.bans.insert("de1.nordvpn.com".to_string(), expires);
+        state.usage.insert("de2.nordvpn.com".to_string(), 3);
+        state.sticky.insert(
+            "login".to_string(),
+            ("de2.nordvpn.com".to_string(), expires),
+        );
+        state.domain_bans.insert(
+            "example.com".to_string(),
+            [("de2.nordvpn.com".to_string(), expires)].into(),
+        );
+        state.stats.push(EndpointUsage {
+            hostname: "de2.nordvpn.com".to_string(),
+            requests: 3,
+            failures: 1,
+            bytes: 512,
+            last_used: Some(expires),
+        });
+        store.save(&state).unwrap();
+        store.save(&state).unwrap();
+        assert_eq!(store.load().unwrap(), state);
+    }
+}
@@ -0,0 +1,675 @@
+//! Raw TCP tunnels through an endpoint, for traffic that doesn't go through reqwest.
+use std::{
+    collections::HashMap,
+    fmt, io,
+    net::{IpAddr, SocketAddr},
+    pin::Pin,
+    sync::{Arc, OnceLock, RwLock},
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use base64::{Engine, prelude::BASE64_STANDARD};
+use rustls_platform_verifier::{ConfigVerifierExt, Verifier};
+use sha2::{Digest, Sha256};
+use socket2::{SockRef, TcpKeepalive};
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, ReadBuf},
+    net::TcpStream,
+};
+use tokio_rustls::{
+    TlsConnector,
+    client::TlsStream,
+    rustls::{
+        self, CertificateError, ClientConfig, DigitallySignedStruct, SignatureScheme,
+        client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier},
+        pki_types::{CertificateDer, ServerName, UnixTime},
+    },
+};
+
+use crate::{Credentials, Protocol, ProxyInfo, dial};
+
+/// Connection to the target, tunneled through the endpoint.
+pub enum ProxyStream {
+    Tcp(TcpStream),
+    Tls(Box<TlsStream<TcpStream>>),
+}
+
+/// TCP options for connections to endpoints. The defaults are the OS ones, without a timeout.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SocketOptions {
+    keepalive: Option<Duration>,
+    nodelay: bool,
+    connect_timeout: Option<Duration>,
+}
+
+impl SocketOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sends keepalive probes after the connection was idle for `idle`, so that endpoints or
+    /// middleboxes dropping it silently are noticed instead of stalling the tunnel.
+    pub fn keepalive(mut self, idle: Duration) -> Self {
+        self.keepalive = Some(idle);
+        self
+    }
+
+    /// Disables Nagle's algorithm.
+    pub fn nodelay(mut self, enabled: bool) -> Self {
+        self.nodelay = enabled;
+        self
+    }
+
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = Some(timeout);
+        self
+    }
+
+    /// Connects to `host:port` with these options applied.
+    pub async fn connect(&self, host: &str, port: u16) -> io::Result<TcpStream> {
+        let connect = dial::connect(host, port);
+        let stream = match self.connect_timeout {
+            Some(v) => tokio::time::timeout(v, connect)
+                .await
+                .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "connect timed out"))??,
+            None => connect.await?,
+        };
+        stream.set_nodelay(self.nodelay)?;
+        if let Some(v) = self.keepalive {
+            SockRef::from(&stream).set_tcp_keepalive(&TcpKeepalive::new().with_time(v))?;
+        }
+        Ok(stream)
+    }
+}
+
+type Fingerprints = RwLock<HashMap<String, Vec<[u8; 32]>>>;
+
+/// SHA-256 fingerprints of the certificates HTTPS endpoints are expected to present, checked
+/// during the handshake after the usual verification, so no credentials are sent otherwise.
+///
+/// Endpoints without pins are only verified against the platform's roots. Clones share the
+/// pins, so they can be refreshed while a `Gateway` or client uses them. Connections made
+/// with reqwest are only checked if their client uses [`CertificatePins::tls_config`].
+#[derive(Clone, Default)]
+pub struct CertificatePins {
+    inner: Arc<Fingerprints>,
+    config: Arc<OnceLock<Arc<ClientConfig>>>,
+}
+
+impl CertificatePins {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// SHA-256 of a DER-encoded certificate, as pinned.
+    pub fn fingerprint(certificate: &[u8]) -> [u8; 32] {
+        Sha256::digest(certificate).into()
+    }
+
+    /// Accepts a certificate with `fingerprint` from `hostname`, in addition to the ones
+    /// pinned already, e.g. the next one during a rotation.
+    pub fn pin(&self, hostname: impl Into<String>, fingerprint: [u8; 32]) {
+        let mut inner = self.inner.write().unwrap();
+        let pins = inner.entry(hostname.into()).or_default();
+        if !pins.contains(&fingerprint) {
+            pins.push(fingerprint);
+        }
+    }
+
+    /// Replaces all pins at once, e.g. with a freshly distributed set.
+    pub fn replace(&self, pins: impl IntoIterator<Item = (String, [u8; 32])>) {
+        let mut inner = HashMap::<_, Vec<_>>::new();
+        for (hostname, fingerprint) in pins {
+            inner.entry(hostname).or_default().push(fingerprint);
+        }
+        *self.inner.write().unwrap() = inner;
+    }
+
+    /// Pins the certificate `proxy` presents now in place of its current pins, trusting it
+    /// if it passes the platform's verification.
+    pub async fn refresh(&self, proxy: &ProxyInfo) -> io::Result<[u8; 32]> {
+        let stream = dial::connect(&proxy.hostname, proxy.port).await?;
+        let stream = tls_connect(proxy, stream, tls_config()?).await?;
+        let fingerprint = end_entity(&stream)
+            .map(Self::fingerprint)
+            .ok_or_else(|| io::Error::other("no certificate presented"))?;
+        debug!("pinned the certificate of {}", proxy.hostname);
+        self.inner
+            .write()
+            .unwrap()
+            .insert(proxy.hostname.clone(), vec![fingerprint]);
+        Ok(fingerprint)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.inner.read().unwrap().is_empty()
+    }
+
+    /// A TLS config verifying servers against the platform's roots and these pins, for
+    /// reqwest clients via `ClientBuilder::tls_backend_preconfigured` or `Pool::tls_config`.
+    ///
+    /// It negotiates no ALPN protocol, so targets are spoken to over HTTP/1.1.
+    pub fn tls_config(&self) -> io::Result<ClientConfig> {
+        Ok(self.shared_config()?.as_ref().clone())
+    }
+
+    fn shared_config(&self) -> io::Result<Arc<ClientConfig>> {
+        if let Some(v) = self.config.get() {
+            return Ok(v.clone());
+        }
+        let builder = ClientConfig::builder();
+        let platform =
+            Verifier::new(builder.crypto_provider().clone()).map_err(io::Error::other)?;
+        let verifier = PinnedVerifier {
+            platform,
+            pins: self.inner.clone(),
+        };
+        let config = builder
+            .dangerous()
+            .with_custom_certificate_verifier(Arc::new(verifier))
+            .with_no_client_auth();
+        Ok(self.config.get_or_init(|| Arc::new(config)).clone())
+    }
+}
+
+fn check(pins: &Fingerprints, hostname: &str, certificate: Option<&[u8]>) -> io::Result<()> {
+    let inner = pins.read().unwrap();
+    let Some(pins) = inner.get(hostname) else {
+        return Ok(());
+    };
+    match certificate.map(CertificatePins::fingerprint) {
+        Some(v) if pins.contains(&v) => Ok(()),
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("the certificate of {hostname} doesn't match its pins"),
+        )),
+    }
+}
+
+/// The platform's verification, followed by the pins of the server name.
+#[derive(Debug)]
+struct PinnedVerifier {
+    platform: Verifier,
+    pins: Arc<Fingerprints>,
+}
+
+impl ServerCertVerifier for PinnedVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+        server_name: &ServerName<'_>,
+        ocsp_response: &[u8],
+        now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        let verified = self.platform.verify_server_cert(
+            end_entity,
+            intermediates,
+            server_name,
+            ocsp_response,
+            now,
+        )?;
+        check(&self.pins, &server_name.to_str(), Some(end_entity)).map_err(|v| {
+            debug!("{v}");
+            rustls::Error::InvalidCertificate(CertificateError::ApplicationVerificationFailure)
+        })?;
+        Ok(verified)
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        certificate: &CertificateDer<'_>,
+        signature: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.platform
+            .verify_tls12_signature(message, certificate, signature)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        certificate: &CertificateDer<'_>,
+        signature: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.platform
+            .verify_tls13_signature(message, certificate, signature)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.platform.supported_verify_schemes()
+    }
+}
+
+impl fmt::Debug for CertificatePins {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let inner = self.inner.read().unwrap();
+        f.debug_map()
+            .entries(inner.iter().map(|(k, v)| (k, v.len())))
+            .finish()
+    }
+}
+
+/// Opens a tunnel to `host:port` through `proxy`.
+///
+/// `host` is resolved by the endpoint, so it may be a name the local resolver doesn't know.
+pub async fn connect(
+    proxy: &ProxyInfo,
+    credentials: &Credentials,
+    host: &str,
+    port: u16,
+) -> io::Result<ProxyStream> {
+    connect_with(proxy, credentials, host, port, &SocketOptions::default()).await
+}
+
+/// Like [`connect`], with `options` applied to the connection to the endpoint.
+pub async fn connect_with(
+    proxy: &ProxyInfo,
+    credentials: &Credentials,
+    host: &str,
+    port: u16,
+    options: &SocketOptions,
+) -> io::Result<ProxyStream> {
+    connect_pinned(
+        proxy,
+        credentials,
+        host,
+        port,
+        options,
+        &CertificatePins::new(),
+    )
+    .await
+}
+
+/// Like [`connect_with`], refusing HTTPS endpoints whose certificate doesn't match `pins`.
+pub async fn connect_pinned(
+    proxy: &ProxyInfo,
+    credentials: &Credentials,
+    host: &str,
+    port: u16,
+    options: &SocketOptions,
+    pins: &CertificatePins,
+) -> io::Result<ProxyStream> {
+    let stream = options.connect(&proxy.hostname, proxy.port).await?;
+    match proxy.protocol {
+        Protocol::Socks5 => {
+            let mut stream = stream;
+            socks5_connect(&mut stream, credentials, host, port).await?;
+            Ok(ProxyStream::Tcp(stream))
+        }
+        Protocol::Https => {
+            let mut stream = tls_connect(proxy, stream, pins.shared_config()?).await?;
+            http_connect(&mut stream, credentials, host, port).await?;
+            Ok(ProxyStream::Tls(Box::new(stream)))
+        }
+    }
+}
+
+async fn tls_connect(
+    proxy: &ProxyInfo,
+    stream: TcpStream,
+    config: Arc<ClientConfig>,
+) -> io::Result<TlsStream<TcpStream>> {
+    let name = ServerName::try_from(proxy.hostname.clone())
+        .map_err(|v| io::Error::new(io::ErrorKind::InvalidInput, v))?;
+    TlsConnector::from(config).connect(name, stream).await
+}
+
+fn end_entity(stream: &TlsStream<TcpStream>) -> Option<&[u8]> {
+    Some(stream.get_ref().1.peer_certificates()?.first()?.as_ref())
+}
+
+fn tls_config() -> io::Result<Arc<ClientConfig>> {
+    static CONFIG: OnceLock<Arc<ClientConfig>> = OnceLock::new();
+    if let Some(v) = CONFIG.get() {
+        return Ok(v.clone());
+    }
+    let config = Arc::new(ClientConfig::with_platform_verifier().map_err(io::Error::other)?);
+    Ok(CONFIG.get_or_init(|| config).clone())
+}
+
+pub(crate) fn authority(host: &str, port: u16) -> String {
+    match host.contains(':') {
+        true => format!("[{host}]:{port}"),
+        false => format!("{host}:{port}"),
+    }
+}
+
+async fn http_connect<S: AsyncRead + AsyncWrite + Unpin>(
+    stream: &mut S,
+    credentials: &Credentials,
+    host: &str,
+    port: u16,
+) -> io::Result<()> {
+    let authority = authority(host, port);
+    let auth = BASE64_STANDARD.encode(format!(
+        "{}:{}",
+        credentials.username(),
+        credentials.password()
+    ));
+    stream
+        .write_all(
+            format!(
+                "CONNECT {authority} HTTP/1.1\r\nHost: {authority}\r\nProxy-Authorization: Basic {auth}\r\n\r\n"
+            )
+            .as_bytes(),
+        )
+        .await?;
+    stream.flush().await?;
+    // Read byte by byte so nothing after the response head is consumed.
+    let mut head = Vec::new();
+    while !head.ends_with(b"\r\n\r\n") {
+        if head.len() > 8192 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "oversized CONNECT response",
+            ));
+        }
+        head.push(stream.read_u8().await?);
+    }
+    let status = String::from_utf8_lossy(&head);
+    match status.split(' ').nth(1) {
+        Some("200") => Ok(()),
+        _ => Err(io::Error::other(format!(
+            "CONNECT refused: {}",
+            status.lines().next().unwrap_or_default()
+        ))),
+    }
+}
+
+/// Listener opened on a SOCKS5 endpoint by [`ProxyInfo::bind`].
+pub struct Bind {
+    stream: TcpStream,
+    address: SocketAddr,
+}
+
+impl Bind {
+    /// Address on the endpoint the peer has to connect to.
+    pub fn address(&self) -> SocketAddr {
+        self.address
+    }
+
+    /// Waits for the peer to connect, returning the tunnel to it and its address.
+    pub async fn accept(mut self) -> io::Result<(ProxyStream, Option<SocketAddr>)> {
+        let peer = read_reply(&mut self.stream).await?;
+        Ok((ProxyStream::Tcp(self.stream), peer))
+    }
+}
+
+impl ProxyInfo {
+    /// Asks the endpoint to accept one inbound connection (SOCKS5 `BIND`), for protocols like
+    /// active-mode FTP where the other side connects back.
+    ///
+    /// Fails with [`io::ErrorKind::Unsupported`] for HTTPS endpoints.
+    pub async fn bind(&self, credentials: &Credentials) -> io::Result<Bind> {
+        if self.protocol != Protocol::Socks5 {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "BIND needs a SOCKS5 endpoint",
+            ));
+        }
+        let mut stream = dial::connect(&self.hostname, self.port).await?;
+        socks5_auth(&mut stream, credentials).await?;
+        // The peer isn't known up front, so let the endpoint accept anyone.
+        socks5_request(&mut stream, 2, "0.0.0.0", 0).await?;
+        let address = read_reply(&mut stream).await?.ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                "BIND reply without an IP address",
+            )
+        })?;
+        Ok(Bind { stream, address })
+    }
+}
+
+async fn socks5_connect<S: AsyncRead + AsyncWrite + Unpin>(
+    stream: &mut S,
+    credentials: &Credentials,
+    host: &str,
+    port: u16,
+) -> io::Result<()> {
+    socks5_auth(stream, credentials).await?;
+    socks5_request(stream, 1, host, port).await?;
+    read_reply(stream).await?;
+    Ok(())
+}
+
+async fn socks5_auth<S: AsyncRead + AsyncWrite + Unpin>(
+    stream: &mut S,
+    credentials: &Credentials,
+) -> io::Result<()> {
+    stream.write_all(&[5, 2, 0, 2]).await?;
+    let mut reply = [0; 2];
+    stream.read_exact(&mut reply).await?;
+    match reply {
+        [5, 0] => {}
+        [5, 2] => {
+            let (username, password) = (credentials.username(), credentials.password());
+            if username.len() > 255 || password.len() > 255 {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "credentials too long for SOCKS5",
+                ));
+            }
+            let mut auth = vec![1, username.len() as u8];
+            auth.extend_from_slice(username.as_bytes());
+            auth.push(password.len() as u8);
+            auth.extend_from_slice(password.as_bytes());
+            stream.write_all(&auth).await?;
+            stream.read_exact(&mut reply).await?;
+            if reply[1] != 0 {
+                return Err(io::Error::new(
+                    io::ErrorKind::PermissionDenied,
+                    "SOCKS5 authentication failed",
+                ));
+            }
+        }
+        _ => return Err(io::Error::other("no acceptable SOCKS5 auth method")),
+    }
+    Ok(())
+}
+
+async fn socks5_request<S: AsyncWrite + Unpin>(
+    stream: &mut S,
+    command: u8,
+    host: &str,
+    port: u16,
+) -> io::Result<()> {
+    let mut request = vec![5, command, 0];
+    match host.parse() {
+        Ok(IpAddr::V4(ip)) => {
+            request.push(1);
+            request.extend_from_slice(&ip.octets());
+        }
+        Ok(IpAddr::V6(ip)) => {
+            request.push(4);
+            request.extend_from_slice(&ip.octets());
+        }
+        Err(_) if host.len() > 255 => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "hostname too long for SOCKS5",
+            ));
+        }
+        Err(_) => {
+            request.extend_from_slice(&[3, host.len() as u8]);
+            request.extend_from_slice(host.as_bytes());
+        }
+    }
+    request.extend_from_slice(&port.to_be_bytes());
+    stream.write_all(&request).await
+}
+
+/// Reads a reply to a request, returning the address in it unless that is a domain name.
+async fn read_reply<S: AsyncRead + Unpin>(stream: &mut S) -> io::Result<Option<SocketAddr>> {
+    let mut reply = [0; 4];
+    stream.read_exact(&mut reply).await?;
+    if reply[1] != 0 {
+        return Err(io::Error::other(format!(
+            "SOCKS5 request failed with code {}",
+            reply[1]
+        )));
+    }
+    let len = match reply[3] {
+        1 => 4,
+        4 => 16,
+        _ => stream.read_u8().await? as usize,
+    };
+    let mut address = vec![0; len + 2];
+    stream.read_exact(&mut address).await?;
+    let port = u16::from_be_bytes([address[len], address[len + 1]]);
+    let ip = match reply[3] {
+        1 => IpAddr::from(<[u8; 4]>::try_from(&address[..4]).unwrap()),
+        4 => IpAddr::from(<[u8; 16]>::try_from(&address[..16]).unwrap()),
+        _ => return Ok(None),
+    };
+    Ok(Some(SocketAddr::new(ip, port)))
+}
+
+impl AsyncRead for ProxyStream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            ProxyStream::Tcp(v) => Pin::new(v).poll_read(cx, buf),
+            ProxyStream::Tls(v) => Pin::new(v).poll_read(cx, buf),
+        }
+    }
+}
+
+impl AsyncWrite for ProxyStream {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        match self.get_mut() {
+            ProxyStream::Tcp(v) => Pin::new(v).poll_write(cx, buf),
+            ProxyStream::Tls(v) => Pin::new(v).poll_write(cx, buf),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            ProxyStream::Tcp(v) => Pin::new(v).poll_flush(cx),
+            ProxyStream::Tls(v) => Pin::new(v).poll_flush(cx),
+        }
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            ProxyStream::Tcp(v) => Pin::new(v).poll_shutdown(cx),
+            ProxyStream::Tls(v) => Pin::new(v).poll_shutdown(cx),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use socket2::SockRef;
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
+
+    use super::{CertificatePins, SocketOptions};
+    use crate::{
+        Country, Credentials,
+        pool::{Pool, tests::proxy},
+    };
+
+    #[tokio::test]
+    async fn socket_options() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let stream = SocketOptions::new()
+            .nodelay(true)
+            .keepalive(Duration::from_secs(30))
+            .connect_timeout(Duration::from_secs(5))
+            .connect("127.0.0.1", port)
+            .await
+            .unwrap();
+        assert!(stream.nodelay().unwrap());
+        assert!(SockRef::from(&stream).keepalive().unwrap());
+    }
+
+    #[test]
+    fn certificate_pins() {
+        let pins = CertificatePins::new();
+        let check = |hostname: &str, certificate: Option<&[u8]>| {
+            super::check(&pins.inner, hostname, certificate)
+        };
+        assert!(check("de1.nordvpn.com", None).is_ok());
+        pins.pin("de1.nordvpn.com", CertificatePins::fingerprint(b"first"));
+        pins.pin("de1.nordvpn.com", CertificatePins::fingerprint(b"second"));
+        assert!(check("de1.nordvpn.com", Some(b"second")).is_ok());
+        let error = check("de1.nordvpn.com", Some(b"other")).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+        assert!(check("de1.nordvpn.com", None).is_err());
+        assert!(check("de2.nordvpn.com", Some(b"other")).is_ok());
+
+        pins.clone().replace([(
+            "de2.nordvpn.com".to_string(),
+            CertificatePins::fingerprint(b"third"),
+        )]);
+        assert!(check("de1.nordvpn.com", Some(b"other")).is_ok());
+        assert!(check("de2.nordvpn.com", Some(b"other")).is_err());
+        let de = proxy("de1.nordvpn.com", Country::DE, 10);
+        let pool = Pool::new(vec![de.clone()]).tls_config(pins.tls_config().unwrap());
+        assert!(pool.client_for(&de).is_ok());
+    }
+
+    #[tokio::test]
+    async fn long_credentials() {
+        let (mut client, mut server) = tokio::io::duplex(64);
+        tokio::spawn(async move {
+            let mut greeting = [0; 4];
+            server.read_exact(&mut greeting).await.unwrap();
+            server.write_all(&[5, 2]).await.unwrap();
+        });
+        let credentials = Credentials::new("user", "p".repeat(256));
+        let error = super::socks5_auth(&mut client, &credentials)
+            .await
+            .unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+    }
+
+    #[tokio::test]
+    async fn bind() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let mut endpoint = proxy("127.0.0.1", Country::DE, 0);
+        endpoint.port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut buf = [0; 4];
+            stream.read_exact(&mut buf).await.unwrap();
+            stream.write_all(&[5, 0]).await.unwrap();
+            let mut request = [0; 10];
+            stream.read_exact(&mut request).await.unwrap();
+            assert_eq!(request, [5, 2, 0, 1, 0, 0, 0, 0, 0, 0]);
+            stream
+                .write_all(&[5, 0, 0, 1, 127, 0, 0, 1, 0x1f, 0x90])
+                .await
+                .unwrap();
+            stream
+                .write_all(&[5, 0, 0, 1, 10, 0, 0, 2, 0x30, 0x39, b'h', b'i'])
+                .await
+                .unwrap();
+        });
+        let bind = endpoint
+            .bind(&Credentials::new("user", "pass"))
+            .await
+            .unwrap();
+        assert_eq!(bind.address(), "127.0.0.1:8080".parse().unwrap());
+        let (mut stream, peer) = bind.accept().await.unwrap();
+        assert_eq!(peer, Some("10.0.0.2:12345".parse().unwrap()));
+        let mut hi = [0; 2];
+        stream.read_exact(&mut hi).await.unwrap();
+        assert_eq!(&hi, b"hi");
+    }
+}
@@ -22,14 +22,19 @@ pub struct Services {
     pub identifier: String,
 }
 
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Groups {
+    pub identifier: String,
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Pivot {
-    pub status: String,
+    pub status: crate::Status,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Technologies {
-    pub identifier: String,
+    pub identifier: crate::Technology,
     pub pivot: Pivot,
     pub metadata: Vec<Metadata>,
 }
@@ -42,10 +47,18 @@ pub struct Metadata {
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Root {
-    pub status: String,
+    pub status: crate::Status,
     pub services: Vec<Services>,
+    #[serde(default)]
+    pub groups: Vec<Groups>,
     pub hostname: String,
     pub load: u32,
     pub locations: Vec<Locations>,
     pub technologies: Vec<Technologies>,
 }
+
+impl Root {
+    pub fn location(&self) -> Option<&Country> {
+        self.locations.first().map(|v| &v.country)
+    }
+}
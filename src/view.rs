@@ -0,0 +1,233 @@
+use std::{ops::Deref, slice};
+
+use crate::{City, Country, Credentials, Load, Obfuscated, ProxyInfo, determinism};
+
+/// Borrowed view over a list of proxies.
+///
+/// Filtering and sorting only shuffle references, so successive filters over thousands of
+/// entries don't clone any of them.
+#[derive(Debug, Clone, Default)]
+pub struct FilteredProxies<'a> {
+    items: Vec<&'a ProxyInfo>,
+}
+
+impl<'a> FilteredProxies<'a> {
+    pub fn new(proxies: &'a [ProxyInfo]) -> Self {
+        FilteredProxies {
+            items: proxies.iter().collect(),
+        }
+    }
+
+    pub fn filter(mut self, f: impl Fn(&ProxyInfo) -> bool) -> Self {
+        self.items.retain(|v| f(v));
+        self
+    }
+
+    pub fn country(self, country: &Country) -> Self {
+        self.filter(|v| &v.country == country)
+    }
+
+    pub fn countries(self, countries: &[Country]) -> Self {
+        self.filter(|v| countries.contains(&v.country))
+    }
+
+    pub fn city(self, city: &City) -> Self {
+        self.filter(|v| &v.city == city)
+    }
+
+    /// Endpoints whose hostname matches the glob `pattern`, e.g. `us*.nordvpn.com` or `de105?`
+    /// (matching the part before the first dot).
+    pub fn hostname(self, pattern: &str) -> Self {
+        self.filter(|v| hostname_matches(pattern, &v.hostname))
+    }
+
+    /// Endpoints on servers that allow peer-to-peer traffic, which the others disconnect.
+    pub fn allow_p2p_only(self) -> Self {
+        self.filter(|v| v.groups.p2p)
+    }
+
+    /// Keeps or drops the endpoints on obfuscated servers according to `mode`.
+    pub fn obfuscated(self, mode: Obfuscated) -> Self {
+        self.filter(|v| mode.allows(v.groups.obfuscated))
+    }
+
+    pub fn max_load(self, load: u32) -> Self {
+        self.filter(|v| v.load <= Load::new(load))
+    }
+
+    pub fn sort_by_key<K: Ord>(mut self, f: impl FnMut(&&'a ProxyInfo) -> K) -> Self {
+        self.items.sort_by_key(f);
+        self
+    }
+
+    /// Least loaded first.
+    pub fn sort_by_load(mut self) -> Self {
+        self.items.sort_by(|a, b| determinism::by_load(a, b));
+        self
+    }
+
+    pub fn take(mut self, n: usize) -> Self {
+        self.items.truncate(n);
+        self
+    }
+
+    pub fn into_vec(self) -> Vec<&'a ProxyInfo> {
+        self.items
+    }
+
+    /// One client per endpoint for fanning out, with the settings they share (timeouts,
+    /// headers, ...) applied by `customize`.
+    pub fn clients(
+        self,
+        credentials: &Credentials,
+        customize: impl Fn(reqwest::ClientBuilder) -> reqwest::ClientBuilder,
+    ) -> reqwest::Result<Vec<(ProxyInfo, reqwest::Client)>> {
+        self.items
+            .into_iter()
+            .map(|v| {
+                let builder = reqwest::Client::builder().proxy(v.proxy_with(credentials));
+                Ok((v.clone(), customize(builder).build()?))
+            })
+            .collect()
+    }
+}
+
+impl<'a> Deref for FilteredProxies<'a> {
+    type Target = [&'a ProxyInfo];
+
+    fn deref(&self) -> &Self::Target {
+        &self.items
+    }
+}
+
+impl<'a> IntoIterator for FilteredProxies<'a> {
+    type Item = &'a ProxyInfo;
+    type IntoIter = std::vec::IntoIter<&'a ProxyInfo>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.items.into_iter()
+    }
+}
+
+impl<'a, 'b> IntoIterator for &'b FilteredProxies<'a> {
+    type Item = &'b &'a ProxyInfo;
+    type IntoIter = slice::Iter<'b, &'a ProxyInfo>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.items.iter()
+    }
+}
+
+impl<'a> From<&'a [ProxyInfo]> for FilteredProxies<'a> {
+    fn from(proxies: &'a [ProxyInfo]) -> Self {
+        Self::new(proxies)
+    }
+}
+
+/// Whether `hostname` matches the glob `pattern`, where `*` stands for any run of characters and
+/// `?` for one. Patterns without a dot match the server name, so `de105?` matches
+/// `de1051.nordvpn.com`.
+pub(crate) fn hostname_matches(pattern: &str, hostname: &str) -> bool {
+    let name = match pattern.contains('.') {
+        true => hostname,
+        false => hostname.split('.').next().unwrap_or_default(),
+    };
+    glob(
+        pattern.to_ascii_lowercase().as_bytes(),
+        name.to_ascii_lowercase().as_bytes(),
+    )
+}
+
+fn glob(pattern: &[u8], text: &[u8]) -> bool {
+    let (mut p, mut t) = (0, 0);
+    // Where the last `*` is in the pattern and the text it started matching at, to backtrack to.
+    let mut star = None;
+    while t < text.len() {
+        match pattern.get(p) {
+            Some(b'*') => {
+                star = Some((p, t));
+                p += 1;
+            }
+            Some(&c) if c == b'?' || c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match star {
+                Some((sp, st)) => {
+                    star = Some((sp, st + 1));
+                    p = sp + 1;
+                    t = st + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == b'*')
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{FilteredProxies, hostname_matches};
+    use crate::{Country, Credentials, Obfuscated, pool::tests::proxy};
+
+    #[test]
+    fn chain() {
+        let proxies = vec![
+            proxy("de1.nordvpn.com", Country::DE, 50),
+            proxy("de2.nordvpn.com", Country::DE, 10),
+            proxy("de3.nordvpn.com", Country::DE, 90),
+            proxy("nl1.nordvpn.com", Country::NL, 5),
+        ];
+        let view = FilteredProxies::new(&proxies)
+            .country(&Country::DE)
+            .max_load(60)
+            .sort_by_load();
+        assert_eq!(view.len(), 2);
+        assert!(std::ptr::eq(view[0], &proxies[1]));
+        let clients = view
+            .clone()
+            .clients(&Credentials::new("user", "pass"), |v| {
+                v.timeout(Duration::from_secs(5))
+            })
+            .unwrap();
+        assert_eq!(clients[1].0.hostname, "de1.nordvpn.com");
+        assert_eq!(view.take(1).into_vec()[0].hostname, "de2.nordvpn.com");
+        let view = FilteredProxies::new(&proxies).hostname("de*");
+        assert_eq!(view.len(), 3);
+    }
+
+    #[cfg(feature = "socks5")]
+    #[test]
+    fn p2p() {
+        let socks = crate::Socks5::from_json_str(include_str!("../fixtures/servers.json")).unwrap();
+        let proxies = crate::ProxyTrait::proxies(&socks, "user", "pass");
+        let view = FilteredProxies::new(&proxies).allow_p2p_only();
+        assert_eq!(view.len(), 1);
+        assert_eq!(view[0].hostname, "de1057.nordvpn.com");
+    }
+
+    #[test]
+    fn obfuscated() {
+        let mut proxies = vec![
+            proxy("de1.nordvpn.com", Country::DE, 10),
+            proxy("de2.nordvpn.com", Country::DE, 10),
+        ];
+        proxies[1].groups.obfuscated = true;
+        let view = |mode| FilteredProxies::new(&proxies).obfuscated(mode).len();
+        assert_eq!(view(Obfuscated::Only), 1);
+        assert_eq!(view(Obfuscated::Exclude), 1);
+        assert_eq!(view(Obfuscated::Any), 2);
+    }
+
+    #[test]
+    fn hostname() {
+        assert!(hostname_matches("us*.nordvpn.com", "us9591.nordvpn.com"));
+        assert!(!hostname_matches("us*.nordvpn.com", "de1.nordvpn.com"));
+        assert!(hostname_matches("de105?", "DE1051.nordvpn.com"));
+        assert!(!hostname_matches("de105?", "de10512.nordvpn.com"));
+        assert!(hostname_matches("*1*", "nl912.nordvpn.com"));
+        assert!(!hostname_matches("de1.nordvpn.com", "de1.nordvpn.com.evil"));
+    }
+}
@@ -0,0 +1,167 @@
+//! Alerts POSTed as JSON when the monitored pool degrades.
+use std::collections::HashMap;
+
+use serde::Serialize;
+
+use crate::Country;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Alert {
+    /// The number of healthy endpoints dropped below the threshold.
+    LowCapacity { healthy: usize, threshold: usize },
+    /// Every endpoint of a country that had healthy endpoints before is down.
+    CountryLost { country: Country },
+    /// The number of healthy endpoints in a country dropped below its threshold.
+    LowCountryCapacity {
+        country: Country,
+        healthy: usize,
+        threshold: usize,
+    },
+}
+
+pub struct Webhook {
+    url: String,
+    min_healthy: usize,
+    min_healthy_in: Vec<(Country, usize)>,
+    client: reqwest::Client,
+}
+
+impl Webhook {
+    pub fn new(url: impl Into<String>) -> Self {
+        Webhook {
+            url: url.into(),
+            min_healthy: 1,
+            min_healthy_in: Vec::new(),
+            client: reqwest::Client::new(),
+        }
+    }
+
+    /// Sends [`Alert::LowCapacity`] once the healthy count drops below `min_healthy`.
+    pub fn min_healthy(mut self, min_healthy: usize) -> Self {
+        self.min_healthy = min_healthy;
+        self
+    }
+
+    /// Sends [`Alert::LowCountryCapacity`] once `country` has fewer than `min_healthy` healthy
+    /// endpoints, e.g. for regional jobs to pause before they fail.
+    pub fn min_healthy_in(mut self, country: Country, min_healthy: usize) -> Self {
+        self.min_healthy_in.retain(|(v, _)| v != &country);
+        self.min_healthy_in.push((country, min_healthy));
+        self
+    }
+
+    pub async fn send(&self, alert: &Alert) -> reqwest::Result<()> {
+        self.client
+            .post(&self.url)
+            .json(alert)
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+
+    /// Alerts for the transition from `previous` to `current`; each condition fires once when
+    /// it starts, not on every round it persists.
+    pub(crate) fn alerts(&self, previous: Option<&Round>, current: &Round) -> Vec<Alert> {
+        let mut alerts = Vec::new();
+        if current.healthy < self.min_healthy
+            && previous.is_none_or(|v| v.healthy >= self.min_healthy)
+        {
+            alerts.push(Alert::LowCapacity {
+                healthy: current.healthy,
+                threshold: self.min_healthy,
+            });
+        }
+        if let Some(previous) = previous {
+            alerts.extend(
+                previous
+                    .countries
+                    .keys()
+                    .filter(|v| !current.countries.contains_key(v))
+                    .map(|v| Alert::CountryLost { country: v.clone() }),
+            );
+        }
+        for (country, threshold) in &self.min_healthy_in {
+            let threshold = *threshold;
+            let healthy = |round: &Round| round.countries.get(country).copied().unwrap_or(0);
+            if healthy(current) < threshold && previous.is_none_or(|v| healthy(v) >= threshold) {
+                alerts.push(Alert::LowCountryCapacity {
+                    country: country.clone(),
+                    healthy: healthy(current),
+                    threshold,
+                });
+            }
+        }
+        alerts
+    }
+}
+
+/// Healthy capacity observed in one probe round.
+#[derive(Debug, Default)]
+pub(crate) struct Round {
+    pub healthy: usize,
+    /// Healthy endpoints by country, leaving out countries without any.
+    pub countries: HashMap<Country, usize>,
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::{Alert, Round, Webhook};
+    use crate::Country;
+
+    fn round(countries: &[Country]) -> Round {
+        Round {
+            healthy: countries.len(),
+            countries: countries.iter().fold(HashMap::new(), |mut v, country| {
+                *v.entry(country.clone()).or_default() += 1;
+                v
+            }),
+        }
+    }
+
+    #[test]
+    fn transitions() {
+        let webhook = Webhook::new("http://localhost/hook").min_healthy(2);
+        let full = round(&[Country::DE, Country::JP]);
+        assert!(webhook.alerts(None, &full).is_empty());
+
+        let degraded = round(&[Country::DE]);
+        assert_eq!(
+            webhook.alerts(Some(&full), &degraded),
+            [
+                Alert::LowCapacity {
+                    healthy: 1,
+                    threshold: 2
+                },
+                Alert::CountryLost {
+                    country: Country::JP
+                }
+            ]
+        );
+        assert!(webhook.alerts(Some(&degraded), &degraded).is_empty());
+    }
+
+    #[test]
+    fn country_capacity() {
+        let webhook = Webhook::new("http://localhost/hook").min_healthy_in(Country::JP, 3);
+        let full = round(&[Country::JP, Country::JP, Country::JP]);
+        assert!(webhook.alerts(None, &full).is_empty());
+        let degraded = round(&[Country::JP, Country::JP]);
+        assert_eq!(
+            webhook.alerts(Some(&full), &degraded),
+            [Alert::LowCountryCapacity {
+                country: Country::JP,
+                healthy: 2,
+                threshold: 3
+            }]
+        );
+        assert!(
+            webhook
+                .alerts(Some(&degraded), &round(&[Country::JP]))
+                .is_empty()
+        );
+    }
+}